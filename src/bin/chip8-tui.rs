@@ -0,0 +1,411 @@
+use std::io::{self, Stdout, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use afoley_chip8::browser::RomBrowser;
+use afoley_chip8::chip8::{Chip8, KEYBOARD_SIZE, MEMORY_SIZE, VIDEO_HEIGHT, VIDEO_WIDTH};
+use afoley_chip8::cli::{self, RunArgs};
+use afoley_chip8::coverage::Coverage;
+use afoley_chip8::debugger::Watch;
+use afoley_chip8::frontend::{Command, DisplaySink, InputSource, Keymap, Palette, Status, SystemClock};
+use afoley_chip8::runner::Runner;
+use afoley_chip8::scheduler::Speed;
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Stylize;
+use crossterm::{cursor, queue, style, terminal};
+
+// Without key release events, a key counts as held for this long after the
+// last press (or auto-repeat) the terminal sent.
+const KEY_HOLD: Duration = Duration::from_millis(150);
+
+// The memory heatmap is a square of one cell per address.
+const HEATMAP_SIZE: usize = 64;
+
+const PANEL_COLUMN: u16 = VIDEO_WIDTH as u16 + 2;
+const STATUS_ROW: u16 = (VIDEO_HEIGHT / 2) as u16 + 3;
+
+// The terminal has no options of its own.
+#[derive(Debug, clap::Args)]
+struct TuiArgs {}
+
+fn map_char_to_chip8_key(c: char) -> Option<usize> {
+    match c.to_ascii_lowercase() {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
+
+// The ROM's direction pad and buttons, from its database entry. Terminals
+// can't report Shift on its own, so only the A button has a key.
+fn map_key_to_pad(code: KeyCode, keymap: &Keymap) -> Option<usize> {
+    match code {
+        KeyCode::Up => keymap.up,
+        KeyCode::Down => keymap.down,
+        KeyCode::Left => keymap.left,
+        KeyCode::Right => keymap.right,
+        KeyCode::Char(' ') => keymap.a,
+        _ => None,
+    }
+}
+
+fn map_key_to_command(key: &KeyEvent) -> Option<Command> {
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        return match key.code {
+            KeyCode::Char('c') | KeyCode::Char('q') => Some(Command::Quit),
+            _ => None,
+        };
+    }
+
+    match key.code {
+        KeyCode::Tab => Some(Command::ToggleTurbo),
+        KeyCode::Char('`') => Some(Command::ToggleSlowMotion),
+        KeyCode::Char('p') => Some(Command::TogglePause),
+        KeyCode::Char('n') => Some(Command::FrameAdvance),
+        KeyCode::F(5) => Some(Command::Reset),
+        KeyCode::F(6) => Some(Command::Reload),
+        KeyCode::F(2) => Some(Command::SaveState),
+        KeyCode::F(3) => Some(Command::LoadState),
+        KeyCode::Char('o') => Some(Command::OpenBrowser),
+        KeyCode::F(12) => Some(Command::Screenshot),
+        KeyCode::F(9) => Some(Command::ToggleRecording),
+        KeyCode::Char('m') => Some(Command::ToggleHeatmap),
+        KeyCode::Esc => Some(Command::CloseBrowser),
+        KeyCode::Enter => Some(Command::BrowserSelect),
+        KeyCode::Up => Some(Command::BrowserPrevious),
+        KeyCode::Down => Some(Command::BrowserNext),
+        _ => None,
+    }
+}
+
+// Two display rows per terminal row, using the upper and lower half blocks.
+fn render_display(video: &[u32]) -> Vec<String> {
+    video
+        .chunks(VIDEO_WIDTH * 2)
+        .map(|rows| {
+            let (top, bottom) = rows.split_at(VIDEO_WIDTH);
+            top.iter()
+                .zip(bottom)
+                .map(|(&t, &b)| match (t != 0, b != 0) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// One cell per memory address, 64 to a row, two rows per line: the upper
+// cell in the foreground colour of a half block and the lower one behind it.
+fn render_heatmap(coverage: &Coverage) -> Vec<String> {
+    (0..MEMORY_SIZE / HEATMAP_SIZE / 2)
+        .map(|line| {
+            (0..HEATMAP_SIZE)
+                .map(|x| {
+                    let upper = coverage.color(line * 2 * HEATMAP_SIZE + x);
+                    let lower = coverage.color((line * 2 + 1) * HEATMAP_SIZE + x);
+                    style::style('▀').with(rgb(upper)).on(rgb(lower)).to_string()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn render_panel(chip8: &Chip8) -> Vec<String> {
+    let v = chip8.registers();
+    let mut lines = vec![
+        format!("PC {:04X}  I {:04X}", chip8.pc(), chip8.index()),
+        String::new(),
+    ];
+    for i in 0..8 {
+        lines.push(format!("V{:X} {:02X}    V{:X} {:02X}", i, v[i], i + 8, v[i + 8]));
+    }
+    lines.push(String::new());
+    lines.push(format!("DT {:02X}    ST {:02X}", chip8.delay_timer(), chip8.sound_timer()));
+    lines.push(format!("SP {:X}", chip8.sp()));
+    for (i, address) in chip8.stack().iter().enumerate().rev().take(3) {
+        lines.push(format!(" {:X}: {:04X}", i, address));
+    }
+    lines
+}
+
+fn status_line(status: Status<'_>) -> String {
+    let mut line = match status.rom.and_then(Path::file_name) {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => String::from("no ROM"),
+    };
+    match status.speed {
+        Speed::Normal => {}
+        Speed::Turbo => line.push_str(" [turbo]"),
+        Speed::SlowMotion => line.push_str(" [slow]"),
+    }
+    if status.paused {
+        line.push_str(" [paused]");
+    }
+    if status.recording {
+        line.push_str(" [rec]");
+    }
+    line.push_str("  ^C quit");
+    line
+}
+
+fn rgb(color: u32) -> style::Color {
+    style::Color::Rgb {
+        r: (color >> 16) as u8,
+        g: (color >> 8) as u8,
+        b: color as u8,
+    }
+}
+
+struct TuiDisplay {
+    out: Stdout,
+    // `None` keeps the terminal's own colours.
+    palette: Option<Palette>,
+    // Rows as last drawn, so that only changed rows go over the wire.
+    drawn: Vec<String>,
+    status: String,
+    message: String,
+    watches: Vec<String>,
+}
+
+impl TuiDisplay {
+    fn draw(&mut self, rows: Vec<String>) -> io::Result<()> {
+        if self.drawn.len() != rows.len() {
+            queue!(self.out, terminal::Clear(terminal::ClearType::All))?;
+            self.drawn.clear();
+        }
+
+        for (y, row) in rows.iter().enumerate() {
+            if self.drawn.get(y) != Some(row) {
+                queue!(
+                    self.out,
+                    cursor::MoveTo(0, y as u16),
+                    terminal::Clear(terminal::ClearType::CurrentLine),
+                    style::Print(row)
+                )?;
+            }
+        }
+        self.drawn = rows;
+        self.out.flush()
+    }
+
+    fn with_status(&self, mut rows: Vec<String>) -> Vec<String> {
+        // A long watch list pushes the status line down.
+        if rows.len() < STATUS_ROW as usize {
+            rows.resize(STATUS_ROW as usize, String::new());
+        }
+        rows.push(format!("{}  {}", self.status, self.message));
+        rows
+    }
+}
+
+impl DisplaySink for TuiDisplay {
+    fn present(&mut self, chip8: &Chip8) -> Result<(), String> {
+        let mut panel = render_panel(chip8);
+        if !self.watches.is_empty() {
+            panel.push(String::new());
+            panel.extend(self.watches.iter().cloned());
+        }
+        let mut rows = vec![format!("┌{}┐", "─".repeat(VIDEO_WIDTH))];
+        let display = render_display(&chip8.video).into_iter().map(|row| match self.palette {
+            Some(palette) => style::style(row)
+                .with(rgb(palette.foreground))
+                .on(rgb(palette.background))
+                .to_string(),
+            None => row,
+        });
+        rows.extend(display.map(|row| format!("│{}│", row)));
+        rows.push(format!("└{}┘", "─".repeat(VIDEO_WIDTH)));
+        for (y, line) in panel.iter().enumerate() {
+            if let Some(row) = rows.get_mut(y) {
+                row.push(' ');
+                row.push_str(line);
+            } else {
+                rows.push(format!("{:width$}{}", "", line, width = PANEL_COLUMN as usize + 1));
+            }
+        }
+        let rows = self.with_status(rows);
+        self.draw(rows).map_err(|e| e.to_string())
+    }
+
+    fn present_heatmap(&mut self, coverage: &Coverage) -> Result<(), String> {
+        let mut rows = vec![format!("┌{}┐", "─".repeat(HEATMAP_SIZE))];
+        rows.extend(render_heatmap(coverage).into_iter().map(|row| format!("│{}│", row)));
+        rows.push(format!("└{}┘", "─".repeat(HEATMAP_SIZE)));
+        rows.push(String::from("executed: green  read: blue  written: red"));
+        let rows = self.with_status(rows);
+        self.draw(rows).map_err(|e| e.to_string())
+    }
+
+    fn present_browser(&mut self, browser: &RomBrowser) -> Result<(), String> {
+        let mut rows = vec![format!("ROMs in {}", browser.dir().display()), String::new()];
+        let visible = STATUS_ROW as usize - rows.len();
+        let offset = browser.scroll_offset(visible);
+
+        if browser.entries().is_empty() {
+            rows.push(String::from("  no ROMs found"));
+        }
+        for (i, path) in browser.entries().iter().enumerate().skip(offset).take(visible) {
+            let marker = if i == browser.selected_index() { '>' } else { ' ' };
+            let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            rows.push(format!("{} {}", marker, name));
+        }
+        let rows = self.with_status(rows);
+        self.draw(rows).map_err(|e| e.to_string())
+    }
+
+    fn set_status(&mut self, status: Status<'_>) -> Result<(), String> {
+        self.status = status_line(status);
+        Ok(())
+    }
+
+    fn notify(&mut self, message: &str) {
+        self.message = message.to_string();
+    }
+
+    fn set_watches(&mut self, watches: &[Watch]) {
+        self.watches = watches.iter().map(Watch::to_string).collect();
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = (palette != Palette::default()).then_some(palette);
+    }
+}
+
+struct TuiInput {
+    // Whether the terminal reports key releases (kitty keyboard protocol).
+    releases: bool,
+    held_until: [Option<Instant>; KEYBOARD_SIZE],
+    keymap: Keymap,
+}
+
+impl InputSource for TuiInput {
+    fn poll(&mut self, keyboard: &mut [bool; KEYBOARD_SIZE], commands: &mut Vec<Command>) {
+        let now = Instant::now();
+
+        while let Ok(true) = event::poll(Duration::ZERO) {
+            let Ok(Event::Key(key)) = event::read() else { continue };
+
+            let chip8_key = match key.code {
+                KeyCode::Char(_) if key.modifiers.contains(KeyModifiers::CONTROL) => None,
+                KeyCode::Char(c) => map_char_to_chip8_key(c).or(map_key_to_pad(key.code, &self.keymap)),
+                code => map_key_to_pad(code, &self.keymap),
+            };
+
+            // The arrow keys navigate the ROM browser even when they are
+            // bound to the pad.
+            if chip8_key.is_some() && key.kind != KeyEventKind::Release {
+                if let KeyCode::Up | KeyCode::Down = key.code {
+                    commands.extend(map_key_to_command(&key));
+                }
+            }
+
+            match (chip8_key, key.kind) {
+                (Some(k), KeyEventKind::Release) => {
+                    keyboard[k] = false;
+                    self.held_until[k] = None;
+                },
+                (Some(k), _) => {
+                    keyboard[k] = true;
+                    if !self.releases {
+                        self.held_until[k] = Some(now + KEY_HOLD);
+                    }
+                },
+                (None, KeyEventKind::Release) => {},
+                (None, _) => commands.extend(map_key_to_command(&key)),
+            }
+        }
+
+        for (k, held_until) in self.held_until.iter_mut().enumerate() {
+            if held_until.is_some_and(|t| t <= now) {
+                keyboard[k] = false;
+                *held_until = None;
+            }
+        }
+    }
+
+    fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+}
+
+// Puts the terminal back the way we found it, including after a panic.
+struct TerminalGuard {
+    releases: bool,
+}
+
+impl TerminalGuard {
+    fn enter(out: &mut Stdout) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        queue!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if releases {
+            queue!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        out.flush()?;
+        Ok(Self { releases })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        if self.releases {
+            let _ = queue!(out, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn main() -> Result<(), String> {
+    cli::main(run)
+}
+
+fn run(args: RunArgs<TuiArgs>) -> Result<(), String> {
+    let mut out = io::stdout();
+    let guard = TerminalGuard::enter(&mut out).map_err(|e| e.to_string())?;
+
+    let display = TuiDisplay {
+        out,
+        palette: None,
+        drawn: Vec::new(),
+        status: String::new(),
+        message: String::new(),
+        watches: Vec::new(),
+    };
+    let input = TuiInput {
+        releases: guard.releases,
+        held_until: [None; KEYBOARD_SIZE],
+        keymap: Keymap::default(),
+    };
+
+    let mut runner = Runner::new(args.options()?, display, input, (), SystemClock);
+    args.start(&mut runner, true)?;
+
+    runner.run()
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const ROM_EXTENSIONS: [&str; 3] = ["ch8", "sc8", "xo8"];
+
+pub fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ROM_EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r)))
+}
+
+/// Lists the ROM files directly inside `dir`, sorted by file name.
+pub fn list_roms(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut roms = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_rom(&path) {
+            roms.push(path);
+        }
+    }
+    roms.sort_by_key(|p| p.file_name().map(|n| n.to_ascii_lowercase()));
+    Ok(roms)
+}
+
+/// Keyboard-driven selection state for an in-app ROM picker.
+#[derive(Debug)]
+pub struct RomBrowser {
+    dir: PathBuf,
+    entries: Vec<PathBuf>,
+    selected: usize,
+}
+
+impl RomBrowser {
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        let entries = list_roms(&dir)?;
+        Ok(Self {
+            dir,
+            entries,
+            selected: 0,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected(&self) -> Option<&Path> {
+        self.entries.get(self.selected).map(PathBuf::as_path)
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = (self.selected + 1) % self.entries.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = (self.selected + self.entries.len() - 1) % self.entries.len();
+        }
+    }
+
+    /// Index of the first entry to show so that the selection stays inside a
+    /// window of `rows` visible entries.
+    pub fn scroll_offset(&self, rows: usize) -> usize {
+        if rows == 0 || self.selected < rows {
+            0
+        } else {
+            self.selected + 1 - rows
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chip8::{VIDEO_HEIGHT, VIDEO_WIDTH};
+use crate::scheduler::FRAME_RATE;
+
+pub const DEFAULT_SCALE: u32 = 10;
+/// Larger scales would overflow a GIF's 16-bit width.
+pub const MAX_SCALE: u32 = 64;
+
+// Browsers stretch GIF delays below 2 centiseconds to 10, so faster changes
+// are merged into the next frame.
+const MIN_GIF_DELAY: u64 = 2;
+
+fn rgb(pixel: u32) -> [u8; 3] {
+    [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]
+}
+
+// Nearest-neighbour upscale of the display, one item per output pixel.
+fn scaled<T: Copy>(video: &[u32], scale: u32, map: impl Fn(u32) -> T) -> Vec<T> {
+    let scale = scale as usize;
+    let mut out = Vec::with_capacity(VIDEO_WIDTH * VIDEO_HEIGHT * scale * scale);
+    for row in video.chunks(VIDEO_WIDTH) {
+        let line: Vec<T> = row
+            .iter()
+            .flat_map(|&p| std::iter::repeat_n(map(p), scale))
+            .collect();
+        for _ in 0..scale {
+            out.extend_from_slice(&line);
+        }
+    }
+    out
+}
+
+/// Encodes the display as an RGB PNG, `scale` output pixels per CHIP-8 pixel.
+pub fn write_png<W: Write>(w: W, video: &[u32], scale: u32) -> io::Result<()> {
+    let width = VIDEO_WIDTH as u32 * scale;
+    let height = VIDEO_HEIGHT as u32 * scale;
+    let data: Vec<u8> = scaled(video, scale, rgb).concat();
+
+    let mut encoder = png::Encoder::new(w, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn save_png(path: &Path, video: &[u32], scale: u32) -> io::Result<()> {
+    write_png(BufWriter::new(File::create(path)?), video, scale)
+}
+
+/// Picks a file name that doesn't exist yet, e.g. `tetris-1718000000.png`.
+pub fn capture_path(dir: &Path, stem: &str, extension: &str) -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let mut path = dir.join(format!("{}-{}.{}", stem, secs, extension));
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{}-{}.{}", stem, secs, n, extension));
+        n += 1;
+    }
+    path
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// Animated GIF
+    Gif,
+    /// Uncompressed YUV4MPEG2 video, e.g. for `ffmpeg -i capture.y4m`
+    Y4m,
+    /// A directory of numbered PNG files
+    Frames,
+}
+
+impl RecordFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("gif") => RecordFormat::Gif,
+            Some(e) if e.eq_ignore_ascii_case("y4m") => RecordFormat::Y4m,
+            _ => RecordFormat::Frames,
+        }
+    }
+}
+
+enum Sink<W: Write> {
+    Gif {
+        encoder: gif::Encoder<W>,
+        pending: Option<(Vec<u8>, Vec<u8>, u64)>,
+    },
+    Y4m(W),
+}
+
+/// Encodes one image per emulated 60 Hz frame into a GIF or Y4M stream.
+pub struct VideoWriter<W: Write> {
+    sink: Sink<W>,
+    scale: u32,
+    frames: u64,
+}
+
+/// Records one image per emulated 60 Hz frame.
+pub struct Recorder {
+    video: Option<VideoWriter<BufWriter<File>>>,
+    path: PathBuf,
+    scale: u32,
+    frames: u64,
+}
+
+fn centiseconds(frame: u64) -> u64 {
+    frame * 100 / FRAME_RATE as u64
+}
+
+fn gif_error(e: gif::EncodingError) -> io::Error {
+    io::Error::other(e)
+}
+
+fn check_scale(scale: u32) -> io::Result<()> {
+    if !(1..=MAX_SCALE).contains(&scale) {
+        let message = format!("capture scale {} isn't between 1 and {}", scale, MAX_SCALE);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+    Ok(())
+}
+
+// Turns the display into palette indices plus a packed RGB palette.
+fn indexed(video: &[u32], scale: u32) -> (Vec<u8>, Vec<u8>) {
+    let mut colours: Vec<u32> = Vec::new();
+    for &p in video {
+        let p = p & 0x00FF_FFFF;
+        if !colours.contains(&p) && colours.len() < 256 {
+            colours.push(p);
+        }
+    }
+    let pixels = scaled(video, scale, |p| {
+        colours.iter().position(|&c| c == p & 0x00FF_FFFF).unwrap_or(0) as u8
+    });
+    let palette = colours.iter().flat_map(|&c| rgb(c)).collect();
+    (pixels, palette)
+}
+
+// Y'CbCr (BT.601, studio range) for one RGB pixel.
+fn yuv(pixel: u32) -> [u8; 3] {
+    let [r, g, b] = rgb(pixel).map(i32::from);
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    [y as u8, u as u8, v as u8]
+}
+
+impl<W: Write> VideoWriter<W> {
+    /// Starts a stream of `format`, which can't be `Frames`.
+    pub fn new(mut w: W, format: RecordFormat, scale: u32) -> io::Result<Self> {
+        check_scale(scale)?;
+        let width = VIDEO_WIDTH as u32 * scale;
+        let height = VIDEO_HEIGHT as u32 * scale;
+
+        let sink = match format {
+            RecordFormat::Gif => {
+                let mut encoder = gif::Encoder::new(w, width as u16, height as u16, &[]).map_err(gif_error)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+                Sink::Gif { encoder, pending: None }
+            },
+            RecordFormat::Y4m => {
+                writeln!(w, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", width, height, FRAME_RATE)?;
+                Sink::Y4m(w)
+            },
+            RecordFormat::Frames => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "frames are written to a directory"));
+            },
+        };
+
+        Ok(Self { sink, scale, frames: 0 })
+    }
+
+    pub fn write_frame(&mut self, video: &[u32]) -> io::Result<()> {
+        let frame = self.frames;
+        self.frames += 1;
+
+        match &mut self.sink {
+            Sink::Gif { encoder, pending } => {
+                let (pixels, palette) = indexed(video, self.scale);
+                match pending {
+                    Some((last, last_palette, _)) if *last == pixels && *last_palette == palette => {},
+                    Some((last, last_palette, start)) if centiseconds(frame) - centiseconds(*start) < MIN_GIF_DELAY => {
+                        *last = pixels;
+                        *last_palette = palette;
+                    },
+                    _ => {
+                        if let Some((last, last_palette, start)) = pending.take() {
+                            let delay = centiseconds(frame) - centiseconds(start);
+                            write_gif_frame(encoder, self.scale, last, last_palette, delay)?;
+                        }
+                        *pending = Some((pixels, palette, frame));
+                    },
+                }
+            },
+            Sink::Y4m(w) => {
+                let planes = scaled(video, self.scale, yuv);
+                w.write_all(b"FRAME\n")?;
+                for plane in 0..3 {
+                    let bytes: Vec<u8> = planes.iter().map(|p| p[plane]).collect();
+                    w.write_all(&bytes)?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Writes out the last GIF frame and flushes, handing back the writer.
+    pub fn finish(self) -> io::Result<W> {
+        let mut w = match self.sink {
+            Sink::Gif { mut encoder, pending } => {
+                if let Some((last, palette, start)) = pending {
+                    let delay = (centiseconds(self.frames) - centiseconds(start)).max(MIN_GIF_DELAY);
+                    write_gif_frame(&mut encoder, self.scale, last, palette, delay)?;
+                }
+                encoder.into_inner().map_err(gif_error)?
+            },
+            Sink::Y4m(w) => w,
+        };
+        w.flush()?;
+        Ok(w)
+    }
+}
+
+impl Recorder {
+    /// Picks the format from `path`'s extension, see `RecordFormat`.
+    pub fn create(path: &Path, scale: u32) -> io::Result<Self> {
+        check_scale(scale)?;
+        let video = match RecordFormat::from_path(path) {
+            RecordFormat::Frames => {
+                fs::create_dir_all(path)?;
+                None
+            },
+            format => Some(VideoWriter::new(BufWriter::new(File::create(path)?), format, scale)?),
+        };
+
+        Ok(Self {
+            video,
+            path: path.to_path_buf(),
+            scale,
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn record_frame(&mut self, video: &[u32]) -> io::Result<()> {
+        let frame = self.frames;
+        self.frames += 1;
+        match &mut self.video {
+            Some(writer) => writer.write_frame(video),
+            None => save_png(&self.path.join(format!("frame_{:06}.png", frame)), video, self.scale),
+        }
+    }
+
+    /// Flushes buffered output. Dropping a recorder without calling this may
+    /// lose the last GIF frame.
+    pub fn finish(self) -> io::Result<PathBuf> {
+        if let Some(writer) = self.video {
+            writer.finish()?;
+        }
+        Ok(self.path)
+    }
+}
+
+fn write_gif_frame<W: Write>(
+    encoder: &mut gif::Encoder<W>,
+    scale: u32,
+    pixels: Vec<u8>,
+    palette: Vec<u8>,
+    delay: u64,
+) -> io::Result<()> {
+    let width = (VIDEO_WIDTH as u32 * scale) as u16;
+    let height = (VIDEO_HEIGHT as u32 * scale) as u16;
+    let mut frame = gif::Frame::from_palette_pixels(width, height, pixels, palette, None);
+    frame.delay = delay.min(u16::MAX as u64) as u16;
+    encoder.write_frame(&frame).map_err(gif_error)
+}
@@ -1,17 +1,20 @@
-use std::fs;
-use std::fs::File;
-use std::io::Read;
-use rand;
+use core::fmt;
 
-const MEMORY_SIZE: usize = 4096;
+use crate::coverage::{Access, Coverage};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+
+pub const MEMORY_SIZE: usize = 4096;
 const SPRITE_MEM_START: usize = 0x50;
-const PROGRAM_MEM_START: usize = 0x200;
-const NUM_REGISTERS: usize = 16;
+pub const PROGRAM_MEM_START: usize = 0x200;
+pub const NUM_REGISTERS: usize = 16;
 const STACK_SIZE: usize = 16;
-const KEYBOARD_SIZE: usize = 16;
+pub const KEYBOARD_SIZE: usize = 16;
+
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - PROGRAM_MEM_START;
 
-const VIDEO_HEIGHT: usize = 32;
-const VIDEO_WIDTH: usize = 64;
+pub const VIDEO_HEIGHT: usize = 32;
+pub const VIDEO_WIDTH: usize = 64;
 
 const KEYBOARD_SPRITES: [[u8; 5]; KEYBOARD_SIZE] = [
     [0xF0, 0x90, 0x90, 0x90, 0xF0], // 0
@@ -32,7 +35,19 @@ const KEYBOARD_SPRITES: [[u8; 5]; KEYBOARD_SIZE] = [
     [0xF0, 0x80, 0xF0, 0x80, 0x80]  // F
 ];
 
-#[derive(Debug)]
+/// A program too big for the memory above 0x200.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomTooLarge {
+    pub len: usize,
+}
+
+impl fmt::Display for RomTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ROM is {} bytes, at most {} fit", self.len, MAX_ROM_SIZE)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Chip8 {
     registers: [u8; NUM_REGISTERS],
     memory: [u8; MEMORY_SIZE],
@@ -43,11 +58,26 @@ pub struct Chip8 {
     delay_timer: u8,
     sound_timer: u8,
     pub keyboard: [bool; KEYBOARD_SIZE],
+    // The keyboard as of the previous cycle, so FX0A can see releases.
+    previous_keyboard: [bool; KEYBOARD_SIZE],
+    // Set by DXYN under the display wait quirk until the frame ends.
+    waiting_for_vblank: bool,
     pub video: [u32; VIDEO_WIDTH * VIDEO_HEIGHT],
     opcode: usize,
+    rom_sha1: [u8; 20],
+    quirks: Quirks,
+    rng: Rng,
+    // Inline rather than boxed, so the core needs no allocator.
+    coverage: Option<Coverage>,
     table: [fn(&mut Chip8); 0xF + 1],
 }
 
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Chip8 {
     pub fn new() -> Self {
         Self {
@@ -60,8 +90,14 @@ impl Chip8 {
             delay_timer: 0,
             sound_timer: 0,
             keyboard: [false; KEYBOARD_SIZE],
+            previous_keyboard: [false; KEYBOARD_SIZE],
+            waiting_for_vblank: false,
             video: [0u32; VIDEO_WIDTH * VIDEO_HEIGHT],
             opcode: 0,
+            rom_sha1: sha1_smol::Sha1::from([]).digest().bytes(),
+            quirks: Quirks::default(),
+            rng: Rng::default(),
+            coverage: None,
             table: [
                 Chip8::table_0,
                 Chip8::op_1nnn,
@@ -83,12 +119,16 @@ impl Chip8 {
         }
     }
 
+    #[cfg(feature = "std")]
     pub fn load_rom(filename: &str) -> Self {
-        let mut f = File::open(&filename).expect("no file found");
-        let metadata = fs::metadata(&filename).expect("unable to read metadata");
-        let mut buffer = vec![0; metadata.len() as usize];
-        f.read(&mut buffer).expect("buffer overflow");
+        let buffer = std::fs::read(filename).expect("no file found");
+        Self::from_bytes(&buffer)
+    }
 
+    /// A machine with `buffer` loaded at 0x200. Bytes past `MAX_ROM_SIZE`
+    /// don't fit in memory and are dropped, so check the size first to
+    /// report it.
+    pub fn from_bytes(buffer: &[u8]) -> Self {
         let mut emulator = Self::new();
 
         let mut ctr = 0;
@@ -99,23 +139,114 @@ impl Chip8 {
             }
         }
 
-        for (i, &e) in buffer.iter().enumerate() {
+        for (i, &e) in buffer.iter().take(MAX_ROM_SIZE).enumerate() {
             emulator.memory[PROGRAM_MEM_START + i] = e;
         }
 
         emulator.pc = PROGRAM_MEM_START;
+        emulator.rom_sha1 = sha1_smol::Sha1::from(buffer).digest().bytes();
 
         emulator
     }
 
+    /// A machine with `program` loaded and the given registers and index
+    /// already set, as if an earlier part of the program had run.
+    pub fn with_state(program: &[u8], registers: [u8; NUM_REGISTERS], index: u16) -> Self {
+        let mut emulator = Self::from_bytes(program);
+        emulator.registers = registers;
+        emulator.index = index;
+        emulator
+    }
+
+    /// Replaces the program image with `rom`, keeping registers, timers,
+    /// the display and the memory below 0x200 as they are. Whatever the old
+    /// program left past the end of `rom` is cleared.
+    pub fn patch_rom(&mut self, rom: &[u8]) -> Result<(), RomTooLarge> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(RomTooLarge { len: rom.len() });
+        }
+        self.memory[PROGRAM_MEM_START..].fill(0);
+        self.memory[PROGRAM_MEM_START..PROGRAM_MEM_START + rom.len()].copy_from_slice(rom);
+        self.rom_sha1 = sha1_smol::Sha1::from(rom).digest().bytes();
+        Ok(())
+    }
+
+    /// Copies as much of `bytes` as fits into memory starting at
+    /// `address`, and returns how many bytes that was.
+    pub fn write_memory(&mut self, address: usize, bytes: &[u8]) -> usize {
+        let end = address.saturating_add(bytes.len()).min(MEMORY_SIZE);
+        let len = end.saturating_sub(address);
+        self.memory[address.min(MEMORY_SIZE)..end].copy_from_slice(&bytes[..len]);
+        len
+    }
+
+    /// SHA-1 of the loaded program, which ROM databases are keyed by.
+    pub fn rom_sha1(&self) -> [u8; 20] {
+        self.rom_sha1
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Replaces the random number generator used by CXKK.
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    /// Starts recording which addresses are executed, read and written, on
+    /// top of what `coverage` already holds. `None` stops recording.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) {
+        self.coverage = coverage;
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    // Records `len` addresses from `start`, wrapping past the end of memory
+    // the way the accesses themselves do.
+    fn record(&mut self, access: Access, start: usize, len: usize) {
+        if let Some(coverage) = self.coverage.as_mut() {
+            let start = start % MEMORY_SIZE;
+            let before_end = len.min(MEMORY_SIZE - start);
+            coverage.record(access, start, before_end);
+            coverage.record(access, 0, len - before_end);
+        }
+    }
+
+    // The address `offset` bytes past I, wrapped into memory.
+    fn index_address(&self, offset: usize) -> usize {
+        (self.index as usize + offset) % MEMORY_SIZE
+    }
+
+    // Stores the result of an 8XY_ instruction and its flag. VF holds the
+    // flag afterwards, even when it is also the destination.
+    fn set_with_flag(&mut self, v_x: usize, value: u8, flag: u8) {
+        if self.quirks.flag_overwritten {
+            self.registers[0xF] = flag;
+            self.registers[v_x] = value;
+        } else {
+            self.registers[v_x] = value;
+            self.registers[0xF] = flag;
+        }
+    }
+
     // Start OpCodes
     fn op_00e0(&mut self) {
         self.video = [0u32; VIDEO_WIDTH * VIDEO_HEIGHT];
     }
 
+    // A return with an empty stack does nothing.
     fn op_00ee(&mut self) {
-        self.sp -= 1;
-        self.pc = self.stack[self.sp];
+        if self.sp > 0 {
+            self.sp -= 1;
+            self.pc = self.stack[self.sp];
+        }
     }
 
     fn op_1nnn(&mut self) {
@@ -123,16 +254,19 @@ impl Chip8 {
         self.pc = address;
     }
 
+    // A call with all 16 stack slots in use does nothing.
     fn op_2nnn(&mut self) {
         let address: usize = self.opcode & 0x0FFFusize;
-        self.stack[self.sp] = self.pc;
-        self.sp += 1;
-        self.pc = address;
+        if self.sp < STACK_SIZE {
+            self.stack[self.sp] = self.pc;
+            self.sp += 1;
+            self.pc = address;
+        }
     }
 
     fn op_3xkk(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        let byte: u8 = self.opcode as u8 & 0x00FFu8;
+        let byte: u8 = self.opcode as u8;
 
         if self.registers[v_x] == byte {
             self.pc += 2;
@@ -141,7 +275,7 @@ impl Chip8 {
 
     fn op_4xkk(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        let byte: u8 = self.opcode as u8 & 0x00FFu8;
+        let byte: u8 = self.opcode as u8;
 
         if self.registers[v_x] != byte {
             self.pc += 2;
@@ -159,14 +293,14 @@ impl Chip8 {
 
     fn op_6xkk(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        let byte: u8 = self.opcode as u8 & 0x00FFu8;
+        let byte: u8 = self.opcode as u8;
 
         self.registers[v_x] = byte;
     }
 
     fn op_7xkk(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        let byte: u8 = self.opcode as u8 & 0x00FFu8;
+        let byte: u8 = self.opcode as u8;
         self.registers[v_x] = self.registers[v_x].wrapping_add(byte);
     }
 
@@ -199,8 +333,7 @@ impl Chip8 {
         let v_y: usize = (self.opcode & 0x00F0usize) >> 4usize;
 
         let (sum, carry) = self.registers[v_x].overflowing_add(self.registers[v_y]);
-        self.registers[0xF] = carry as u8;
-        self.registers[v_x] = sum;
+        self.set_with_flag(v_x, sum, carry as u8);
     }
 
     fn op_8xy5(&mut self) {
@@ -208,14 +341,13 @@ impl Chip8 {
         let v_y: usize = (self.opcode & 0x00F0usize) >> 4usize;
 
         let (diff, borrow) = self.registers[v_x].overflowing_sub(self.registers[v_y]);
-        self.registers[0xF] = !borrow as u8;
-        self.registers[v_x] = diff;
+        self.set_with_flag(v_x, diff, !borrow as u8);
     }
 
     fn op_8xy6(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        self.registers[0xF] = self.registers[v_x] & 0x1u8;
-        self.registers[v_x] >>= 1;
+        let value = self.registers[v_x];
+        self.set_with_flag(v_x, value >> 1, value & 0x1u8);
     }
 
     fn op_8xy7(&mut self) {
@@ -223,14 +355,13 @@ impl Chip8 {
         let v_y: usize = (self.opcode & 0x00F0usize) >> 4usize;
 
         let (diff, borrow) = self.registers[v_y].overflowing_sub(self.registers[v_x]);
-        self.registers[0xF] = !borrow as u8;
-        self.registers[v_x] = diff;
+        self.set_with_flag(v_x, diff, !borrow as u8);
     }
 
     fn op_8xye(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        self.registers[0xF] = (self.registers[v_x] & 0x80u8) >> 7u8;
-        self.registers[v_x] <<= 1;
+        let value = self.registers[v_x];
+        self.set_with_flag(v_x, value << 1, (value & 0x80u8) >> 7u8);
     }
 
     fn op_9xy0(&mut self) {
@@ -249,13 +380,14 @@ impl Chip8 {
 
     fn op_bnnn(&mut self) {
         let address: u16 = self.opcode as u16 & 0x0FFFu16;
-        self.pc = self.registers[0] as usize + address as usize;
+        // Wraps around the 12-bit address space.
+        self.pc = (self.registers[0] as usize + address as usize) & 0x0FFF;
     }
 
     fn op_cxkk(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
         let byte = (self.opcode & 0x00FFusize) as u8;
-        self.registers[v_x] = byte & rand::random::<u8>();
+        self.registers[v_x] = byte & self.rng.next_byte();
     }
 
     fn op_dxyn(&mut self) {
@@ -267,9 +399,10 @@ impl Chip8 {
         let y_pos: usize = self.registers[v_y] as usize % VIDEO_HEIGHT;
 
         self.registers[0xF] = 0;
+        self.record(Access::Read, self.index as usize, height);
 
         for row in 0..height {
-            let sprite_byte = self.memory[self.index as usize + row];
+            let sprite_byte = self.memory[self.index_address(row)];
 
             for col in 0..8 {
                 let sprite_pixel = sprite_byte & (0x80u8 >> col);
@@ -284,11 +417,14 @@ impl Chip8 {
                 }
             }
         }
+
+        self.waiting_for_vblank = self.quirks.display_wait;
     }
 
     fn op_ex9e(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        let key: u8 = self.registers[v_x];
+        // Only the low nibble names a key.
+        let key: u8 = self.registers[v_x] & 0xF;
 
         if self.keyboard[key as usize] {
             self.pc += 2;
@@ -297,7 +433,8 @@ impl Chip8 {
 
     fn op_exa1(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        let key: u8 = self.registers[v_x];
+        // Only the low nibble names a key.
+        let key: u8 = self.registers[v_x] & 0xF;
 
         if !self.keyboard[key as usize] {
             self.pc += 2;
@@ -312,10 +449,16 @@ impl Chip8 {
     fn op_fx0a(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
 
-        if let Some(i) = self.keyboard.iter().position(|&k| k) {
+        let key = if self.quirks.fx0a_on_press {
+            self.keyboard.iter().position(|&k| k)
+        } else {
+            (0..KEYBOARD_SIZE).find(|&k| self.previous_keyboard[k] && !self.keyboard[k])
+        };
+
+        if let Some(i) = key {
             self.registers[v_x] = i as u8;
         } else {
-            self.pc -= 2;
+            self.pc = (self.pc + MEMORY_SIZE - 2) % MEMORY_SIZE;
         }
     }
 
@@ -331,45 +474,56 @@ impl Chip8 {
 
     fn op_fx1e(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        self.index += self.registers[v_x] as u16;
+        self.index = (self.index + self.registers[v_x] as u16) & 0x0FFF;
     }
 
     fn op_fx29(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        let val: u8 = self.registers[v_x];
-        self.index = (SPRITE_MEM_START + (5 * val) as usize) as u16;
+        let val: usize = (self.registers[v_x] & 0xF) as usize;
+        self.index = (SPRITE_MEM_START + 5 * val) as u16;
     }
 
     fn op_fx33(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
         let val: u8 = self.registers[v_x];
+        self.record(Access::Write, self.index as usize, 3);
 
-        self.memory[self.index as usize + 2] = val % 10;
-        self.memory[self.index as usize + 1] = (val / 10) % 10;
-        self.memory[self.index as usize] = (val / 100) % 10;
+        self.memory[self.index_address(2)] = val % 10;
+        self.memory[self.index_address(1)] = (val / 10) % 10;
+        self.memory[self.index_address(0)] = (val / 100) % 10;
     }
 
     fn op_fx55(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        
+        self.record(Access::Write, self.index as usize, v_x + 1);
+
         for i in 0..=v_x {
-            self.memory[self.index as usize + i] = self.registers[i];
+            self.memory[self.index_address(i)] = self.registers[i];
         }
     }
 
     fn op_fx65(&mut self) {
         let v_x: usize = (self.opcode & 0x0F00usize) >> 8usize;
-        
+        self.record(Access::Read, self.index as usize, v_x + 1);
+
         for i in 0..=v_x {
-            self.registers[i] = self.memory[self.index as usize + i];
+            self.registers[i] = self.memory[self.index_address(i)];
         }
     }
 
     fn op_null(&mut self) {}
 
     pub fn cycle(&mut self) {
-        self.opcode = ((self.memory[self.pc] as usize) << 8) | (self.memory[self.pc + 1] as usize);
-        self.pc += 2;
+        if self.waiting_for_vblank {
+            return;
+        }
+
+        // Skips at the top of memory run off the end; fetching wraps.
+        self.pc %= MEMORY_SIZE;
+        let next = (self.pc + 1) % MEMORY_SIZE;
+        self.opcode = ((self.memory[self.pc] as usize) << 8) | (self.memory[next] as usize);
+        self.record(Access::Execute, self.pc, 2);
+        self.pc = (self.pc + 2) % MEMORY_SIZE;
 
         match (self.opcode & 0xF000) >> 12 {
             0x0 => self.table_0(),
@@ -379,6 +533,80 @@ impl Chip8 {
             _ => (self.table[(self.opcode & 0xF000usize) >> 12usize])(self),
         }
 
+        self.previous_keyboard = self.keyboard;
+    }
+
+    pub fn registers(&self) -> &[u8; NUM_REGISTERS] {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Sets VX, e.g. from a debugger.
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.registers[x] = value;
+    }
+
+    /// Points I at `index`, wrapped into memory.
+    pub fn set_index(&mut self, index: u16) {
+        self.index = index % MEMORY_SIZE as u16;
+    }
+
+    /// Moves execution to `pc`, wrapped into memory.
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc % MEMORY_SIZE;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// Whether a draw under the display wait quirk has stalled the CPU
+    /// until the end of the frame. `cycle` does nothing meanwhile.
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
+    }
+
+    /// Decrements the delay and sound timers and ends any display wait.
+    /// Called once per 60 Hz frame, independently of how many instructions
+    /// ran during that frame.
+    pub fn tick_timers(&mut self) {
+        self.waiting_for_vblank = false;
+
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -409,7 +637,10 @@ impl Chip8 {
         table_0[0x0] = Chip8::op_00e0;
         table_0[0xE] = Chip8::op_00ee;
 
-        table_0[self.opcode & 0x000Fusize](self);
+        // Opcodes past the end of the table are unassigned, like op_null.
+        if let Some(op) = table_0.get(self.opcode & 0x000Fusize) {
+            op(self);
+        }
     }
 
     fn table_8(&mut self) {
@@ -441,7 +672,10 @@ impl Chip8 {
         table_8[0x7] = Chip8::op_8xy7;
         table_8[0xE] = Chip8::op_8xye;
 
-        table_8[self.opcode & 0x000Fusize](self);
+        // Opcodes past the end of the table are unassigned, like op_null.
+        if let Some(op) = table_8.get(self.opcode & 0x000Fusize) {
+            op(self);
+        }
     }
 
     fn table_e(&mut self) {
@@ -465,7 +699,10 @@ impl Chip8 {
         table_e[0x1] = Chip8::op_exa1;
         table_e[0xE] = Chip8::op_ex9e;
 
-        table_e[self.opcode & 0x000Fusize](self);
+        // Opcodes past the end of the table are unassigned, like op_null.
+        if let Some(op) = table_e.get(self.opcode & 0x000Fusize) {
+            op(self);
+        }
     }
 
     fn table_f(&mut self) {
@@ -584,6 +821,12 @@ impl Chip8 {
         table_f[0x55] = Chip8::op_fx55;
         table_f[0x65] = Chip8::op_fx65;
 
-        table_f[self.opcode & 0x00FFusize](self);
+        // Opcodes past the end of the table are unassigned, like op_null.
+        if let Some(op) = table_f.get(self.opcode & 0x00FFusize) {
+            op(self);
+        }
     }
 }
+
+#[cfg(test)]
+mod tests;
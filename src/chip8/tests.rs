@@ -0,0 +1,735 @@
+use super::*;
+
+const ON: u32 = 0xFFFFFFFF;
+
+fn regs(values: &[(usize, u8)]) -> [u8; NUM_REGISTERS] {
+    let mut registers = [0; NUM_REGISTERS];
+    for &(x, value) in values {
+        registers[x] = value;
+    }
+    registers
+}
+
+// Runs `opcode` as the first instruction of a machine with the given registers.
+fn exec(opcode: u16, registers: &[(usize, u8)]) -> Chip8 {
+    exec_with_index(opcode, registers, 0)
+}
+
+fn exec_with_index(opcode: u16, registers: &[(usize, u8)], index: u16) -> Chip8 {
+    let mut chip8 = Chip8::with_state(&opcode.to_be_bytes(), regs(registers), index);
+    chip8.cycle();
+    chip8
+}
+
+// Runs `opcode` as the next instruction of an existing machine.
+fn step(chip8: &mut Chip8, opcode: u16) {
+    let pc = chip8.pc();
+    chip8.write_memory(pc, &opcode.to_be_bytes());
+    chip8.cycle();
+}
+
+fn lit(chip8: &Chip8, x: usize, y: usize) -> bool {
+    chip8.video[y * VIDEO_WIDTH + x] == ON
+}
+
+fn lit_count(chip8: &Chip8) -> usize {
+    chip8.video.iter().filter(|&&p| p == ON).count()
+}
+
+#[test]
+fn from_bytes_loads_font_and_program() {
+    let chip8 = Chip8::from_bytes(&[0x12, 0x34]);
+    assert_eq!(&chip8.memory()[SPRITE_MEM_START..SPRITE_MEM_START + 5], &KEYBOARD_SPRITES[0]);
+    assert_eq!(&chip8.memory()[SPRITE_MEM_START + 75..SPRITE_MEM_START + 80], &KEYBOARD_SPRITES[0xF]);
+    assert_eq!(&chip8.memory()[PROGRAM_MEM_START..PROGRAM_MEM_START + 2], &[0x12, 0x34]);
+    assert_eq!(chip8.pc(), PROGRAM_MEM_START);
+}
+
+#[test]
+fn patch_rom_replaces_the_whole_program() {
+    let mut chip8 = Chip8::from_bytes(&[0x12, 0x34, 0x56, 0x78]);
+    chip8.set_register(0x3, 0x42);
+    assert_eq!(chip8.patch_rom(&[0xAB]), Ok(()));
+    assert_eq!(&chip8.memory()[PROGRAM_MEM_START..PROGRAM_MEM_START + 4], &[0xAB, 0, 0, 0]);
+    assert_eq!(chip8.registers()[0x3], 0x42);
+    assert_eq!(chip8.rom_sha1(), Chip8::from_bytes(&[0xAB]).rom_sha1());
+
+    assert_eq!(chip8.patch_rom(&[0; MAX_ROM_SIZE + 1]), Err(RomTooLarge { len: MAX_ROM_SIZE + 1 }));
+    assert_eq!(chip8.memory()[PROGRAM_MEM_START], 0xAB);
+}
+
+#[test]
+fn write_memory_stops_at_the_end_of_memory() {
+    let mut chip8 = Chip8::new();
+    assert_eq!(chip8.write_memory(0x300, &[1, 2]), 2);
+    assert_eq!(chip8.write_memory(MEMORY_SIZE - 1, &[3, 4, 5]), 1);
+    assert_eq!(chip8.write_memory(MEMORY_SIZE + 5, &[6]), 0);
+    assert_eq!(chip8.memory()[MEMORY_SIZE - 1], 3);
+}
+
+#[test]
+fn from_bytes_drops_bytes_past_memory() {
+    let chip8 = Chip8::from_bytes(&[0xAA; MAX_ROM_SIZE + 10]);
+    assert_eq!(chip8.memory()[MEMORY_SIZE - 1], 0xAA);
+}
+
+#[test]
+fn with_state_sets_registers_and_index() {
+    let chip8 = Chip8::with_state(&[0x00, 0xE0], regs(&[(0x3, 7), (0xF, 1)]), 0x345);
+    assert_eq!(chip8.registers()[0x3], 7);
+    assert_eq!(chip8.registers()[0xF], 1);
+    assert_eq!(chip8.index(), 0x345);
+    assert_eq!(chip8.memory()[PROGRAM_MEM_START + 1], 0xE0);
+}
+
+#[test]
+fn op_00e0_clears_the_display() {
+    let mut chip8 = Chip8::new();
+    chip8.video = [ON; VIDEO_WIDTH * VIDEO_HEIGHT];
+    step(&mut chip8, 0x00E0);
+    assert_eq!(lit_count(&chip8), 0);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn op_2nnn_and_op_00ee_call_and_return() {
+    let mut chip8 = Chip8::new();
+    step(&mut chip8, 0x2400);
+    assert_eq!(chip8.pc(), 0x400);
+    assert_eq!(chip8.stack(), &[0x202]);
+
+    step(&mut chip8, 0x2600);
+    assert_eq!(chip8.stack(), &[0x202, 0x402]);
+
+    step(&mut chip8, 0x00EE);
+    assert_eq!(chip8.pc(), 0x402);
+    step(&mut chip8, 0x00EE);
+    assert_eq!(chip8.pc(), 0x202);
+    assert_eq!(chip8.sp(), 0);
+}
+
+#[test]
+fn op_2nnn_nests_sixteen_deep() {
+    let mut chip8 = Chip8::new();
+    for _ in 0..STACK_SIZE {
+        step(&mut chip8, 0x2300);
+    }
+    assert_eq!(chip8.sp(), STACK_SIZE);
+    assert_eq!(chip8.stack()[0], 0x202);
+    assert!(chip8.stack()[1..].iter().all(|&a| a == 0x302));
+}
+
+#[test]
+fn op_2nnn_with_a_full_stack_does_nothing() {
+    let mut chip8 = Chip8::new();
+    for _ in 0..STACK_SIZE {
+        step(&mut chip8, 0x2300);
+    }
+    step(&mut chip8, 0x2400);
+    assert_eq!((chip8.sp(), chip8.pc()), (STACK_SIZE, 0x302));
+}
+
+#[test]
+fn op_00ee_with_an_empty_stack_does_nothing() {
+    let chip8 = exec(0x00EE, &[]);
+    assert_eq!((chip8.sp(), chip8.pc()), (0, 0x202));
+}
+
+#[test]
+fn op_1nnn_jumps() {
+    assert_eq!(exec(0x1ABC, &[]).pc(), 0xABC);
+    assert_eq!(exec(0x1000, &[]).pc(), 0x000);
+}
+
+#[test]
+fn op_3xkk_skips_if_equal() {
+    assert_eq!(exec(0x3542, &[(0x5, 0x42)]).pc(), 0x204);
+    assert_eq!(exec(0x3542, &[(0x5, 0x43)]).pc(), 0x202);
+    assert_eq!(exec(0x3F01, &[(0xF, 0x01)]).pc(), 0x204);
+}
+
+#[test]
+fn op_4xkk_skips_if_not_equal() {
+    assert_eq!(exec(0x4542, &[(0x5, 0x42)]).pc(), 0x202);
+    assert_eq!(exec(0x4542, &[(0x5, 0x43)]).pc(), 0x204);
+}
+
+#[test]
+fn op_5xy0_skips_if_registers_equal() {
+    assert_eq!(exec(0x5120, &[(0x1, 9), (0x2, 9)]).pc(), 0x204);
+    assert_eq!(exec(0x5120, &[(0x1, 9), (0x2, 8)]).pc(), 0x202);
+    assert_eq!(exec(0x5110, &[(0x1, 9)]).pc(), 0x204);
+}
+
+#[test]
+fn op_6xkk_loads() {
+    assert_eq!(exec(0x6A5C, &[(0xA, 1)]).registers()[0xA], 0x5C);
+    assert_eq!(exec(0x6F01, &[]).registers()[0xF], 0x01);
+}
+
+#[test]
+fn op_7xkk_adds_with_wraparound_and_no_carry() {
+    assert_eq!(exec(0x7305, &[(0x3, 10)]).registers()[0x3], 15);
+
+    let chip8 = exec(0x7302, &[(0x3, 0xFF)]);
+    assert_eq!(chip8.registers()[0x3], 0x01);
+    assert_eq!(chip8.registers()[0xF], 0, "7XKK never touches VF");
+}
+
+#[test]
+fn op_8xy0_copies() {
+    let chip8 = exec(0x8120, &[(0x1, 1), (0x2, 0xAB)]);
+    assert_eq!(chip8.registers()[0x1], 0xAB);
+    assert_eq!(chip8.registers()[0x2], 0xAB);
+}
+
+#[test]
+fn op_8xy1_8xy2_8xy3_bitwise() {
+    let operands = [(0x1, 0b1100), (0x2, 0b1010)];
+    assert_eq!(exec(0x8121, &operands).registers()[0x1], 0b1110);
+    assert_eq!(exec(0x8122, &operands).registers()[0x1], 0b1000);
+    assert_eq!(exec(0x8123, &operands).registers()[0x1], 0b0110);
+}
+
+#[test]
+fn op_8xy1_8xy2_8xy3_leave_vf_alone() {
+    for opcode in [0x8121, 0x8122, 0x8123] {
+        assert_eq!(exec(opcode, &[(0x1, 3), (0x2, 5), (0xF, 0x77)]).registers()[0xF], 0x77);
+    }
+}
+
+#[test]
+fn op_8xy4_adds_with_carry() {
+    let chip8 = exec(0x8124, &[(0x1, 200), (0x2, 55), (0xF, 1)]);
+    assert_eq!(chip8.registers()[0x1], 255);
+    assert_eq!(chip8.registers()[0xF], 0);
+
+    let chip8 = exec(0x8124, &[(0x1, 200), (0x2, 56)]);
+    assert_eq!(chip8.registers()[0x1], 0);
+    assert_eq!(chip8.registers()[0xF], 1);
+
+    let chip8 = exec(0x8114, &[(0x1, 0x80)]);
+    assert_eq!(chip8.registers()[0x1], 0);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn op_8xy4_with_vf_as_source() {
+    // VF is read as an operand before the carry replaces it.
+    let chip8 = exec(0x81F4, &[(0x1, 0xFF), (0xF, 0x02)]);
+    assert_eq!(chip8.registers()[0x1], 0x01);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn op_8xy4_with_vf_as_destination() {
+    let chip8 = exec(0x8F14, &[(0x1, 0x01), (0xF, 0x02)]);
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn op_8xy5_subtracts_with_not_borrow() {
+    let chip8 = exec(0x8125, &[(0x1, 10), (0x2, 3)]);
+    assert_eq!(chip8.registers()[0x1], 7);
+    assert_eq!(chip8.registers()[0xF], 1);
+
+    let chip8 = exec(0x8125, &[(0x1, 3), (0x2, 10)]);
+    assert_eq!(chip8.registers()[0x1], 249);
+    assert_eq!(chip8.registers()[0xF], 0);
+
+    // Equal operands don't borrow.
+    let chip8 = exec(0x8125, &[(0x1, 5), (0x2, 5)]);
+    assert_eq!(chip8.registers()[0x1], 0);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn op_8xy5_with_vf_as_destination() {
+    let chip8 = exec(0x8F15, &[(0x1, 3), (0xF, 10)]);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn op_8xy5_with_vf_as_source() {
+    let chip8 = exec(0x81F5, &[(0x1, 3), (0xF, 10)]);
+    assert_eq!(chip8.registers()[0x1], 249);
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn flag_overwritten_quirk_lets_the_result_replace_vf() {
+    let quirks = Quirks { flag_overwritten: true, ..Quirks::default() };
+    for (opcode, registers, vf) in [
+        (0x8F14, [(0x1, 0x01), (0xF, 0x02)], 0x03),
+        (0x8F15, [(0x1, 3), (0xF, 10)], 7),
+        (0x8F06, [(0x1, 0), (0xF, 0b0000_0101)], 0b0000_0010),
+        (0x8F17, [(0x1, 10), (0xF, 3)], 7),
+        (0x8F0E, [(0x1, 0), (0xF, 0b1000_0001)], 0b0000_0010),
+    ] {
+        let mut chip8 = Chip8::with_state(&u16::to_be_bytes(opcode), regs(&registers), 0);
+        chip8.set_quirks(quirks);
+        chip8.cycle();
+        assert_eq!(chip8.registers()[0xF], vf, "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn flag_overwritten_quirk_keeps_other_destinations() {
+    let mut chip8 = Chip8::with_state(&[0x81, 0x24], regs(&[(0x1, 200), (0x2, 56)]), 0);
+    chip8.set_quirks(Quirks { flag_overwritten: true, ..Quirks::default() });
+    chip8.cycle();
+    assert_eq!(chip8.registers()[0x1], 0);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn op_8xy6_shifts_right_into_vf() {
+    let chip8 = exec(0x8126, &[(0x1, 0b1000_0011), (0x2, 0xFF)]);
+    assert_eq!(chip8.registers()[0x1], 0b0100_0001);
+    assert_eq!(chip8.registers()[0xF], 1);
+
+    let chip8 = exec(0x8126, &[(0x1, 0b0000_0010), (0xF, 1)]);
+    assert_eq!(chip8.registers()[0x1], 0b0000_0001);
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn op_8xy6_with_vf_as_destination() {
+    let chip8 = exec(0x8F06, &[(0xF, 0b0000_0101)]);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn op_8xy7_subtracts_reversed() {
+    let chip8 = exec(0x8127, &[(0x1, 3), (0x2, 10)]);
+    assert_eq!(chip8.registers()[0x1], 7);
+    assert_eq!(chip8.registers()[0xF], 1);
+
+    let chip8 = exec(0x8127, &[(0x1, 10), (0x2, 3)]);
+    assert_eq!(chip8.registers()[0x1], 249);
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn op_8xy7_with_vf_as_destination() {
+    let chip8 = exec(0x8F17, &[(0x1, 10), (0xF, 3)]);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn op_8xye_shifts_left_into_vf() {
+    let chip8 = exec(0x812E, &[(0x1, 0b1100_0001)]);
+    assert_eq!(chip8.registers()[0x1], 0b1000_0010);
+    assert_eq!(chip8.registers()[0xF], 1);
+
+    let chip8 = exec(0x812E, &[(0x1, 0b0100_0000), (0xF, 1)]);
+    assert_eq!(chip8.registers()[0x1], 0b1000_0000);
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn op_8xye_with_vf_as_destination() {
+    let chip8 = exec(0x8F0E, &[(0xF, 0b1000_0001)]);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn op_9xy0_skips_if_registers_differ() {
+    assert_eq!(exec(0x9120, &[(0x1, 9), (0x2, 8)]).pc(), 0x204);
+    assert_eq!(exec(0x9120, &[(0x1, 9), (0x2, 9)]).pc(), 0x202);
+}
+
+#[test]
+fn op_annn_sets_index() {
+    assert_eq!(exec(0xA123, &[]).index(), 0x123);
+}
+
+#[test]
+fn op_bnnn_jumps_with_v0_offset() {
+    assert_eq!(exec(0xB300, &[(0x0, 0x10), (0x1, 0x20)]).pc(), 0x310);
+    assert_eq!(exec(0xBFFF, &[(0x0, 0xFF)]).pc(), 0x0FE);
+}
+
+#[test]
+fn cycles_after_wrapping_past_the_end_of_memory() {
+    let mut chip8 = exec(0xBFFF, &[(0x0, 0x01)]);
+    assert_eq!(chip8.pc(), 0x000);
+    chip8.cycle();
+    assert_eq!(chip8.pc(), 0x002);
+
+    // An instruction in the last byte fetches its second byte from 0x000.
+    chip8.set_pc(MEMORY_SIZE - 1);
+    chip8.write_memory(MEMORY_SIZE - 1, &[0x61]);
+    chip8.write_memory(0x000, &[0x07]);
+    chip8.cycle();
+    assert_eq!((chip8.registers()[0x1], chip8.pc()), (0x07, 0x001));
+}
+
+#[test]
+fn set_index_wraps_into_memory() {
+    let mut chip8 = Chip8::new();
+    chip8.set_index(0xFFFF);
+    assert_eq!(chip8.index(), 0xFFF);
+    chip8.set_index(0x1234);
+    assert_eq!(chip8.index(), 0x234);
+}
+
+#[test]
+fn accesses_through_i_wrap_past_the_end_of_memory() {
+    // DXY2 at I = 0xFFF draws the last byte, then the first.
+    let mut chip8 = Chip8::with_state(&[0xD0, 0x12], regs(&[]), 0xFFF);
+    chip8.write_memory(0xFFF, &[0x80]);
+    chip8.memory[0x000] = 0x40;
+    chip8.cycle();
+    assert!(lit(&chip8, 0, 0) && lit(&chip8, 1, 1));
+    assert_eq!(lit_count(&chip8), 2);
+
+    // FX33 at I = 0xFFE puts the ones digit at 0x000.
+    let chip8 = exec_with_index(0xF033, &[(0x0, 123)], 0xFFE);
+    assert_eq!((chip8.memory()[0xFFE], chip8.memory()[0xFFF], chip8.memory()[0x000]), (1, 2, 3));
+
+    let registers: Vec<(usize, u8)> = (0..16).map(|x| (x, x as u8 + 1)).collect();
+    let chip8 = exec_with_index(0xFF55, &registers, 0xFFF);
+    assert_eq!(chip8.memory()[0xFFF], 1);
+    assert_eq!(&chip8.memory()[..15], &(2..=16).collect::<Vec<u8>>()[..]);
+
+    let mut chip8 = Chip8::with_state(&[0xFF, 0x65], regs(&[]), 0xFFF);
+    chip8.write_memory(0xFFF, &[0xAA]);
+    chip8.memory[0x001] = 0xBB;
+    chip8.cycle();
+    assert_eq!((chip8.registers()[0x0], chip8.registers()[0x2]), (0xAA, 0xBB));
+}
+
+#[test]
+fn coverage_wraps_past_the_end_of_memory() {
+    let mut chip8 = Chip8::with_state(&[0xF2, 0x55], regs(&[]), 0xFFE);
+    chip8.set_coverage(Some(Coverage::default()));
+    chip8.cycle();
+    chip8.set_pc(MEMORY_SIZE - 1);
+    chip8.write_memory(MEMORY_SIZE - 1, &[0x60]);
+    chip8.cycle();
+
+    let coverage = chip8.coverage().unwrap();
+    assert_eq!(coverage.ranges(Access::Write).collect::<Vec<_>>(), [(0x000, 0x000), (0xFFE, 0xFFF)]);
+    assert_eq!(coverage.ranges(Access::Execute).collect::<Vec<_>>(), [(0x000, 0x000), (0x200, 0x201), (0xFFF, 0xFFF)]);
+}
+
+#[test]
+fn op_cxkk_masks_random_byte() {
+    let mut chip8 = Chip8::with_state(&[0xC3, 0x0F], regs(&[]), 0);
+    chip8.set_rng(Rng::from_fn(|| 0xAB));
+    chip8.cycle();
+    assert_eq!(chip8.registers()[0x3], 0x0B);
+
+    let mut chip8 = Chip8::with_state(&[0xC3, 0x00], regs(&[(0x3, 0xFF)]), 0);
+    chip8.cycle();
+    assert_eq!(chip8.registers()[0x3], 0);
+}
+
+#[test]
+fn op_cxkk_is_reproducible_with_a_seed() {
+    let run = || {
+        let mut chip8 = Chip8::from_bytes(&[0xC0, 0xFF, 0xC1, 0xFF]);
+        chip8.set_rng(Rng::seeded(1234));
+        chip8.cycle();
+        chip8.cycle();
+        [chip8.registers()[0x0], chip8.registers()[0x1]]
+    };
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn op_dxyn_draws_sprite_rows() {
+    // Font "0" is F0 90 90 90 F0.
+    let chip8 = exec_with_index(0xD125, &[(0x1, 10), (0x2, 4)], SPRITE_MEM_START as u16);
+    assert!(lit(&chip8, 10, 4) && lit(&chip8, 13, 4));
+    assert!(!lit(&chip8, 14, 4));
+    assert!(lit(&chip8, 10, 5) && !lit(&chip8, 11, 5) && lit(&chip8, 13, 5));
+    assert!(lit(&chip8, 11, 8));
+    assert!(!lit(&chip8, 10, 9));
+    assert_eq!(lit_count(&chip8), 14);
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn op_dxyn_with_zero_height_draws_nothing() {
+    let chip8 = exec_with_index(0xD120, &[], SPRITE_MEM_START as u16);
+    assert_eq!(lit_count(&chip8), 0);
+}
+
+#[test]
+fn op_dxyn_sets_vf_on_collision_and_erases() {
+    let mut chip8 = Chip8::with_state(&[], regs(&[(0xF, 1)]), SPRITE_MEM_START as u16);
+    step(&mut chip8, 0xD015);
+    assert_eq!(chip8.registers()[0xF], 0);
+
+    step(&mut chip8, 0xD015);
+    assert_eq!(chip8.registers()[0xF], 1);
+    assert_eq!(lit_count(&chip8), 0);
+}
+
+#[test]
+fn op_dxyn_collision_needs_overlapping_pixels() {
+    let mut chip8 = Chip8::with_state(&[], regs(&[(0x1, 4)]), SPRITE_MEM_START as u16);
+    step(&mut chip8, 0xD005);
+    step(&mut chip8, 0xD105);
+    assert_eq!(chip8.registers()[0xF], 0, "sprites side by side don't collide");
+
+    step(&mut chip8, 0xD015);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn op_dxyn_wraps_start_coordinates() {
+    let chip8 = exec_with_index(0xD121, &[(0x1, 64 + 3), (0x2, 32 + 2)], SPRITE_MEM_START as u16);
+    assert!(lit(&chip8, 3, 2) && lit(&chip8, 6, 2));
+    assert_eq!(lit_count(&chip8), 4);
+}
+
+#[test]
+fn op_dxyn_wraps_past_the_bottom_edge() {
+    let chip8 = exec_with_index(0xD125, &[(0x1, 0), (0x2, 30)], SPRITE_MEM_START as u16);
+    assert!(lit(&chip8, 0, 30) && lit(&chip8, 0, 31));
+    assert!(lit(&chip8, 0, 0) && lit(&chip8, 1, 2));
+}
+
+#[test]
+fn op_dxyn_reads_vf_coordinate_before_clearing_it() {
+    let chip8 = exec_with_index(0xD0F1, &[(0x0, 0), (0xF, 5)], SPRITE_MEM_START as u16);
+    assert!(lit(&chip8, 0, 5));
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn display_wait_quirk_stalls_until_the_frame_ends() {
+    let mut chip8 = Chip8::with_state(&[0xD0, 0x15, 0x61, 0x01], regs(&[]), SPRITE_MEM_START as u16);
+    chip8.set_quirks(Quirks::cosmac_vip());
+    chip8.cycle();
+    assert!(chip8.is_waiting_for_vblank());
+    assert_eq!(lit_count(&chip8), 14, "the sprite is drawn before the wait");
+
+    chip8.cycle();
+    assert_eq!(chip8.pc(), 0x202);
+    assert_eq!(chip8.registers()[0x1], 0);
+
+    chip8.tick_timers();
+    assert!(!chip8.is_waiting_for_vblank());
+    chip8.cycle();
+    assert_eq!(chip8.registers()[0x1], 1);
+}
+
+#[test]
+fn op_dxyn_does_not_wait_by_default() {
+    let chip8 = exec_with_index(0xD015, &[], SPRITE_MEM_START as u16);
+    assert!(!chip8.is_waiting_for_vblank());
+}
+
+#[test]
+fn quirk_profiles() {
+    assert_eq!(Quirks::from_profile("modern"), Some(Quirks::default()));
+    assert!(Quirks::from_profile("vip").unwrap().display_wait);
+    let legacy = Quirks::from_profile("legacy").unwrap();
+    assert!(legacy.flag_overwritten && legacy.fx0a_on_press && !legacy.display_wait);
+    assert_eq!(Quirks::from_profile("schip"), None);
+    for name in crate::quirks::PROFILES {
+        assert!(Quirks::from_profile(name).is_some(), "{}", name);
+    }
+}
+
+#[test]
+fn op_ex9e_skips_if_key_pressed() {
+    let mut chip8 = Chip8::with_state(&[0xE3, 0x9E], regs(&[(0x3, 0xA)]), 0);
+    chip8.keyboard[0xA] = true;
+    chip8.cycle();
+    assert_eq!(chip8.pc(), 0x204);
+
+    assert_eq!(exec(0xE39E, &[(0x3, 0xA)]).pc(), 0x202);
+}
+
+#[test]
+fn op_ex9e_and_op_exa1_use_the_low_nibble_of_vx() {
+    let mut chip8 = Chip8::with_state(&[0xE3, 0x9E], regs(&[(0x3, 0xFA)]), 0);
+    chip8.keyboard[0xA] = true;
+    chip8.cycle();
+    assert_eq!(chip8.pc(), 0x204);
+
+    assert_eq!(exec(0xE3A1, &[(0x3, 0xFA)]).pc(), 0x204);
+}
+
+#[test]
+fn op_exa1_skips_if_key_not_pressed() {
+    let mut chip8 = Chip8::with_state(&[0xE3, 0xA1], regs(&[(0x3, 0xA)]), 0);
+    chip8.keyboard[0xA] = true;
+    chip8.cycle();
+    assert_eq!(chip8.pc(), 0x202);
+
+    assert_eq!(exec(0xE3A1, &[(0x3, 0xA)]).pc(), 0x204);
+}
+
+#[test]
+fn op_fx07_reads_delay_timer() {
+    let mut chip8 = Chip8::with_state(&[0x61, 0x2A, 0xF1, 0x15, 0xF4, 0x07], regs(&[]), 0);
+    chip8.cycle();
+    chip8.cycle();
+    chip8.tick_timers();
+    chip8.cycle();
+    assert_eq!(chip8.registers()[0x4], 0x29);
+}
+
+#[test]
+fn op_fx0a_waits_for_a_press_and_release() {
+    let mut chip8 = Chip8::from_bytes(&[0xF5, 0x0A]);
+    chip8.cycle();
+    chip8.cycle();
+    assert_eq!(chip8.pc(), 0x200);
+
+    chip8.keyboard[0x7] = true;
+    chip8.cycle();
+    chip8.cycle();
+    assert_eq!(chip8.pc(), 0x200, "a held key doesn't finish the wait");
+
+    chip8.keyboard[0x7] = false;
+    chip8.cycle();
+    assert_eq!(chip8.registers()[0x5], 0x7);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn op_fx0a_takes_the_released_key() {
+    let mut chip8 = Chip8::from_bytes(&[0xF5, 0x0A]);
+    chip8.keyboard[0x2] = true;
+    chip8.keyboard[0x9] = true;
+    chip8.cycle();
+    chip8.keyboard[0x9] = false;
+    chip8.cycle();
+    assert_eq!(chip8.registers()[0x5], 0x9);
+}
+
+#[test]
+fn op_fx0a_waits_at_the_start_of_memory() {
+    // Fetching the last two bytes wraps PC to 0x000 before FX0A backs up.
+    let mut chip8 = Chip8::new();
+    chip8.set_pc(MEMORY_SIZE - 2);
+    chip8.write_memory(MEMORY_SIZE - 2, &[0xF5, 0x0A]);
+    chip8.cycle();
+    assert_eq!(chip8.pc(), MEMORY_SIZE - 2);
+}
+
+#[test]
+fn fx0a_on_press_quirk_finishes_on_key_down() {
+    let mut chip8 = Chip8::from_bytes(&[0xF5, 0x0A]);
+    chip8.set_quirks(Quirks { fx0a_on_press: true, ..Quirks::default() });
+    chip8.cycle();
+    assert_eq!(chip8.pc(), 0x200);
+
+    chip8.keyboard[0x7] = true;
+    chip8.cycle();
+    assert_eq!(chip8.registers()[0x5], 0x7);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn op_fx15_and_op_fx18_set_timers() {
+    let chip8 = exec(0xF215, &[(0x2, 30)]);
+    assert_eq!(chip8.delay_timer(), 30);
+    assert!(!chip8.is_beeping());
+
+    let chip8 = exec(0xF218, &[(0x2, 2)]);
+    assert_eq!(chip8.sound_timer(), 2);
+    assert!(chip8.is_beeping());
+}
+
+#[test]
+fn timers_count_down_to_zero() {
+    let mut chip8 = exec(0xF218, &[(0x2, 2)]);
+    chip8.tick_timers();
+    chip8.tick_timers();
+    assert!(!chip8.is_beeping());
+    chip8.tick_timers();
+    assert_eq!(chip8.sound_timer(), 0);
+}
+
+#[test]
+fn op_fx1e_adds_to_index_without_touching_vf() {
+    let chip8 = exec_with_index(0xF31E, &[(0x3, 0x10), (0xF, 0x55)], 0xFF8);
+    assert_eq!(chip8.index(), 0x008);
+    assert_eq!(chip8.registers()[0xF], 0x55);
+}
+
+#[test]
+fn op_fx29_points_at_font_sprite() {
+    assert_eq!(exec(0xF429, &[(0x4, 0x0)]).index() as usize, SPRITE_MEM_START);
+    assert_eq!(exec(0xF429, &[(0x4, 0xA)]).index() as usize, SPRITE_MEM_START + 50);
+    assert_eq!(exec(0xF429, &[(0x4, 0xF)]).index() as usize, SPRITE_MEM_START + 75);
+    assert_eq!(exec(0xF429, &[(0x4, 0xFF)]).index() as usize, SPRITE_MEM_START + 75);
+}
+
+#[test]
+fn op_fx33_stores_bcd() {
+    for (value, digits) in [(0, [0, 0, 0]), (9, [0, 0, 9]), (10, [0, 1, 0]), (123, [1, 2, 3]), (255, [2, 5, 5])] {
+        let chip8 = exec_with_index(0xF633, &[(0x6, value)], 0x300);
+        assert_eq!(&chip8.memory()[0x300..0x303], &digits, "BCD of {}", value);
+        assert_eq!(chip8.index(), 0x300);
+    }
+}
+
+#[test]
+fn op_fx55_stores_v0_through_vx() {
+    let registers: Vec<(usize, u8)> = (0..NUM_REGISTERS).map(|x| (x, x as u8 + 1)).collect();
+    let chip8 = exec_with_index(0xF355, &registers, 0x300);
+    assert_eq!(&chip8.memory()[0x300..0x305], &[1, 2, 3, 4, 0]);
+    assert_eq!(chip8.index(), 0x300);
+
+    let chip8 = exec_with_index(0xFF55, &registers, 0x300);
+    assert_eq!(chip8.memory()[0x30F], 16);
+}
+
+#[test]
+fn op_fx65_loads_v0_through_vx() {
+    let mut chip8 = Chip8::with_state(&[0xF2, 0x65], regs(&[(0x3, 0xEE)]), 0x300);
+    chip8.write_memory(0x300, &[7, 8, 9, 10]);
+    chip8.cycle();
+    assert_eq!(&chip8.registers()[..4], &[7, 8, 9, 0xEE]);
+    assert_eq!(chip8.index(), 0x300);
+}
+
+#[test]
+fn op_fx65_into_vf() {
+    let mut chip8 = Chip8::with_state(&[0xFF, 0x65], regs(&[]), 0x300);
+    chip8.write_memory(0x30F, &[0x42]);
+    chip8.cycle();
+    assert_eq!(chip8.registers()[0xF], 0x42);
+}
+
+#[test]
+fn unknown_opcodes_are_ignored() {
+    for opcode in [0x0123, 0x000F, 0x8128, 0x812F, 0xE1FF, 0xF1FF] {
+        let chip8 = exec(opcode, &[(0x1, 1), (0x2, 2)]);
+        assert_eq!(chip8.pc(), 0x202, "{:04X}", opcode);
+        assert_eq!(chip8.registers(), &regs(&[(0x1, 1), (0x2, 2)]), "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn coverage_records_fetches_loads_and_stores() {
+    // LD I, 0x300; DRW V0, V0, 3; LD [I], V1; LD B, V0; LD V2, [I]
+    let program = [0xA3, 0x00, 0xD0, 0x03, 0xF1, 0x55, 0xF0, 0x33, 0xF2, 0x65];
+    let mut chip8 = Chip8::from_bytes(&program);
+    chip8.set_coverage(Some(Coverage::default()));
+    for _ in 0..5 {
+        chip8.cycle();
+    }
+
+    let coverage = chip8.coverage().unwrap();
+    assert_eq!(coverage.ranges(Access::Execute).collect::<Vec<_>>(), [(0x200, 0x209)]);
+    assert_eq!(coverage.ranges(Access::Read).collect::<Vec<_>>(), [(0x300, 0x302)]);
+    assert_eq!(coverage.ranges(Access::Write).collect::<Vec<_>>(), [(0x300, 0x302)]);
+    assert_eq!(coverage.count(Access::Execute), 10);
+}
+
+#[test]
+fn coverage_is_off_by_default() {
+    let mut chip8 = Chip8::from_bytes(&[0xF0, 0x55]);
+    chip8.cycle();
+    assert!(chip8.coverage().is_none());
+}
@@ -0,0 +1,233 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::capture;
+use crate::database::Database;
+use crate::disasm::Disassembly;
+use crate::expr::Expr;
+use crate::frontend::{AudioSink, DisplaySink, InputSource, TimeSource};
+use crate::gdb::GdbServer;
+use crate::info::RomInfo;
+use crate::quirks::{self, Quirks};
+use crate::runner::{Runner, RunnerOptions};
+use crate::symbols::Symbols;
+
+// The command line every frontend shares, with `F` adding the frontend's
+// own options to `run`. (A doc comment would become the `--help` text.)
+#[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli<F: clap::Args> {
+    #[command(subcommand)]
+    pub command: Option<CliCommand<F>>,
+
+    // Without a subcommand, the emulator runs with these.
+    #[command(flatten)]
+    pub run: RunArgs<F>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum CliCommand<F: clap::Args> {
+    /// Run a ROM (the default)
+    Run(Box<RunArgs<F>>),
+    /// Print what static analysis can tell about a ROM, without running it
+    Info(InfoArgs),
+    /// Disassemble the code reachable from the entry point, labelling jump
+    /// targets, subroutines and sprite data
+    Disasm(DisasmArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DisasmArgs {
+    /// ROM file to disassemble
+    pub rom: PathBuf,
+
+    /// Print the control-flow graph in Graphviz DOT instead
+    #[arg(long)]
+    pub dot: bool,
+
+    /// Symbol file from the assembler, or Octo's symbol export, to name
+    /// labels with
+    #[arg(long)]
+    pub symbols: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct InfoArgs {
+    /// ROM file to analyse
+    pub rom: PathBuf,
+
+    /// A chip-8-database checkout's `database` directory to identify ROMs
+    /// with, instead of the bundled subset
+    #[arg(long)]
+    pub rom_db: Option<PathBuf>,
+
+    /// JSON file of per-ROM settings, keyed by SHA-1, that take precedence
+    /// over the ROM database
+    #[arg(long)]
+    pub rom_overrides: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RunArgs<F: clap::Args> {
+    /// ROM to start with. Without it, the ROM browser opens on startup
+    #[arg(short, long)]
+    pub rom: Option<PathBuf>,
+
+    /// Directory listed by the in-app ROM browser
+    #[arg(long, default_value = "roms")]
+    pub rom_dir: PathBuf,
+
+    /// Instructions executed per 60 Hz frame [default: from the ROM
+    /// database, else 10]
+    #[arg(short, long)]
+    pub cycles_per_frame: Option<u32>,
+
+    /// Interpreter quirks to emulate: modern, vip (COSMAC VIP display wait)
+    /// or legacy (this emulator's old behaviour) [default: from the ROM
+    /// database, else modern]
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(quirks::PROFILES))]
+    pub quirks: Option<String>,
+
+    /// A chip-8-database checkout's `database` directory to identify ROMs
+    /// with, instead of the bundled subset
+    #[arg(long)]
+    pub rom_db: Option<PathBuf>,
+
+    /// JSON file of per-ROM settings, keyed by SHA-1, that take precedence
+    /// over the ROM database
+    #[arg(long)]
+    pub rom_overrides: Option<PathBuf>,
+
+    /// Reload the ROM whenever the file changes on disk
+    #[arg(short, long)]
+    pub watch: bool,
+
+    /// After a watched reload, restore the quick save state (if any) on top
+    /// of the new ROM
+    #[arg(long, requires = "watch")]
+    pub watch_restore: bool,
+
+    /// Directory that screenshots (F12) and recordings (F9) are saved to
+    #[arg(long, default_value = ".")]
+    pub capture_dir: PathBuf,
+
+    /// Output pixels per CHIP-8 pixel for screenshots and recordings
+    #[arg(long, default_value_t = capture::DEFAULT_SCALE, value_parser = capture_scale())]
+    pub capture_scale: u32,
+
+    /// Record which addresses are executed, read and written, and merge
+    /// them into this file on exit. M shows them as a memory heatmap
+    #[arg(long)]
+    pub coverage: Option<PathBuf>,
+
+    /// Count the instructions run per address and subroutine, and write a
+    /// flat profile and call tree to this file on exit. A `.folded` path
+    /// gets collapsed stacks for flamegraph tools instead
+    #[arg(long)]
+    pub profile: Option<PathBuf>,
+
+    /// Symbol file from the assembler, or Octo's symbol export, to name
+    /// subroutines and addresses in the profile and trace with
+    #[arg(long)]
+    pub symbols: Option<PathBuf>,
+
+    /// Write every instruction run to this file, one line each with its
+    /// address, opcode and assembly
+    #[arg(long)]
+    pub trace: Option<PathBuf>,
+
+    /// Show the value of this expression, such as `mem[0x2F0]` or
+    /// `V3 + V4`, whenever it changes. May be repeated
+    #[arg(long = "watch-expr", value_name = "EXPR", value_parser = Expr::parse)]
+    pub watch_exprs: Vec<Expr>,
+
+    /// Serve the GDB remote protocol on this localhost port. The ROM pauses
+    /// whenever a client attaches, until it continues
+    #[arg(long)]
+    pub gdb: Option<u16>,
+
+    #[command(flatten)]
+    pub frontend: F,
+}
+
+impl<F: clap::Args> RunArgs<F> {
+    /// Reads the ROM database and symbol file the options name.
+    pub fn options(&self) -> Result<RunnerOptions, String> {
+        Ok(RunnerOptions {
+            cycles_per_frame: self.cycles_per_frame,
+            quirks: self.quirks.as_deref().and_then(Quirks::from_profile),
+            database: Some(Database::open(self.rom_db.as_deref(), self.rom_overrides.as_deref())?),
+            rom_dir: self.rom_dir.clone(),
+            watch: self.watch,
+            watch_restore: self.watch_restore,
+            capture_dir: self.capture_dir.clone(),
+            capture_scale: self.capture_scale,
+            coverage: self.coverage.clone(),
+            profile: self.profile.clone(),
+            symbols: self.symbols.as_deref().map(Symbols::load).transpose()?,
+            watches: self.watch_exprs.clone(),
+        })
+    }
+
+    /// Loads `--rom`, or opens the ROM browser without one if `browse`, and
+    /// starts the trace and GDB server if asked.
+    pub fn start<D, I, A, T>(&self, runner: &mut Runner<D, I, A, T>, browse: bool) -> Result<(), String>
+    where
+        D: DisplaySink,
+        I: InputSource,
+        A: AudioSink,
+        T: TimeSource,
+    {
+        match &self.rom {
+            Some(path) => runner.load(path)?,
+            None if browse => runner.open_browser()?,
+            None => {},
+        }
+        if let Some(path) = &self.trace {
+            runner.start_trace(path)?;
+        }
+        if let Some(port) = self.gdb {
+            let server = GdbServer::listen(port)?;
+            runner.display_mut().notify(&format!("GDB server listening on {}", server.address()));
+            runner.attach_debugger(Box::new(server));
+        }
+        Ok(())
+    }
+}
+
+fn capture_scale() -> clap::builder::RangedI64ValueParser<u32> {
+    clap::value_parser!(u32).range(1..=capture::MAX_SCALE as i64)
+}
+
+/// Parses the command line and runs the subcommand, handing `run` to the
+/// frontend.
+pub fn main<F: clap::Args>(run: impl FnOnce(RunArgs<F>) -> Result<(), String>) -> Result<(), String> {
+    let cli = Cli::<F>::parse();
+    match cli.command {
+        Some(CliCommand::Run(args)) => run(*args),
+        Some(CliCommand::Info(args)) => info(args),
+        Some(CliCommand::Disasm(args)) => disasm(args),
+        None => run(cli.run),
+    }
+}
+
+fn info(args: InfoArgs) -> Result<(), String> {
+    let rom = std::fs::read(&args.rom).map_err(|e| format!("{}: {}", args.rom.display(), e))?;
+    let database = Database::open(args.rom_db.as_deref(), args.rom_overrides.as_deref())?;
+    print!("{}", RomInfo::analyse(&rom, Some(&database)));
+    Ok(())
+}
+
+fn disasm(args: DisasmArgs) -> Result<(), String> {
+    let rom = std::fs::read(&args.rom).map_err(|e| format!("{}: {}", args.rom.display(), e))?;
+    let mut disassembly = Disassembly::analyse(&rom);
+    if let Some(path) = &args.symbols {
+        disassembly.apply_symbols(&Symbols::load(path)?);
+    }
+    match args.dot {
+        true => print!("{}", disassembly.to_dot()),
+        false => print!("{}", disassembly.listing()),
+    }
+    Ok(())
+}
@@ -0,0 +1,202 @@
+#[cfg(feature = "std")]
+use std::fmt::Write;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::chip8::MEMORY_SIZE;
+
+const WORDS: usize = MEMORY_SIZE / 64;
+
+/// The ways an instruction can touch a memory address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Fetched as part of an instruction
+    Execute,
+    /// Loaded through I, by DXYN or FX65
+    Read,
+    /// Stored through I, by FX33 or FX55
+    Write,
+}
+
+impl Access {
+    pub const ALL: [Access; 3] = [Access::Execute, Access::Read, Access::Write];
+
+    /// The name used in coverage files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Access::Execute => "execute",
+            Access::Read => "read",
+            Access::Write => "write",
+        }
+    }
+}
+
+/// Which of the 4 KiB of memory a program executed, read and wrote, as
+/// recorded by `Chip8::set_coverage`. Coverage from several runs of a ROM
+/// can be combined with `merge`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coverage {
+    executed: [u64; WORDS],
+    read: [u64; WORDS],
+    written: [u64; WORDS],
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self {
+            executed: [0; WORDS],
+            read: [0; WORDS],
+            written: [0; WORDS],
+        }
+    }
+}
+
+impl Coverage {
+    fn bits(&self, access: Access) -> &[u64; WORDS] {
+        match access {
+            Access::Execute => &self.executed,
+            Access::Read => &self.read,
+            Access::Write => &self.written,
+        }
+    }
+
+    fn bits_mut(&mut self, access: Access) -> &mut [u64; WORDS] {
+        match access {
+            Access::Execute => &mut self.executed,
+            Access::Read => &mut self.read,
+            Access::Write => &mut self.written,
+        }
+    }
+
+    /// Marks `len` addresses from `start`. Addresses past the end of memory
+    /// are ignored.
+    pub fn record(&mut self, access: Access, start: usize, len: usize) {
+        let bits = self.bits_mut(access);
+        for address in start..(start + len).min(MEMORY_SIZE) {
+            bits[address / 64] |= 1 << (address % 64);
+        }
+    }
+
+    pub fn contains(&self, access: Access, address: usize) -> bool {
+        address < MEMORY_SIZE && self.bits(access)[address / 64] & (1 << (address % 64)) != 0
+    }
+
+    /// How many addresses were touched this way.
+    pub fn count(&self, access: Access) -> usize {
+        self.bits(access).iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Adds everything `other` recorded.
+    pub fn merge(&mut self, other: &Coverage) {
+        for access in Access::ALL {
+            let theirs = other.bits(access);
+            for (word, their_word) in self.bits_mut(access).iter_mut().zip(theirs) {
+                *word |= their_word;
+            }
+        }
+    }
+
+    /// The colour (0xRRGGBB) of `address` in a memory heatmap: green for
+    /// executed, blue for read and red for written, added together for
+    /// addresses touched more than one way.
+    pub fn color(&self, address: usize) -> u32 {
+        let mut color = 0;
+        if self.contains(Access::Execute, address) {
+            color |= 0x00C000;
+        }
+        if self.contains(Access::Read, address) {
+            color |= 0x0060FF;
+        }
+        if self.contains(Access::Write, address) {
+            color |= 0xFF0000;
+        }
+        color
+    }
+
+    /// Runs of consecutive addresses touched this way, as `(first, last)`.
+    pub fn ranges(&self, access: Access) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut address = 0;
+        core::iter::from_fn(move || {
+            while address < MEMORY_SIZE && !self.contains(access, address) {
+                address += 1;
+            }
+            if address == MEMORY_SIZE {
+                return None;
+            }
+            let first = address;
+            while address < MEMORY_SIZE && self.contains(access, address) {
+                address += 1;
+            }
+            Some((first, address - 1))
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+const FILE_HEADER: &str = "chip8-coverage 1";
+
+#[cfg(feature = "std")]
+impl Coverage {
+    /// The coverage file format: a `chip8-coverage 1` header line, then one
+    /// line per run of addresses, such as `execute 200-2B3` or `write 700`,
+    /// in hex. Blank lines and lines starting with `#` are ignored.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", FILE_HEADER);
+        for access in Access::ALL {
+            for (first, last) in self.ranges(access) {
+                let _ = match first == last {
+                    true => writeln!(text, "{} {:03X}", access.name(), first),
+                    false => writeln!(text, "{} {:03X}-{:03X}", access.name(), first, last),
+                };
+            }
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+        match lines.next() {
+            Some((_, FILE_HEADER)) => {},
+            _ => return Err(format!("line 1: expected `{}`", FILE_HEADER)),
+        }
+
+        let mut coverage = Coverage::default();
+        for (number, line) in lines.filter(|(_, line)| !line.is_empty() && !line.starts_with('#')) {
+            let error = |message: &str| format!("line {}: {}", number, message);
+            let (name, range) = line.split_once(' ').ok_or_else(|| error("expected an access and a range"))?;
+            let access = Access::ALL
+                .into_iter()
+                .find(|a| a.name() == name)
+                .ok_or_else(|| error(&format!("unknown access `{}`", name)))?;
+            let range = range.trim();
+            let (first, last) = range.split_once('-').unwrap_or((range, range));
+            let parse = |hex: &str| {
+                usize::from_str_radix(hex, 16)
+                    .ok()
+                    .filter(|&a| a < MEMORY_SIZE)
+                    .ok_or_else(|| error(&format!("bad address `{}`", hex)))
+            };
+            let (first, last) = (parse(first)?, parse(last)?);
+            if last < first {
+                return Err(error("range ends before it starts"));
+            }
+            coverage.record(access, first, last - first + 1);
+        }
+        Ok(coverage)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_text(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Writes this coverage merged with whatever `path` already holds, so
+    /// repeated runs accumulate.
+    pub fn save_merged(&self, path: &Path) -> Result<(), String> {
+        let mut merged = *self;
+        if path.exists() {
+            merged.merge(&Self::load(path)?);
+        }
+        std::fs::write(path, merged.to_text()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::chip8::{Chip8, MEMORY_SIZE, NUM_REGISTERS};
+use crate::debugger::{DebugServer, Debugger, StopReason};
+use crate::expr::{self, Expr};
+use crate::frontend::Command;
+use crate::symbols::Symbols;
+
+// The machine is the only thread clients see.
+const THREAD_ID: u64 = 1;
+
+// `variablesReference`s of the scopes every stack frame shares.
+const REGISTERS: u64 = 1;
+const TIMERS: u64 = 2;
+const STACK: u64 = 3;
+
+fn hex(address: usize) -> String {
+    format!("0x{:03X}", address)
+}
+
+// `0x2A4` or `676`, as memory references and variable values are written.
+fn parse_number(text: &str) -> Option<usize> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => usize::from_str_radix(digits, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+// A breakpoint's `condition`, if it has one.
+fn condition(breakpoint: &Value) -> Result<Option<Expr>, String> {
+    let condition = breakpoint["condition"].as_str().filter(|c| !c.trim().is_empty());
+    condition.map(Expr::parse).transpose().map_err(|e| format!("bad condition: {}", e))
+}
+
+// I and PC also point into the memory view.
+fn pointer(name: &str, address: usize) -> Value {
+    json!({ "name": name, "value": hex(address), "variablesReference": 0, "memoryReference": hex(address) })
+}
+
+/// One client's side of the Debug Adapter Protocol, without the transport:
+/// each message from the client goes into `handle`, and `take_messages`
+/// hands back the responses and events to send.
+#[derive(Debug, Default)]
+pub struct DapSession {
+    seq: u64,
+    out: Vec<Value>,
+    symbols: Symbols,
+    // Where the source files the symbols name are relative to.
+    source_dir: PathBuf,
+    stop_on_entry: bool,
+    // Breakpoint addresses and conditions per source path, and those set
+    // by address.
+    source_breakpoints: BTreeMap<String, Vec<(u16, Option<Expr>)>>,
+    instruction_breakpoints: Vec<(u16, Option<Expr>)>,
+    terminated: bool,
+}
+
+impl DapSession {
+    /// Whether the client ended the session, which quits the emulator.
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Responses and events queued since the last call, in order.
+    pub fn take_messages(&mut self) -> Vec<Value> {
+        std::mem::take(&mut self.out)
+    }
+
+    fn send(&mut self, mut message: Value) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        self.out.push(message);
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    /// Answers one request from the client.
+    pub fn handle(&mut self, request: &Value, chip8: &mut Chip8, debugger: &mut Debugger, commands: &mut Vec<Command>) {
+        let command = request["command"].as_str().unwrap_or_default();
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+        });
+        match self.request(command, &request["arguments"], chip8, debugger, commands) {
+            Ok(body) => {
+                response["success"] = json!(true);
+                if !body.is_null() {
+                    response["body"] = body;
+                }
+            },
+            Err(message) => {
+                response["success"] = json!(false);
+                response["message"] = json!(message);
+            },
+        }
+        self.send(response);
+
+        match command {
+            "initialize" => self.event("initialized", json!({})),
+            "configurationDone" if self.stop_on_entry => {
+                self.event("stopped", json!({ "reason": "entry", "threadId": THREAD_ID, "allThreadsStopped": true }))
+            },
+            "terminate" => self.event("terminated", json!({})),
+            _ => {},
+        }
+    }
+
+    /// Sends a `stopped` event if the machine paused since the last call.
+    pub fn poll_stop(&mut self, debugger: &mut Debugger) {
+        let reason = match debugger.take_stop() {
+            Some(StopReason::Step) => "step",
+            Some(StopReason::Breakpoint) => "breakpoint",
+            Some(StopReason::Watchpoint) => "data breakpoint",
+            Some(StopReason::Interrupt) => "pause",
+            None => return,
+        };
+        self.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }));
+    }
+
+    fn request(
+        &mut self,
+        command: &str,
+        args: &Value,
+        chip8: &mut Chip8,
+        debugger: &mut Debugger,
+        commands: &mut Vec<Command>,
+    ) -> Result<Value, String> {
+        let body = match command {
+            "initialize" => json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsSetVariable": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsDataBreakpoints": true,
+                "supportsTerminateRequest": true,
+            }),
+            "launch" => {
+                self.launch(args, debugger, commands)?;
+                Value::Null
+            },
+            "setBreakpoints" => self.set_breakpoints(args, debugger)?,
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args, debugger),
+            "setExceptionBreakpoints" => json!({ "breakpoints": [] }),
+            // Any expression can be watched, registers included.
+            "dataBreakpointInfo" => {
+                let name = args["name"].as_str().unwrap_or_default();
+                match Expr::parse(name) {
+                    Ok(expr) => json!({ "dataId": expr.source(), "description": expr.source(), "accessTypes": ["write"] }),
+                    Err(e) => json!({ "dataId": null, "description": e.to_string() }),
+                }
+            },
+            "setDataBreakpoints" => {
+                debugger.clear_watchpoints();
+                let mut breakpoints = Vec::new();
+                for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+                    let expr = Expr::parse(breakpoint["dataId"].as_str().unwrap_or_default()).map_err(|e| e.to_string());
+                    match expr.and_then(|expr| Ok((expr, condition(breakpoint)?))) {
+                        Ok((expr, condition)) => {
+                            debugger.add_watchpoint(chip8, expr, condition);
+                            breakpoints.push(json!({ "verified": true }));
+                        },
+                        Err(message) => breakpoints.push(json!({ "verified": false, "message": message })),
+                    }
+                }
+                json!({ "breakpoints": breakpoints })
+            },
+            "configurationDone" => {
+                if !self.stop_on_entry {
+                    debugger.resume(chip8);
+                }
+                Value::Null
+            },
+            "threads" => json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] }),
+            "stackTrace" => {
+                // The stack holds return addresses; the calls are just before.
+                let mut frames = vec![self.frame(0, chip8.pc())];
+                for (depth, &ret) in chip8.stack().iter().rev().enumerate() {
+                    frames.push(self.frame(depth + 1, ret.saturating_sub(2)));
+                }
+                json!({ "stackFrames": frames, "totalFrames": frames.len() })
+            },
+            "scopes" => json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK, "expensive": false },
+            ] }),
+            "variables" => json!({ "variables": variables(args["variablesReference"].as_u64(), chip8) }),
+            "setVariable" => {
+                let name = args["name"].as_str().unwrap_or_default();
+                let value = args["value"].as_str().and_then(parse_number).ok_or("expected a number")?;
+                json!({ "value": set_variable(chip8, name, value)? })
+            },
+            "continue" => {
+                debugger.resume(chip8);
+                json!({ "allThreadsContinued": true })
+            },
+            "next" => {
+                let pc = chip8.pc();
+                match chip8.memory()[pc] >> 4 {
+                    0x2 => debugger.run_to(chip8, pc + 2, chip8.sp()),
+                    _ => debugger.step(chip8),
+                }
+                Value::Null
+            },
+            "stepIn" => {
+                debugger.step(chip8);
+                Value::Null
+            },
+            "stepOut" => {
+                let &ret = chip8.stack().last().ok_or("not in a subroutine")?;
+                debugger.run_to(chip8, ret, chip8.sp() - 1);
+                Value::Null
+            },
+            "pause" => {
+                debugger.pause(StopReason::Interrupt);
+                Value::Null
+            },
+            "evaluate" => {
+                let expression = args["expression"].as_str().unwrap_or_default();
+                let expr = Expr::parse(expression).map_err(|e| e.to_string())?;
+                json!({ "result": expr::format_value(expr.eval(chip8)?), "variablesReference": 0 })
+            },
+            "readMemory" => {
+                let start = memory_address(args)?;
+                let count = args["count"].as_u64().unwrap_or(0).try_into().unwrap_or(usize::MAX);
+                let end = start.saturating_add(count);
+                let readable = &chip8.memory()[start.min(MEMORY_SIZE)..end.min(MEMORY_SIZE)];
+                json!({
+                    "address": hex(start),
+                    "data": BASE64.encode(readable),
+                    "unreadableBytes": count - readable.len(),
+                })
+            },
+            "writeMemory" => {
+                let start = memory_address(args)?;
+                let data = args["data"].as_str().unwrap_or_default();
+                let bytes = BASE64.decode(data).map_err(|e| format!("bad data: {}", e))?;
+                if start.saturating_add(bytes.len()) > MEMORY_SIZE {
+                    return Err(format!("{} bytes at {} run past the end of memory", bytes.len(), hex(start)));
+                }
+                chip8.write_memory(start, &bytes);
+                json!({ "bytesWritten": bytes.len() })
+            },
+            "disconnect" | "terminate" => {
+                self.terminated = true;
+                commands.push(Command::Quit);
+                Value::Null
+            },
+            _ => return Err(format!("unsupported request `{}`", command)),
+        };
+        Ok(body)
+    }
+
+    fn launch(&mut self, args: &Value, debugger: &mut Debugger, commands: &mut Vec<Command>) -> Result<(), String> {
+        let program = args["program"].as_str().ok_or("launch needs a `program`")?;
+        let program = PathBuf::from(program);
+        std::fs::metadata(&program).map_err(|e| format!("{}: {}", program.display(), e))?;
+        if let Some(path) = args["symbols"].as_str().map(Path::new) {
+            self.symbols = Symbols::load(path)?;
+            self.source_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        }
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        commands.push(Command::Load(program));
+        // Nothing runs until the client has set its breakpoints, and this
+        // pause isn't a stop to report.
+        debugger.pause(StopReason::Interrupt);
+        debugger.take_stop();
+        Ok(())
+    }
+
+    fn set_breakpoints(&mut self, args: &Value, debugger: &mut Debugger) -> Result<Value, String> {
+        let path = args["source"]["path"].as_str().ok_or("setBreakpoints needs a source path")?;
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as u32;
+            match (self.symbols.line_address(path, line), condition(breakpoint)) {
+                (Some((address, source)), Ok(condition)) => {
+                    addresses.push((address, condition));
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": source.line,
+                        "instructionReference": hex(address as usize),
+                    }));
+                },
+                (None, _) => breakpoints.push(json!({ "verified": false, "line": line, "message": "no code at or after this line" })),
+                (_, Err(message)) => breakpoints.push(json!({ "verified": false, "line": line, "message": message })),
+            }
+        }
+        self.source_breakpoints.insert(path.to_string(), addresses);
+        self.sync_breakpoints(debugger);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value, debugger: &mut Debugger) -> Value {
+        let mut breakpoints = Vec::new();
+        self.instruction_breakpoints.clear();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let reference = breakpoint["instructionReference"].as_str().and_then(parse_number);
+            let address = reference.and_then(|a| offset_address(a, breakpoint["offset"].as_i64().unwrap_or(0)));
+            match (address, condition(breakpoint)) {
+                (Some(address), Ok(condition)) if address < MEMORY_SIZE => {
+                    self.instruction_breakpoints.push((address as u16, condition));
+                    breakpoints.push(json!({ "verified": true, "instructionReference": hex(address) }));
+                },
+                (_, Err(message)) => breakpoints.push(json!({ "verified": false, "message": message })),
+                _ => breakpoints.push(json!({ "verified": false, "message": "not an address in memory" })),
+            }
+        }
+        self.sync_breakpoints(debugger);
+        json!({ "breakpoints": breakpoints })
+    }
+
+    // The client sets breakpoints a source at a time, so the debugger's are
+    // rebuilt from all of them.
+    fn sync_breakpoints(&self, debugger: &mut Debugger) {
+        debugger.clear_breakpoints();
+        for (address, condition) in self.source_breakpoints.values().flatten().chain(&self.instruction_breakpoints) {
+            match condition {
+                Some(condition) => debugger.set_conditional_breakpoint(*address, condition.clone()),
+                None => debugger.set_breakpoint(*address),
+            };
+        }
+    }
+
+    fn frame(&self, id: usize, address: usize) -> Value {
+        let name = self.symbols.symbolize(address as u16).unwrap_or_else(|| hex(address));
+        let mut frame = json!({
+            "id": id,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": hex(address),
+        });
+        if let Some(source) = self.symbols.line(address as u16) {
+            let path = self.source_dir.join(&source.file);
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            frame["line"] = json!(source.line);
+            frame["column"] = json!(1);
+            frame["source"] = json!({ "name": name, "path": path.to_string_lossy() });
+        }
+        frame
+    }
+}
+
+fn variables(reference: Option<u64>, chip8: &Chip8) -> Vec<Value> {
+    match reference {
+        Some(REGISTERS) => {
+            let mut variables: Vec<Value> = (0..NUM_REGISTERS)
+                .map(|x| variable(&format!("V{:X}", x), format!("0x{:02X}", chip8.registers()[x])))
+                .collect();
+            variables.push(pointer("I", chip8.index() as usize));
+            variables.push(pointer("PC", chip8.pc()));
+            variables.push(variable("SP", chip8.sp().to_string()));
+            variables
+        },
+        Some(TIMERS) => vec![
+            variable("DT", chip8.delay_timer().to_string()),
+            variable("ST", chip8.sound_timer().to_string()),
+        ],
+        // Return addresses, outermost first.
+        Some(STACK) => chip8.stack().iter().enumerate().map(|(i, &ret)| pointer(&format!("[{}]", i), ret)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Sets a register or timer from the variables view, returning its new value
+// as the view shows it.
+fn set_variable(chip8: &mut Chip8, name: &str, value: usize) -> Result<String, String> {
+    let byte = || u8::try_from(value).map_err(|_| format!("{} doesn't fit in {}", value, name));
+    let word = || u16::try_from(value).ok().filter(|&v| (v as usize) < MEMORY_SIZE).ok_or(format!("{} isn't an address", value));
+    match name {
+        "I" => chip8.set_index(word()?),
+        "PC" => chip8.set_pc(word()? as usize),
+        "DT" => chip8.set_delay_timer(byte()?),
+        "ST" => chip8.set_sound_timer(byte()?),
+        _ => {
+            let x = name
+                .strip_prefix('V')
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+                .ok_or_else(|| format!("{} can't be changed", name))?;
+            chip8.set_register(x, byte()?);
+            return Ok(format!("0x{:02X}", value));
+        },
+    }
+    Ok(match name {
+        "I" | "PC" => hex(value),
+        _ => value.to_string(),
+    })
+}
+
+// `memoryReference` plus `offset`, as `readMemory` and `writeMemory` take.
+fn memory_address(args: &Value) -> Result<usize, String> {
+    let reference = args["memoryReference"].as_str().and_then(parse_number).ok_or("bad memory reference")?;
+    offset_address(reference, args["offset"].as_i64().unwrap_or(0)).ok_or("address out of range".to_string())
+}
+
+// `None` when the offset takes the address below 0 or past `usize::MAX`.
+fn offset_address(reference: usize, offset: i64) -> Option<usize> {
+    match usize::try_from(offset) {
+        Ok(offset) => reference.checked_add(offset),
+        Err(_) => reference.checked_sub(offset.unsigned_abs().try_into().ok()?),
+    }
+}
+
+/// Reads one `Content-Length` framed message. `None` at the end of the
+/// stream.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(io::Error::from)
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+/// A Debug Adapter Protocol server for editors, usually over stdin and
+/// stdout. The client's `launch` request names the ROM, and optionally a
+/// symbol file (see `Symbols`) to map addresses to source lines with.
+pub struct DapServer<W> {
+    messages: Receiver<Value>,
+    output: W,
+    session: DapSession,
+}
+
+impl<W: Write> DapServer<W> {
+    /// Serves the client on the other ends of `input` and `output`. Reads
+    /// block, so `input` is read on a thread of its own.
+    pub fn new(input: impl Read + Send + 'static, output: W) -> Self {
+        let (sender, messages) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(input);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            messages,
+            output,
+            session: DapSession::default(),
+        }
+    }
+}
+
+impl<W: Write> DebugServer for DapServer<W> {
+    fn poll(&mut self, chip8: &mut Chip8, debugger: &mut Debugger, commands: &mut Vec<Command>) -> Result<(), String> {
+        loop {
+            match self.messages.try_recv() {
+                Ok(message) => self.session.handle(&message, chip8, debugger, commands),
+                Err(TryRecvError::Empty) => break,
+                // The client went away without saying goodbye.
+                Err(TryRecvError::Disconnected) => {
+                    if !self.session.is_terminated() {
+                        self.session.terminated = true;
+                        commands.push(Command::Quit);
+                    }
+                    break;
+                },
+            }
+        }
+        self.session.poll_stop(debugger);
+
+        for message in self.session.take_messages() {
+            write_message(&mut self.output, &message).map_err(|e| e.to_string())?;
+        }
+        self.output.flush().map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::frontend::{Keymap, Palette};
+use crate::quirks::Quirks;
+
+// A trimmed copy of the chip-8-database (https://github.com/chip-8/chip-8-database)
+// covering the ROMs in roms/. `Database::load_dir` reads the full one.
+const BUNDLED_HASHES: &str = include_str!("../database/sha1-hashes.json");
+const BUNDLED_PROGRAMS: &str = include_str!("../database/programs.json");
+const BUNDLED_PLATFORMS: &str = include_str!("../database/platforms.json");
+
+/// Platforms this emulator runs natively. ROMs for other platforms still run
+/// with the quirks and speed from their entry, but may misbehave.
+pub const SUPPORTED_PLATFORMS: [&str; 3] = ["originalChip8", "hybridVIP", "modernChip8"];
+
+/// Lower-case hex, the form the database keys ROMs by.
+pub fn sha1_hex(sha1: &[u8; 20]) -> String {
+    sha1.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkSet {
+    vblank: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Platform {
+    id: String,
+    default_tickrate: Option<u32>,
+    #[serde(default)]
+    quirks: QuirkSet,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    title: Option<String>,
+    platforms: Option<Vec<String>>,
+    quirky_platforms: Option<HashMap<String, QuirkSet>>,
+    tickrate: Option<u32>,
+    colors: Option<Colors>,
+    keys: Option<HashMap<String, usize>>,
+}
+
+impl Rom {
+    // Fields set in `other` replace ours.
+    fn merge(&mut self, other: Rom) {
+        self.title = other.title.or(self.title.take());
+        self.platforms = other.platforms.or(self.platforms.take());
+        self.quirky_platforms = other.quirky_platforms.or(self.quirky_platforms.take());
+        self.tickrate = other.tickrate.or(self.tickrate);
+        self.colors = other.colors.or(self.colors.take());
+        self.keys = other.keys.or(self.keys.take());
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: HashMap<String, Rom>,
+}
+
+/// Settings for one ROM, from its database entry and its platform's
+/// defaults. `None` means the entry doesn't say.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RomProfile {
+    pub title: String,
+    /// The first platform the entry lists, as a chip-8-database id.
+    pub platform: Option<String>,
+    pub quirks: Option<Quirks>,
+    pub cycles_per_frame: Option<u32>,
+    pub palette: Option<Palette>,
+    pub keymap: Option<Keymap>,
+}
+
+fn parse_color(color: &str) -> Option<u32> {
+    let hex = color.strip_prefix('#')?;
+    match hex.len() {
+        6 => u32::from_str_radix(hex, 16).ok(),
+        // #rgb is short for #rrggbb.
+        3 => {
+            let c = u32::from_str_radix(hex, 16).ok()?;
+            Some(((c >> 8) & 0xF) * 0x110000 + ((c >> 4) & 0xF) * 0x1100 + (c & 0xF) * 0x11)
+        },
+        _ => None,
+    }
+}
+
+fn keymap(keys: &HashMap<String, usize>) -> Keymap {
+    let key = |name: &str| keys.get(name).copied().filter(|&k| k < 16);
+    Keymap {
+        up: key("up"),
+        down: key("down"),
+        left: key("left"),
+        right: key("right"),
+        a: key("a"),
+        b: key("b"),
+    }
+}
+
+/// ROM settings keyed by SHA-1, in the chip-8-database format.
+#[derive(Debug, Clone, Default)]
+pub struct Database {
+    roms: HashMap<String, Rom>,
+    platforms: Vec<Platform>,
+}
+
+impl Database {
+    /// The database compiled into the emulator.
+    pub fn bundled() -> Self {
+        Self::from_json(BUNDLED_HASHES, BUNDLED_PROGRAMS, BUNDLED_PLATFORMS)
+            .expect("bundled ROM database is valid")
+    }
+
+    /// The database at `dir` (or the bundled one) with the overrides from
+    /// `overrides` applied, as the frontends' `--rom-db` and
+    /// `--rom-overrides` options ask for.
+    pub fn open(dir: Option<&Path>, overrides: Option<&Path>) -> Result<Self, String> {
+        let mut database = match dir {
+            Some(dir) => Self::load_dir(dir)?,
+            None => Self::bundled(),
+        };
+        if let Some(path) = overrides {
+            database.load_overrides(path)?;
+        }
+        Ok(database)
+    }
+
+    /// Parses the contents of `sha1-hashes.json`, `programs.json` and
+    /// `platforms.json`.
+    pub fn from_json(hashes: &str, programs: &str, platforms: &str) -> Result<Self, String> {
+        let hashes: HashMap<String, usize> =
+            serde_json::from_str(hashes).map_err(|e| format!("sha1-hashes.json: {}", e))?;
+        let programs: Vec<Program> =
+            serde_json::from_str(programs).map_err(|e| format!("programs.json: {}", e))?;
+        let platforms: Vec<Platform> =
+            serde_json::from_str(platforms).map_err(|e| format!("platforms.json: {}", e))?;
+
+        let mut roms = HashMap::new();
+        for (hash, index) in hashes {
+            let Some(program) = programs.get(index) else {
+                return Err(format!("sha1-hashes.json: {} points past the end of programs.json", hash));
+            };
+            let mut rom = program.roms.get(&hash).cloned().unwrap_or_default();
+            rom.title = rom.title.or_else(|| Some(program.title.clone()));
+            roms.insert(hash.to_lowercase(), rom);
+        }
+        Ok(Self { roms, platforms })
+    }
+
+    /// Reads a checkout of the database's `database/` directory.
+    pub fn load_dir(dir: &Path) -> Result<Self, String> {
+        let read = |name: &str| {
+            let path = dir.join(name);
+            fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))
+        };
+        Self::from_json(&read("sha1-hashes.json")?, &read("programs.json")?, &read("platforms.json")?)
+    }
+
+    /// Applies a local override file: a JSON object from SHA-1 to a ROM
+    /// entry as in `programs.json`, plus an optional `title`. Fields given
+    /// there replace those of the existing entry; unknown hashes are added.
+    pub fn add_overrides(&mut self, json: &str) -> Result<(), String> {
+        let overrides: HashMap<String, Rom> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        for (hash, rom) in overrides {
+            self.roms.entry(hash.to_lowercase()).or_default().merge(rom);
+        }
+        Ok(())
+    }
+
+    pub fn load_overrides(&mut self, path: &Path) -> Result<(), String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.add_overrides(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn lookup(&self, sha1: &[u8; 20]) -> Option<RomProfile> {
+        let rom = self.roms.get(&sha1_hex(sha1))?;
+        let platform_id = rom.platforms.as_ref().and_then(|p| p.first());
+        let platform = platform_id.and_then(|id| self.platforms.iter().find(|p| &p.id == id));
+
+        // The entry's quirks for its platform win over the platform's own.
+        let vblank = platform_id
+            .and_then(|id| rom.quirky_platforms.as_ref()?.get(id)?.vblank)
+            .or(platform.and_then(|p| p.quirks.vblank));
+        let quirks = vblank.map(|display_wait| Quirks {
+            display_wait,
+            ..Quirks::default()
+        });
+
+        let pixels = rom.colors.as_ref().map(|c| &c.pixels[..]).unwrap_or_default();
+        let palette = match pixels {
+            [background, foreground, ..] => parse_color(background)
+                .zip(parse_color(foreground))
+                .map(|(background, foreground)| Palette { background, foreground }),
+            _ => None,
+        };
+
+        Some(RomProfile {
+            title: rom.title.clone().unwrap_or_default(),
+            platform: platform_id.cloned(),
+            quirks,
+            cycles_per_frame: rom.tickrate.or(platform.and_then(|p| p.default_tickrate)),
+            palette,
+            keymap: rom.keys.as_ref().map(keymap),
+        })
+    }
+}
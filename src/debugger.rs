@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::chip8::Chip8;
+use crate::expr::{self, Expr};
+use crate::frontend::Command;
+
+/// Why the debugger last paused the machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// A single step finished
+    Step,
+    /// The PC reached a breakpoint
+    Breakpoint,
+    /// A watched expression changed
+    Watchpoint,
+    /// The client asked to pause, or just attached
+    Interrupt,
+}
+
+// Stops when `expr` changes value, if `condition` holds then.
+#[derive(Debug, Clone)]
+struct Watchpoint {
+    expr: Expr,
+    condition: Option<Expr>,
+    value: Result<i64, String>,
+}
+
+/// Execution control shared by the remote debugger protocols: breakpoints,
+/// watchpoints, pausing and single steps. The `Runner` asks `should_stop`
+/// before every instruction and stops emulating while `is_paused`.
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    // Breakpoint addresses, with the condition each stops on, if any.
+    breakpoints: BTreeMap<u16, Option<Expr>>,
+    watchpoints: Vec<Watchpoint>,
+    paused: bool,
+    // Set when the machine pauses, until the server reports it.
+    stop: Option<StopReason>,
+    // The address execution resumed from, whose breakpoint mustn't stop it
+    // again before that instruction has run.
+    resumed_at: Option<usize>,
+    // Where a step over or out ends: an address, reached with at most this
+    // many return addresses on the stack.
+    target: Option<(usize, usize)>,
+}
+
+/// A debugger protocol the `Runner` polls once per step, such as
+/// `gdb::GdbServer` or `dap::DapServer`.
+pub trait DebugServer {
+    /// Handles whatever the client sent, and reports a stop if the machine
+    /// paused since the last poll. Anything beyond the machine's state, such
+    /// as loading a ROM or quitting, goes through `commands`. Errors are
+    /// fatal to the server.
+    fn poll(&mut self, chip8: &mut Chip8, debugger: &mut Debugger, commands: &mut Vec<Command>) -> Result<(), String>;
+}
+
+impl Debugger {
+    /// Returns false if there already was a breakpoint at `address`, whose
+    /// condition is dropped.
+    pub fn set_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address, None).is_none()
+    }
+
+    /// A breakpoint that only stops when `condition` is true, such as
+    /// `V3 == 0x10`. Returns false if there already was one at `address`.
+    pub fn set_conditional_breakpoint(&mut self, address: u16, condition: Expr) -> bool {
+        self.breakpoints.insert(address, Some(condition)).is_none()
+    }
+
+    /// Returns false if there was no breakpoint at `address`.
+    pub fn clear_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    /// Stops before the next instruction whenever the value of `expr`, such
+    /// as `mem[0x2F0]`, has changed, if `condition` is true then.
+    pub fn add_watchpoint(&mut self, chip8: &Chip8, expr: Expr, condition: Option<Expr>) {
+        let value = expr.eval(chip8);
+        self.watchpoints.push(Watchpoint { expr, condition, value });
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    // Takes the watched values as they are now, so that changes made while
+    // paused don't count.
+    fn refresh_watchpoints(&mut self, chip8: &Chip8) {
+        for watchpoint in &mut self.watchpoints {
+            watchpoint.value = watchpoint.expr.eval(chip8);
+        }
+    }
+
+    // Whether any watched value changed where its condition holds.
+    fn watchpoint_hit(&mut self, chip8: &Chip8) -> bool {
+        let mut hit = false;
+        for watchpoint in &mut self.watchpoints {
+            let value = watchpoint.expr.eval(chip8);
+            if value != watchpoint.value {
+                watchpoint.value = value;
+                hit |= watchpoint.condition.as_ref().is_none_or(|c| c.is_true(chip8));
+            }
+        }
+        hit
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self, reason: StopReason) {
+        self.paused = true;
+        self.stop = Some(reason);
+        self.target = None;
+    }
+
+    /// Lets the machine run from where it is, past any breakpoint there.
+    pub fn resume(&mut self, chip8: &Chip8) {
+        self.paused = false;
+        self.stop = None;
+        self.resumed_at = Some(chip8.pc());
+        self.target = None;
+        self.refresh_watchpoints(chip8);
+    }
+
+    /// Resumes until the PC reaches `address` with at most `depth` return
+    /// addresses on the stack, to step over a call or out of a subroutine.
+    /// A breakpoint on the way stops it first.
+    pub fn run_to(&mut self, chip8: &Chip8, address: usize, depth: usize) {
+        self.resume(chip8);
+        self.target = Some((address, depth));
+    }
+
+    /// The reason for a pause that hasn't been reported yet.
+    pub fn take_stop(&mut self) -> Option<StopReason> {
+        self.stop.take()
+    }
+
+    /// Runs exactly one instruction and pauses. A draw stalled by the
+    /// display wait quirk ends its frame first, so the step makes progress.
+    pub fn step(&mut self, chip8: &mut Chip8) {
+        if chip8.is_waiting_for_vblank() {
+            chip8.tick_timers();
+        }
+        chip8.cycle();
+        self.refresh_watchpoints(chip8);
+        self.pause(StopReason::Step);
+    }
+
+    /// Called before each instruction: pauses and returns true at a
+    /// breakpoint whose condition holds, after a watched value changed, or
+    /// where `run_to` was headed. A condition that fails to evaluate stops.
+    pub fn should_stop(&mut self, chip8: &Chip8) -> bool {
+        let pc = chip8.pc();
+        if self.resumed_at.take() == Some(pc) {
+            return false;
+        }
+        if let Some(condition) = self.breakpoints.get(&(pc as u16)) {
+            if condition.as_ref().is_none_or(|c| c.is_true(chip8)) {
+                self.pause(StopReason::Breakpoint);
+                return true;
+            }
+        }
+        if self.watchpoint_hit(chip8) {
+            self.pause(StopReason::Watchpoint);
+            return true;
+        }
+        if self.target.is_some_and(|(address, depth)| pc == address && chip8.sp() <= depth) {
+            self.pause(StopReason::Step);
+            return true;
+        }
+        false
+    }
+}
+
+/// An expression shown while the machine runs, re-evaluated every frame
+/// and after every step.
+#[derive(Debug, Clone)]
+pub struct Watch {
+    expr: Expr,
+    value: Option<Result<i64, String>>,
+}
+
+impl Watch {
+    pub fn new(expr: Expr) -> Self {
+        Self { expr, value: None }
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    /// The last value, or why it couldn't be evaluated; `None` until the
+    /// first `update`.
+    pub fn value(&self) -> Option<&Result<i64, String>> {
+        self.value.as_ref()
+    }
+
+    /// Re-evaluates the expression, returning whether its value changed.
+    pub fn update(&mut self, chip8: &Chip8) -> bool {
+        let value = Some(self.expr.eval(chip8));
+        let changed = value != self.value;
+        self.value = value;
+        changed
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(Ok(value)) => write!(f, "{} = {}", self.expr, expr::format_value(*value)),
+            Some(Err(e)) => write!(f, "{}: {}", self.expr, e),
+            None => write!(f, "{} = ?", self.expr),
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use crate::chip8::{MAX_ROM_SIZE, MEMORY_SIZE, PROGRAM_MEM_START};
+use crate::quirks::Quirks;
+
+/// Machine variants the detector can tell apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    /// The platform's id in the chip-8-database.
+    pub fn database_id(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "originalChip8",
+            Platform::SuperChip => "superchip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    pub fn name(self) -> &'static str {
+        match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
+}
+
+/// Instruction patterns whose behaviour depends on a quirk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QuirkHints {
+    /// 8XY4-8XYE with VF as the destination, so the flag order matters
+    pub vf_destination: bool,
+    /// FX0A, which waits for a release or a press depending on the quirk
+    pub waits_for_key: bool,
+    /// DXYN without ever setting the delay timer: the game likely relied
+    /// on the COSMAC VIP's display wait to run at the right speed
+    pub unpaced_drawing: bool,
+    /// 8XY6/8XYE with X != Y, which SUPER-CHIP shifts in place
+    pub shifts_other_register: bool,
+    /// BNNN, which SUPER-CHIP reads as BXNN
+    pub jumps_with_offset: bool,
+}
+
+/// A guess at which machine a ROM was written for, from the opcodes in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection {
+    pub platform: Platform,
+    pub confidence: Confidence,
+    /// Occurrences of opcodes only SUPER-CHIP (or XO-CHIP) has
+    pub superchip_opcodes: u32,
+    /// Occurrences of opcodes only XO-CHIP has
+    pub xochip_opcodes: u32,
+    pub hints: QuirkHints,
+}
+
+impl Detection {
+    /// Quirks to run the ROM with, for the ones this emulator implements.
+    pub fn quirks(&self) -> Quirks {
+        Quirks {
+            display_wait: self.platform == Platform::Chip8 && self.hints.unpaced_drawing,
+            ..Quirks::default()
+        }
+    }
+}
+
+/// The opcode's pattern as written in instruction tables, such as `DXYN` or
+/// `FX33`, across CHIP-8, SUPER-CHIP and XO-CHIP. `None` if no platform
+/// assigns it.
+pub fn opcode_pattern(opcode: u16) -> Option<&'static str> {
+    let n = opcode & 0xF;
+    let pattern = match (opcode >> 12, opcode & 0xFF) {
+        (0x0, _) => match opcode {
+            0x00E0 => "00E0",
+            0x00EE => "00EE",
+            0x00FB => "00FB",
+            0x00FC => "00FC",
+            0x00FD => "00FD",
+            0x00FE => "00FE",
+            0x00FF => "00FF",
+            0x00C1..=0x00CF => "00CN",
+            0x00D1..=0x00DF => "00DN",
+            // Calls into COSMAC VIP machine code.
+            _ => "0NNN",
+        },
+        (0x1, _) => "1NNN",
+        (0x2, _) => "2NNN",
+        (0x3, _) => "3XNN",
+        (0x4, _) => "4XNN",
+        (0x5, _) => match n {
+            0x0 => "5XY0",
+            0x2 => "5XY2",
+            0x3 => "5XY3",
+            _ => return None,
+        },
+        (0x6, _) => "6XNN",
+        (0x7, _) => "7XNN",
+        (0x8, _) => match n {
+            0x0 => "8XY0",
+            0x1 => "8XY1",
+            0x2 => "8XY2",
+            0x3 => "8XY3",
+            0x4 => "8XY4",
+            0x5 => "8XY5",
+            0x6 => "8XY6",
+            0x7 => "8XY7",
+            0xE => "8XYE",
+            _ => return None,
+        },
+        (0x9, _) if n == 0 => "9XY0",
+        (0xA, _) => "ANNN",
+        (0xB, _) => "BNNN",
+        (0xC, _) => "CXNN",
+        (0xD, _) if n == 0 => "DXY0",
+        (0xD, _) => "DXYN",
+        (0xE, 0x9E) => "EX9E",
+        (0xE, 0xA1) => "EXA1",
+        (0xF, _) if opcode == 0xF000 => "F000",
+        (0xF, _) if opcode == 0xF002 => "F002",
+        (0xF, 0x01) => "FN01",
+        (0xF, 0x07) => "FX07",
+        (0xF, 0x0A) => "FX0A",
+        (0xF, 0x15) => "FX15",
+        (0xF, 0x18) => "FX18",
+        (0xF, 0x1E) => "FX1E",
+        (0xF, 0x29) => "FX29",
+        (0xF, 0x30) => "FX30",
+        (0xF, 0x33) => "FX33",
+        (0xF, 0x3A) => "FX3A",
+        (0xF, 0x55) => "FX55",
+        (0xF, 0x65) => "FX65",
+        (0xF, 0x75) => "FX75",
+        (0xF, 0x85) => "FX85",
+        _ => return None,
+    };
+    Some(pattern)
+}
+
+// Distinct kinds of evidence, so a single opcode repeated through sprite
+// data doesn't count for much.
+#[derive(Default)]
+struct Evidence {
+    kinds: u32,
+    count: u32,
+}
+
+impl Evidence {
+    fn add(&mut self, kind: u32) {
+        self.kinds |= 1 << kind;
+        self.count += 1;
+    }
+
+    fn confidence(&self) -> Confidence {
+        match (self.kinds.count_ones(), self.count) {
+            (k, _) if k >= 3 => Confidence::High,
+            (2, _) => Confidence::Medium,
+            (1, c) if c >= 4 => Confidence::Medium,
+            _ => Confidence::Low,
+        }
+    }
+}
+
+// Breadth-first walk over instruction addresses. Each address is queued at
+// most once, so `pending` can't overflow.
+struct Tracer {
+    code: [bool; MEMORY_SIZE],
+    pending: [u16; MEMORY_SIZE],
+    len: usize,
+    end: usize,
+}
+
+impl Tracer {
+    fn visit(&mut self, address: usize) {
+        if (PROGRAM_MEM_START..self.end - 1).contains(&address) && !self.code[address] {
+            self.code[address] = true;
+            self.pending[self.len] = address as u16;
+            self.len += 1;
+        }
+    }
+}
+
+/// Addresses where an instruction reachable from `PROGRAM_MEM_START` starts,
+/// following jumps, calls and both sides of skips. Computed jumps (BNNN)
+/// aren't followed, so code only they reach is missed.
+pub fn trace(rom: &[u8]) -> [bool; MEMORY_SIZE] {
+    let mut tracer = Tracer {
+        code: [false; MEMORY_SIZE],
+        pending: [0; MEMORY_SIZE],
+        len: 0,
+        end: (PROGRAM_MEM_START + rom.len()).min(MEMORY_SIZE),
+    };
+    tracer.visit(PROGRAM_MEM_START);
+
+    let mut next = 0;
+    while next < tracer.len {
+        let address = tracer.pending[next] as usize;
+        next += 1;
+        let offset = address - PROGRAM_MEM_START;
+        let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+        let nnn = (opcode & 0x0FFF) as usize;
+        match opcode >> 12 {
+            _ if opcode == 0x00EE || opcode == 0x00FD => {},
+            // XO-CHIP's long load is followed by its 16-bit address.
+            _ if opcode == 0xF000 => tracer.visit(address + 4),
+            0x1 => tracer.visit(nnn),
+            0x2 => {
+                tracer.visit(nnn);
+                tracer.visit(address + 2);
+            },
+            0xB => {},
+            0x3 | 0x4 | 0x5 | 0x9 => {
+                tracer.visit(address + 2);
+                tracer.visit(address + 4);
+            },
+            0xE if matches!(opcode & 0xFF, 0x9E | 0xA1) => {
+                tracer.visit(address + 2);
+                tracer.visit(address + 4);
+            },
+            _ => tracer.visit(address + 2),
+        }
+    }
+    tracer.code
+}
+
+/// Looks through the instructions `trace` reaches for opcodes and patterns
+/// that give the ROM's platform away. Only reachable code is considered, as
+/// sprite data can look like any opcode.
+pub fn detect(rom: &[u8]) -> Detection {
+    let mut superchip = Evidence::default();
+    let mut xochip = Evidence::default();
+    let mut hints = QuirkHints::default();
+    let mut draws = false;
+    let mut sets_delay = false;
+
+    let code = trace(rom);
+    let instructions = code.iter().filter(|&&c| c).count();
+    for (address, _) in code.iter().enumerate().filter(|(_, &c)| c) {
+        let offset = address - PROGRAM_MEM_START;
+        let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+        let x = (opcode >> 8) & 0xF;
+        let y = (opcode >> 4) & 0xF;
+        let n = opcode & 0xF;
+
+        match opcode {
+            0x00FE | 0x00FF => superchip.add(0),
+            0x00FB..=0x00FD => superchip.add(1),
+            0x00C1..=0x00CF => superchip.add(2),
+            0xF000 => xochip.add(0),
+            0xF002 => xochip.add(1),
+            0x00D1..=0x00DF => xochip.add(2),
+            _ => match (opcode >> 12, opcode & 0xFF) {
+                (0xD, _) if n == 0 => superchip.add(3),
+                (0xD, _) => draws = true,
+                (0xF, 0x30) => superchip.add(4),
+                (0xF, 0x75) | (0xF, 0x85) => superchip.add(5),
+                (0xF, 0x01) if x <= 3 => xochip.add(3),
+                (0xF, 0x3A) => xochip.add(4),
+                (0x5, _) if n == 2 || n == 3 => xochip.add(5),
+                (0xF, 0x15) => sets_delay = true,
+                (0xF, 0x0A) => hints.waits_for_key = true,
+                (0xB, _) => hints.jumps_with_offset = true,
+                (0x8, _) if matches!(n, 0x4..=0x7 | 0xE) => {
+                    hints.vf_destination |= x == 0xF;
+                    hints.shifts_other_register |= matches!(n, 0x6 | 0xE) && x != y;
+                },
+                _ => {},
+            },
+        }
+    }
+    hints.unpaced_drawing = draws && !sets_delay;
+
+    // XO-CHIP has 64 KiB of memory, so nothing else could load a ROM this big.
+    if rom.len() > MAX_ROM_SIZE {
+        xochip.add(6);
+        xochip.add(7);
+        xochip.add(8);
+    }
+
+    let (platform, confidence) = if xochip.confidence() > Confidence::Low {
+        (Platform::XoChip, xochip.confidence())
+    } else if superchip.confidence() > Confidence::Low || superchip.count > 0 && xochip.count == 0 {
+        (Platform::SuperChip, superchip.confidence())
+    } else if xochip.count > 0 {
+        (Platform::XoChip, Confidence::Low)
+    } else {
+        // Plenty of instructions and nothing beyond CHIP-8 is a good sign.
+        let confidence = if instructions >= 16 { Confidence::Medium } else { Confidence::Low };
+        (Platform::Chip8, confidence)
+    };
+
+    Detection {
+        platform,
+        confidence,
+        superchip_opcodes: superchip.count,
+        xochip_opcodes: xochip.count,
+        hints,
+    }
+}
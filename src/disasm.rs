@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::chip8::{MEMORY_SIZE, PROGRAM_MEM_START};
+use crate::detect;
+use crate::symbols::Symbols;
+
+// Bytes per `db` line in data regions.
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// Assembly for one instruction, with `address` formatting the targets of
+/// jumps, calls and ANNN. `long` is the word after the opcode, which only
+/// XO-CHIP's F000 uses.
+pub fn mnemonic(opcode: u16, long: u16, address: &dyn Fn(u16) -> String) -> String {
+    let x = (opcode >> 8) & 0xF;
+    let y = (opcode >> 4) & 0xF;
+    let n = opcode & 0xF;
+    let kk = opcode & 0xFF;
+    let nnn = opcode & 0x0FFF;
+    let Some(pattern) = detect::opcode_pattern(opcode) else {
+        return format!("db 0x{:02X}, 0x{:02X}", opcode >> 8, kk);
+    };
+    match pattern {
+        "00E0" => "CLS".to_string(),
+        "00EE" => "RET".to_string(),
+        "00CN" => format!("SCD {}", n),
+        "00DN" => format!("SCU {}", n),
+        "00FB" => "SCR".to_string(),
+        "00FC" => "SCL".to_string(),
+        "00FD" => "EXIT".to_string(),
+        "00FE" => "LOW".to_string(),
+        "00FF" => "HIGH".to_string(),
+        "0NNN" => format!("SYS 0x{:03X}", nnn),
+        "1NNN" => format!("JP {}", address(nnn)),
+        "2NNN" => format!("CALL {}", address(nnn)),
+        "3XNN" => format!("SE V{:X}, 0x{:02X}", x, kk),
+        "4XNN" => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        "5XY0" => format!("SE V{:X}, V{:X}", x, y),
+        "5XY2" => format!("SAVE V{:X}-V{:X}", x, y),
+        "5XY3" => format!("LOAD V{:X}-V{:X}", x, y),
+        "6XNN" => format!("LD V{:X}, 0x{:02X}", x, kk),
+        "7XNN" => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        "8XY0" => format!("LD V{:X}, V{:X}", x, y),
+        "8XY1" => format!("OR V{:X}, V{:X}", x, y),
+        "8XY2" => format!("AND V{:X}, V{:X}", x, y),
+        "8XY3" => format!("XOR V{:X}, V{:X}", x, y),
+        "8XY4" => format!("ADD V{:X}, V{:X}", x, y),
+        "8XY5" => format!("SUB V{:X}, V{:X}", x, y),
+        "8XY6" => format!("SHR V{:X}, V{:X}", x, y),
+        "8XY7" => format!("SUBN V{:X}, V{:X}", x, y),
+        "8XYE" => format!("SHL V{:X}, V{:X}", x, y),
+        "9XY0" => format!("SNE V{:X}, V{:X}", x, y),
+        "ANNN" => format!("LD I, {}", address(nnn)),
+        "BNNN" => format!("JP V0, {}", address(nnn)),
+        "CXNN" => format!("RND V{:X}, 0x{:02X}", x, kk),
+        "DXY0" | "DXYN" => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        "EX9E" => format!("SKP V{:X}", x),
+        "EXA1" => format!("SKNP V{:X}", x),
+        "F000" => format!("LD I, long 0x{:04X}", long),
+        "F002" => "AUDIO".to_string(),
+        "FN01" => format!("PLANE {}", x),
+        "FX07" => format!("LD V{:X}, DT", x),
+        "FX0A" => format!("LD V{:X}, K", x),
+        "FX15" => format!("LD DT, V{:X}", x),
+        "FX18" => format!("LD ST, V{:X}", x),
+        "FX1E" => format!("ADD I, V{:X}", x),
+        "FX29" => format!("LD F, V{:X}", x),
+        "FX30" => format!("LD HF, V{:X}", x),
+        "FX33" => format!("LD B, V{:X}", x),
+        "FX3A" => format!("PITCH V{:X}", x),
+        "FX55" => format!("LD [I], V{:X}", x),
+        "FX65" => format!("LD V{:X}, [I]", x),
+        "FX75" => format!("LD R, V{:X}", x),
+        "FX85" => format!("LD V{:X}, R", x),
+        _ => unreachable!("unhandled pattern {}", pattern),
+    }
+}
+
+/// How control gets from one block to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edge {
+    /// Straight on to the next instruction
+    Next,
+    Jump,
+    Call,
+    /// Over the next instruction, when a skip's condition holds
+    Skip,
+}
+
+/// A run of instructions only entered at its first and left after its last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: u16,
+    /// Address just past the last instruction
+    pub end: u16,
+    pub successors: Vec<(u16, Edge)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u16,
+    /// The address word after F000; 0 for other opcodes
+    pub long: u16,
+}
+
+impl Instruction {
+    pub fn size(&self) -> u16 {
+        if self.opcode == 0xF000 {
+            4
+        } else {
+            2
+        }
+    }
+
+    fn is_skip(&self) -> bool {
+        matches!(self.opcode >> 12, 0x3 | 0x4 | 0x5 | 0x9)
+            || self.opcode >> 12 == 0xE && matches!(self.opcode & 0xFF, 0x9E | 0xA1)
+    }
+
+    // Where control can go next, as `detect::trace` follows it.
+    fn successors(&self) -> Vec<(u16, Edge)> {
+        let nnn = self.opcode & 0x0FFF;
+        let next = self.address + self.size();
+        match self.opcode >> 12 {
+            _ if matches!(self.opcode, 0x00EE | 0x00FD) => vec![],
+            0x1 => vec![(nnn, Edge::Jump)],
+            0x2 => vec![(nnn, Edge::Call), (next, Edge::Next)],
+            0xB => vec![],
+            _ if self.is_skip() => vec![(next, Edge::Next), (next + 2, Edge::Skip)],
+            _ => vec![(next, Edge::Next)],
+        }
+    }
+}
+
+/// Code and data found by following control flow from `PROGRAM_MEM_START`
+/// (see `detect::trace`), split into basic blocks.
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    rom: Vec<u8>,
+    instructions: BTreeMap<u16, Instruction>,
+    blocks: BTreeMap<u16, Block>,
+    /// ANNN targets inside the ROM that aren't code: probably sprites
+    data: BTreeSet<u16>,
+    labels: BTreeMap<u16, String>,
+    symbols: Symbols,
+}
+
+impl Disassembly {
+    pub fn analyse(rom: &[u8]) -> Self {
+        let code = detect::trace(rom);
+        let end = PROGRAM_MEM_START + rom.len();
+        let word = |address: usize| {
+            let offset = address - PROGRAM_MEM_START;
+            match rom.get(offset..offset + 2) {
+                Some(&[high, low]) => u16::from_be_bytes([high, low]),
+                _ => 0,
+            }
+        };
+
+        let mut instructions = BTreeMap::new();
+        for address in (0..MEMORY_SIZE).filter(|&a| code[a]) {
+            let opcode = word(address);
+            let long = if opcode == 0xF000 { word(address + 2) } else { 0 };
+            instructions.insert(address as u16, Instruction { address: address as u16, opcode, long });
+        }
+
+        // Blocks start at the entry point and wherever control arrives other
+        // than by falling through.
+        let mut leaders = BTreeSet::from([PROGRAM_MEM_START as u16]);
+        let mut data = BTreeSet::new();
+        let mut subroutines = BTreeSet::new();
+        for instruction in instructions.values() {
+            for (target, edge) in instruction.successors() {
+                if edge != Edge::Next || instruction.is_skip() {
+                    leaders.insert(target);
+                }
+                if edge == Edge::Call {
+                    subroutines.insert(target);
+                    leaders.insert(instruction.address + instruction.size());
+                }
+            }
+            let nnn = instruction.opcode & 0x0FFF;
+            if instruction.opcode >> 12 == 0xA && (PROGRAM_MEM_START..end).contains(&(nnn as usize)) {
+                data.insert(nnn);
+            }
+        }
+        data.retain(|address| !instructions.contains_key(address));
+
+        let mut blocks = BTreeMap::new();
+        let mut current: Option<Block> = None;
+        for instruction in instructions.values() {
+            let address = instruction.address;
+            if let Some(block) = current.take() {
+                if block.end == address && !leaders.contains(&address) {
+                    current = Some(block);
+                } else {
+                    blocks.insert(block.start, block);
+                }
+            }
+            let block = current.get_or_insert(Block { start: address, end: address, successors: vec![] });
+            block.end = address + instruction.size();
+            block.successors = instruction.successors();
+            // Anything that doesn't just fall through ends the block.
+            if block.successors != [(block.end, Edge::Next)] {
+                blocks.insert(block.start, current.take().unwrap());
+            }
+        }
+        if let Some(block) = current {
+            blocks.insert(block.start, block);
+        }
+        for block in blocks.values_mut() {
+            block.successors.retain(|(target, _)| instructions.contains_key(target));
+        }
+
+        let mut labels = BTreeMap::new();
+        for &start in blocks.keys() {
+            let label = if start as usize == PROGRAM_MEM_START {
+                "start".to_string()
+            } else if subroutines.contains(&start) {
+                format!("sub_{:03X}", start)
+            } else {
+                format!("loc_{:03X}", start)
+            };
+            labels.insert(start, label);
+        }
+        // Blocks entered only by falling through don't need a label.
+        let targets: BTreeSet<u16> = blocks
+            .values()
+            .flat_map(|b| b.successors.iter())
+            .filter(|(_, edge)| *edge != Edge::Next)
+            .map(|&(target, _)| target)
+            .collect();
+        labels.retain(|address, _| *address as usize == PROGRAM_MEM_START || targets.contains(address));
+        for &address in &data {
+            labels.insert(address, format!("data_{:03X}", address));
+        }
+
+        Self {
+            rom: rom.to_vec(),
+            instructions,
+            blocks,
+            data,
+            labels,
+            symbols: Symbols::default(),
+        }
+    }
+
+    /// Names addresses in the ROM as `symbols` does instead of the made-up
+    /// `sub_2A0` style, and writes other addresses relative to the nearest
+    /// symbol, such as `sprites+0x4`.
+    pub fn apply_symbols(&mut self, symbols: &Symbols) {
+        let program = PROGRAM_MEM_START..PROGRAM_MEM_START + self.rom.len();
+        for (address, name) in symbols.labels().filter(|&(a, _)| program.contains(&(a as usize))) {
+            self.labels.insert(address, name.to_string());
+        }
+        self.symbols = symbols.clone();
+    }
+
+    pub fn instructions(&self) -> impl Iterator<Item = &Instruction> {
+        self.instructions.values()
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    /// Starts of regions ANNN points at, which are probably sprites.
+    pub fn data(&self) -> impl Iterator<Item = u16> + '_ {
+        self.data.iter().copied()
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(|l| l.as_str())
+    }
+
+    /// `address` as its label if it has one, else relative to a symbol, else
+    /// in hex.
+    pub fn address(&self, address: u16) -> String {
+        match self.label(address) {
+            Some(label) => label.to_string(),
+            None => self.symbols.symbolize(address).unwrap_or_else(|| format!("0x{:03X}", address)),
+        }
+    }
+
+    fn instruction_text(&self, instruction: &Instruction) -> String {
+        mnemonic(instruction.opcode, instruction.long, &|a| self.address(a))
+    }
+
+    /// The ROM as labelled assembly: reachable code as instructions, and
+    /// everything else as `db` lines, noting which bytes ANNN points at.
+    pub fn listing(&self) -> String {
+        let mut out = String::new();
+        let end = (PROGRAM_MEM_START + self.rom.len()) as u16;
+        let mut address = PROGRAM_MEM_START as u16;
+        while address < end {
+            if let Some(label) = self.label(address) {
+                let _ = writeln!(out, "{}:", label);
+            }
+            if let Some(instruction) = self.instructions.get(&address) {
+                let _ = writeln!(
+                    out,
+                    "  0x{:03X}  {:04X}  {}",
+                    address,
+                    instruction.opcode,
+                    self.instruction_text(instruction)
+                );
+                address += instruction.size();
+                continue;
+            }
+
+            // Data runs to the next instruction or label.
+            let mut stop = address + 1;
+            while stop < end
+                && (stop - address) < DATA_BYTES_PER_LINE as u16
+                && !self.instructions.contains_key(&stop)
+                && !self.labels.contains_key(&stop)
+            {
+                stop += 1;
+            }
+            let offset = (address as usize) - PROGRAM_MEM_START;
+            let bytes: Vec<String> = self.rom[offset..offset + (stop - address) as usize]
+                .iter()
+                .map(|b| format!("0x{:02X}", b))
+                .collect();
+            let _ = writeln!(out, "  0x{:03X}        db {}", address, bytes.join(", "));
+            address = stop;
+        }
+        out
+    }
+
+    /// The control-flow graph in Graphviz DOT, one node per block.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        for block in self.blocks.values() {
+            let mut text = String::new();
+            if let Some(label) = self.label(block.start) {
+                let _ = write!(text, "{}:\\l", label);
+            }
+            for instruction in self.instructions.range(block.start..block.end).map(|(_, i)| i) {
+                let _ = write!(text, "0x{:03X}  {}\\l", instruction.address, self.instruction_text(instruction));
+            }
+            let _ = writeln!(out, "    \"0x{:03X}\" [label=\"{}\"];", block.start, text.replace('"', "\\\""));
+        }
+        for block in self.blocks.values() {
+            for &(target, edge) in &block.successors {
+                let style = match edge {
+                    Edge::Next => "",
+                    Edge::Jump => " [label=\"jump\"]",
+                    Edge::Call => " [label=\"call\", style=dashed]",
+                    Edge::Skip => " [label=\"skip\"]",
+                };
+                let _ = writeln!(out, "    \"0x{:03X}\" -> \"0x{:03X}\"{};", block.start, target, style);
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
@@ -0,0 +1,353 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::chip8::{Chip8, KEYBOARD_SIZE, MEMORY_SIZE};
+
+/// Where and why an expression failed to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The byte offset into the expression.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.position + 1, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unary {
+    Not,
+    Negate,
+    Complement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Binary {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+// Binary operators by their spelling, loosest binding first, as in C.
+const BINARY: [(&str, Binary, u8); 18] = [
+    ("||", Binary::Or, 1),
+    ("&&", Binary::And, 2),
+    ("|", Binary::BitOr, 3),
+    ("^", Binary::BitXor, 4),
+    ("&", Binary::BitAnd, 5),
+    ("==", Binary::Equal, 6),
+    ("!=", Binary::NotEqual, 6),
+    ("<", Binary::Less, 7),
+    ("<=", Binary::LessEqual, 7),
+    (">", Binary::Greater, 7),
+    (">=", Binary::GreaterEqual, 7),
+    ("<<", Binary::ShiftLeft, 8),
+    (">>", Binary::ShiftRight, 8),
+    ("+", Binary::Add, 9),
+    ("-", Binary::Subtract, 9),
+    ("*", Binary::Multiply, 10),
+    ("/", Binary::Divide, 10),
+    ("%", Binary::Remainder, 10),
+];
+
+// Longest first, so `<=` isn't read as `<` then `=`.
+const OPERATORS: [&str; 24] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/", "%", "!", "~", "(",
+    ")", "[", "]",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Register(usize),
+    Index,
+    Pc,
+    Sp,
+    Delay,
+    Sound,
+    Memory(Box<Node>),
+    Key(Box<Node>),
+    Unary(Unary, Box<Node>),
+    Binary(Binary, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Number(i64),
+    Name(&'a str),
+    Operator(&'static str),
+    End,
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "`{}`", n),
+            Token::Name(name) => write!(f, "`{}`", name),
+            Token::Operator(op) => write!(f, "`{}`", op),
+            Token::End => write!(f, "end of expression"),
+        }
+    }
+}
+
+fn error<T>(position: usize, message: impl Into<String>) -> Result<T, ParseError> {
+    Err(ParseError { position, message: message.into() })
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token<'_>)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start();
+        let position = text.len() - rest.len();
+        let Some(c) = rest.chars().next() else { break };
+        let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+        let (token, len) = if c.is_ascii_digit() {
+            let word = &rest[..end];
+            let number = match (word.strip_prefix("0x"), word.strip_prefix("0b")) {
+                (Some(hex), _) => i64::from_str_radix(hex, 16),
+                (_, Some(binary)) => i64::from_str_radix(binary, 2),
+                _ => word.parse(),
+            };
+            match number {
+                Ok(n) => (Token::Number(n), end),
+                Err(_) => return error(position, format!("bad number `{}`", word)),
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            (Token::Name(&rest[..end]), end)
+        } else if let Some(op) = OPERATORS.into_iter().find(|op| rest.starts_with(op)) {
+            (Token::Operator(op), op.len())
+        } else {
+            return error(position, format!("unexpected `{}`", c));
+        };
+        tokens.push((position, token));
+        rest = &rest[len..];
+    }
+    tokens.push((text.len(), Token::End));
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token<'a>)>,
+    next: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> (usize, Token<'a>) {
+        self.tokens[self.next]
+    }
+
+    fn advance(&mut self) -> (usize, Token<'a>) {
+        let token = self.peek();
+        if token.1 != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), ParseError> {
+        match self.advance() {
+            (_, Token::Operator(found)) if found == op => Ok(()),
+            (position, found) => error(position, format!("expected `{}`, found {}", op, found)),
+        }
+    }
+
+    // Operators binding at least as tightly as `min`, left to right.
+    fn binary(&mut self, min: u8) -> Result<Node, ParseError> {
+        let mut left = self.unary()?;
+        while let (_, Token::Operator(spelling)) = self.peek() {
+            let Some(&(_, op, precedence)) = BINARY.iter().find(|(s, _, p)| *s == spelling && *p >= min) else { break };
+            self.advance();
+            let right = self.binary(precedence + 1)?;
+            left = Node::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        let op = match self.peek().1 {
+            Token::Operator("!") => Unary::Not,
+            Token::Operator("-") => Unary::Negate,
+            Token::Operator("~") => Unary::Complement,
+            _ => return self.primary(),
+        };
+        self.advance();
+        Ok(Node::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Node, ParseError> {
+        let (position, token) = self.advance();
+        match token {
+            Token::Number(n) => Ok(Node::Number(n)),
+            Token::Operator("(") => {
+                let node = self.binary(0)?;
+                self.expect(")")?;
+                Ok(node)
+            },
+            Token::Name(name) => self.name(position, name),
+            _ => error(position, format!("expected a value, found {}", token)),
+        }
+    }
+
+    fn name(&mut self, position: usize, name: &str) -> Result<Node, ParseError> {
+        let lower = name.to_ascii_lowercase();
+        let register = lower.strip_prefix('v').filter(|x| x.len() == 1).and_then(|x| usize::from_str_radix(x, 16).ok());
+        if let Some(x) = register {
+            return Ok(Node::Register(x));
+        }
+        Ok(match lower.as_str() {
+            "i" => Node::Index,
+            "pc" => Node::Pc,
+            "sp" => Node::Sp,
+            "dt" | "delay" => Node::Delay,
+            "st" | "sound" => Node::Sound,
+            "mem" | "key" => {
+                self.expect("[")?;
+                let node = Box::new(self.binary(0)?);
+                self.expect("]")?;
+                match lower.as_str() {
+                    "mem" => Node::Memory(node),
+                    _ => Node::Key(node),
+                }
+            },
+            _ => return error(position, format!("unknown name `{}`", name)),
+        })
+    }
+}
+
+/// An expression over the machine's state, for breakpoint conditions and
+/// watches, such as `V3 == 0x10 && I > 0x300` or `mem[0x2F0] != 0`.
+///
+/// Values are V0-VF, I, PC, SP, DT (or `delay`), ST (or `sound`), a byte of
+/// memory `mem[address]`, whether a key is held `key[n]`, and numbers in
+/// decimal, `0x` hex or `0b` binary. The operators are C's, with the same
+/// precedence; comparisons and `!`, `&&` and `||` give 1 or 0. Names are
+/// case-insensitive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    source: String,
+    node: Node,
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser { tokens: tokenize(text)?, next: 0 };
+        let node = parser.binary(0)?;
+        match parser.peek() {
+            (_, Token::End) => Ok(Expr { source: text.trim().to_string(), node }),
+            (position, token) => error(position, format!("unexpected {}", token)),
+        }
+    }
+
+    /// The expression as it was written.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Fails on division by zero, an address or key out of range, or a
+    /// shift by 64 or more.
+    pub fn eval(&self, chip8: &Chip8) -> Result<i64, String> {
+        eval(&self.node, chip8)
+    }
+
+    /// Whether the expression is non-zero. One that fails to evaluate
+    /// counts as true, so a broken breakpoint condition still stops.
+    pub fn is_true(&self, chip8: &Chip8) -> bool {
+        self.eval(chip8) != Ok(0)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// A value as debuggers show it: small and negative numbers in decimal,
+/// others in decimal and hex, such as `16 (0x10)`.
+pub fn format_value(value: i64) -> String {
+    match value {
+        ..=9 => value.to_string(),
+        _ => format!("{} (0x{:X})", value, value),
+    }
+}
+
+// `value` as an index below `len`, for mem[] and key[].
+fn index(value: i64, len: usize, what: &str) -> Result<usize, String> {
+    match usize::try_from(value) {
+        Ok(i) if i < len => Ok(i),
+        _ => Err(format!("{}[{}] is out of range", what, value)),
+    }
+}
+
+fn eval(node: &Node, chip8: &Chip8) -> Result<i64, String> {
+    Ok(match node {
+        Node::Number(n) => *n,
+        Node::Register(x) => chip8.registers()[*x] as i64,
+        Node::Index => chip8.index() as i64,
+        Node::Pc => chip8.pc() as i64,
+        Node::Sp => chip8.sp() as i64,
+        Node::Delay => chip8.delay_timer() as i64,
+        Node::Sound => chip8.sound_timer() as i64,
+        Node::Memory(address) => chip8.memory()[index(eval(address, chip8)?, MEMORY_SIZE, "mem")?] as i64,
+        Node::Key(key) => chip8.keyboard[index(eval(key, chip8)?, KEYBOARD_SIZE, "key")?] as i64,
+        Node::Unary(op, operand) => {
+            let value = eval(operand, chip8)?;
+            match op {
+                Unary::Not => (value == 0) as i64,
+                Unary::Negate => value.wrapping_neg(),
+                Unary::Complement => !value,
+            }
+        },
+        Node::Binary(Binary::And, left, right) => (eval(left, chip8)? != 0 && eval(right, chip8)? != 0) as i64,
+        Node::Binary(Binary::Or, left, right) => (eval(left, chip8)? != 0 || eval(right, chip8)? != 0) as i64,
+        Node::Binary(op, left, right) => {
+            let (a, b) = (eval(left, chip8)?, eval(right, chip8)?);
+            match op {
+                Binary::BitOr => a | b,
+                Binary::BitXor => a ^ b,
+                Binary::BitAnd => a & b,
+                Binary::Equal => (a == b) as i64,
+                Binary::NotEqual => (a != b) as i64,
+                Binary::Less => (a < b) as i64,
+                Binary::LessEqual => (a <= b) as i64,
+                Binary::Greater => (a > b) as i64,
+                Binary::GreaterEqual => (a >= b) as i64,
+                Binary::ShiftLeft | Binary::ShiftRight => {
+                    let shift = u32::try_from(b).ok().filter(|&s| s < 64).ok_or_else(|| format!("shift by {}", b))?;
+                    match op {
+                        Binary::ShiftLeft => a << shift,
+                        _ => a >> shift,
+                    }
+                },
+                Binary::Add => a.wrapping_add(b),
+                Binary::Subtract => a.wrapping_sub(b),
+                Binary::Multiply => a.wrapping_mul(b),
+                Binary::Divide | Binary::Remainder if b == 0 => return Err(String::from("division by zero")),
+                Binary::Divide => a.wrapping_div(b),
+                Binary::Remainder => a.wrapping_rem(b),
+                Binary::And | Binary::Or => unreachable!("short-circuited above"),
+            }
+        },
+    })
+}
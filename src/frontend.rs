@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::browser::RomBrowser;
+use crate::chip8::{Chip8, KEYBOARD_SIZE};
+use crate::coverage::Coverage;
+use crate::debugger::Watch;
+use crate::scheduler::Speed;
+
+/// Frontend-independent requests that an `InputSource` can make of the
+/// `Runner`, typically bound to hotkeys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    TogglePause,
+    FrameAdvance,
+    ToggleTurbo,
+    ToggleSlowMotion,
+    /// Restart the loaded ROM
+    Reset,
+    /// Read the ROM from disk again and restart it
+    Reload,
+    Load(PathBuf),
+    SaveState,
+    LoadState,
+    OpenBrowser,
+    CloseBrowser,
+    BrowserNext,
+    BrowserPrevious,
+    BrowserSelect,
+    Screenshot,
+    ToggleRecording,
+    /// Show which memory has been executed, read and written instead of the
+    /// display, starting coverage recording if needed
+    ToggleHeatmap,
+}
+
+/// What the emulator is currently doing, for window titles and status lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status<'a> {
+    pub rom: Option<&'a Path>,
+    pub speed: Speed,
+    pub paused: bool,
+    pub recording: bool,
+}
+
+/// Colours to draw the display with, as 0xRRGGBB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub background: u32,
+    pub foreground: u32,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            background: 0x000000,
+            foreground: 0xFFFFFF,
+        }
+    }
+}
+
+impl Palette {
+    /// The colour of a `Chip8::video` pixel.
+    pub fn color(&self, pixel: u32) -> u32 {
+        if pixel != 0 {
+            self.foreground
+        } else {
+            self.background
+        }
+    }
+}
+
+/// CHIP-8 keys that a ROM uses as a direction pad and action buttons, so
+/// frontends can bind them to the arrow keys and the like on top of the
+/// usual keypad layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Keymap {
+    pub up: Option<usize>,
+    pub down: Option<usize>,
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub a: Option<usize>,
+    pub b: Option<usize>,
+}
+
+pub trait DisplaySink {
+    fn present(&mut self, chip8: &Chip8) -> Result<(), String>;
+
+    /// Draws the ROM picker instead of the emulator display.
+    fn present_browser(&mut self, _browser: &RomBrowser) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Draws a 64x64 map of the 4 KiB of memory, one cell per address in
+    /// `Coverage::color`, instead of the emulator display.
+    fn present_heatmap(&mut self, _coverage: &Coverage) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn set_status(&mut self, _status: Status<'_>) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Switches colours, e.g. to those the ROM's database entry asks for.
+    fn set_palette(&mut self, _palette: Palette) {}
+
+    /// Shows the watch list after any of its values changed.
+    fn set_watches(&mut self, _watches: &[Watch]) {}
+
+    /// Reports something the user asked for, e.g. where a screenshot went.
+    fn notify(&mut self, _message: &str) {}
+}
+
+pub trait InputSource {
+    /// Updates `keyboard` with the current CHIP-8 key state and queues any
+    /// commands that arrived since the last poll.
+    fn poll(&mut self, keyboard: &mut [bool; KEYBOARD_SIZE], commands: &mut Vec<Command>);
+
+    /// Binds the ROM's direction pad and buttons in addition to the keypad.
+    fn set_keymap(&mut self, _keymap: Keymap) {}
+}
+
+pub trait AudioSink {
+    fn set_beeping(&mut self, beeping: bool);
+}
+
+pub trait TimeSource {
+    fn now(&self) -> Instant;
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// Wall-clock time for interactive frontends.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when slept on, so headless and test frontends
+/// run as fast as possible while still seeing 60 Hz frame pacing.
+#[derive(Debug, Clone, Copy)]
+pub struct ManualClock {
+    now: Instant,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self { now: Instant::now() }
+    }
+}
+
+impl TimeSource for ManualClock {
+    fn now(&self) -> Instant {
+        self.now
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.now += duration;
+    }
+}
+
+impl DisplaySink for () {
+    fn present(&mut self, _chip8: &Chip8) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl InputSource for () {
+    fn poll(&mut self, _keyboard: &mut [bool; KEYBOARD_SIZE], _commands: &mut Vec<Command>) {}
+}
+
+impl AudioSink for () {
+    fn set_beeping(&mut self, _beeping: bool) {}
+}
@@ -0,0 +1,417 @@
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+
+use crate::chip8::{Chip8, MEMORY_SIZE, NUM_REGISTERS};
+use crate::debugger::{DebugServer, Debugger, StopReason};
+use crate::frontend::Command;
+
+/// The register layout served to clients, also in `gdb/target.xml` for
+/// ones that can't fetch it.
+pub const TARGET_XML: &str = include_str!("../gdb/target.xml");
+
+/// Bytes per register, in `g` packet order: V0-VF, I, PC, SP, DT and ST.
+/// Values are big-endian.
+pub const REGISTER_SIZES: [usize; NUM_REGISTERS + 5] = [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 1, 1, 1];
+
+const PACKET_SIZE: usize = 0x1000;
+const INTERRUPT: u8 = 0x03;
+
+// Signals as stop replies report them.
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// One client's side of the GDB remote serial protocol, without the
+/// socket: bytes from the client go into `process`, which returns the bytes
+/// to send back.
+#[derive(Debug, Default)]
+pub struct GdbSession {
+    input: Vec<u8>,
+    // The last packet sent, in case the client asks for it again.
+    last_reply: Vec<u8>,
+    no_ack: bool,
+    // Between `c` or `s` and the stop reply that ends it.
+    running: bool,
+    signal: u8,
+    detached: bool,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+fn parse_hex(hex: &str) -> Option<usize> {
+    usize::from_str_radix(hex, 16).ok()
+}
+
+// `addr,length`, as `m`, `M` and `qXfer` take. Ranges that run past the end
+// of the address space are malformed, so `start + len` can't overflow.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (start, len) = text.split_once(',')?;
+    let (start, len) = (parse_hex(start)?, parse_hex(len)?);
+    start.checked_add(len)?;
+    Some((start, len))
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+// `$data#xx`, with the protocol's special characters escaped.
+fn frame(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data {
+        if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+            escaped.extend([b'}', byte ^ 0x20]);
+        } else {
+            escaped.push(byte);
+        }
+    }
+    let mut packet = vec![b'$'];
+    packet.extend(&escaped);
+    packet.extend(format!("#{:02x}", checksum(&escaped)).bytes());
+    packet
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut escaped = false;
+    for &byte in data {
+        match (escaped, byte) {
+            (false, b'}') => escaped = true,
+            (true, _) => {
+                bytes.push(byte ^ 0x20);
+                escaped = false;
+            },
+            _ => bytes.push(byte),
+        }
+    }
+    bytes
+}
+
+fn read_register(chip8: &Chip8, number: usize) -> Option<Vec<u8>> {
+    let value = match number {
+        0..=15 => vec![chip8.registers()[number]],
+        16 => chip8.index().to_be_bytes().to_vec(),
+        17 => (chip8.pc() as u16).to_be_bytes().to_vec(),
+        18 => vec![chip8.sp() as u8],
+        19 => vec![chip8.delay_timer()],
+        20 => vec![chip8.sound_timer()],
+        _ => return None,
+    };
+    Some(value)
+}
+
+// SP can only be "written" with the value it has, as the stack it counts
+// lives outside memory, and I only with an address in memory.
+fn write_register(chip8: &mut Chip8, number: usize, bytes: &[u8]) -> bool {
+    if REGISTER_SIZES.get(number) != Some(&bytes.len()) {
+        return false;
+    }
+    let word = || u16::from_be_bytes([bytes[0], bytes[1]]);
+    match number {
+        0..=15 => chip8.set_register(number, bytes[0]),
+        16 if word() as usize >= MEMORY_SIZE => return false,
+        16 => chip8.set_index(word()),
+        17 => chip8.set_pc(word() as usize),
+        18 => return bytes[0] as usize == chip8.sp(),
+        19 => chip8.set_delay_timer(bytes[0]),
+        _ => chip8.set_sound_timer(bytes[0]),
+    }
+    true
+}
+
+impl GdbSession {
+    /// Whether the client detached or killed the session, so the connection
+    /// should close.
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    /// Handles every complete packet in `bytes` plus whatever came before,
+    /// and reports a stop if the machine paused while running. Returns the
+    /// bytes to send to the client.
+    pub fn process(&mut self, bytes: &[u8], chip8: &mut Chip8, debugger: &mut Debugger) -> Vec<u8> {
+        self.input.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        self.report_stop(debugger, &mut out);
+        while let Some(&first) = self.input.first() {
+            match first {
+                b'$' => {
+                    let Some(hash) = self.input.iter().position(|&b| b == b'#') else { break };
+                    if self.input.len() < hash + 3 {
+                        break;
+                    }
+                    let packet: Vec<u8> = self.input.drain(..hash + 3).collect();
+                    let data = &packet[1..hash];
+                    let sent = std::str::from_utf8(&packet[hash + 1..]).ok();
+                    let sent = sent.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    if sent != Some(checksum(data)) {
+                        if !self.no_ack {
+                            out.push(b'-');
+                        }
+                        continue;
+                    }
+                    if !self.no_ack {
+                        out.push(b'+');
+                    }
+                    if let Some(reply) = self.handle(&unescape(data), chip8, debugger) {
+                        self.last_reply = frame(reply.as_bytes());
+                        out.extend(&self.last_reply);
+                    }
+                },
+                b'-' => {
+                    self.input.remove(0);
+                    out.extend(&self.last_reply);
+                },
+                INTERRUPT => {
+                    self.input.remove(0);
+                    if self.running {
+                        debugger.pause(StopReason::Interrupt);
+                    }
+                },
+                // Acks, and any noise between packets.
+                _ => {
+                    self.input.remove(0);
+                },
+            }
+        }
+
+        self.report_stop(debugger, &mut out);
+        out
+    }
+
+    // Sends the stop reply a `c` or `s` is waiting for, once the machine
+    // pauses. Other pauses, such as on attaching, only set what `?` says.
+    fn report_stop(&mut self, debugger: &mut Debugger, out: &mut Vec<u8>) {
+        let Some(reason) = debugger.take_stop() else { return };
+        self.signal = if reason == StopReason::Interrupt { SIGINT } else { SIGTRAP };
+        if self.running {
+            self.running = false;
+            self.last_reply = frame(format!("S{:02x}", self.signal).as_bytes());
+            out.extend(&self.last_reply);
+        }
+    }
+
+    // The reply to one packet, or `None` for packets answered later (`c`,
+    // `s`) or never (`k`).
+    fn handle(&mut self, packet: &[u8], chip8: &mut Chip8, debugger: &mut Debugger) -> Option<String> {
+        // Binary packets (`X`) aren't supported, so clients fall back to `M`.
+        let Ok(packet) = std::str::from_utf8(packet) else { return Some(String::new()) };
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => format!("S{:02x}", self.signal),
+            "g" => {
+                let bytes: Vec<u8> = (0..REGISTER_SIZES.len()).flat_map(|n| read_register(chip8, n).unwrap()).collect();
+                to_hex(&bytes)
+            },
+            "G" => match from_hex(args) {
+                Some(bytes) if bytes.len() == REGISTER_SIZES.iter().sum::<usize>() => {
+                    let mut offset = 0;
+                    let mut ok = true;
+                    for (number, size) in REGISTER_SIZES.iter().enumerate() {
+                        ok &= write_register(chip8, number, &bytes[offset..offset + size]);
+                        offset += size;
+                    }
+                    if ok { "OK".to_string() } else { "E01".to_string() }
+                },
+                _ => "E01".to_string(),
+            },
+            "p" => match parse_hex(args).and_then(|n| read_register(chip8, n)) {
+                Some(bytes) => to_hex(&bytes),
+                None => "E01".to_string(),
+            },
+            "P" => {
+                let written = args.split_once('=').and_then(|(number, value)| {
+                    let (number, bytes) = (parse_hex(number)?, from_hex(value)?);
+                    write_register(chip8, number, &bytes).then_some(())
+                });
+                if written.is_some() { "OK".to_string() } else { "E01".to_string() }
+            },
+            "m" => match parse_range(args) {
+                Some((start, len)) if start < MEMORY_SIZE => {
+                    to_hex(&chip8.memory()[start..(start + len).min(MEMORY_SIZE)])
+                },
+                _ => "E01".to_string(),
+            },
+            "M" => {
+                let write = args.split_once(':').and_then(|(range, data)| {
+                    let ((start, len), bytes) = (parse_range(range)?, from_hex(data)?);
+                    (bytes.len() == len && start + len <= MEMORY_SIZE).then_some((start, bytes))
+                });
+                match write {
+                    Some((start, bytes)) => {
+                        chip8.write_memory(start, &bytes);
+                        "OK".to_string()
+                    },
+                    None => "E01".to_string(),
+                }
+            },
+            // Software and hardware breakpoints are the same thing here.
+            "Z" | "z" => {
+                let mut fields = args.split(',');
+                let kind = fields.next();
+                match (kind, fields.next().and_then(parse_hex)) {
+                    (Some("0" | "1"), Some(address)) if address < MEMORY_SIZE => {
+                        if command == "Z" {
+                            debugger.set_breakpoint(address as u16);
+                        } else {
+                            debugger.clear_breakpoint(address as u16);
+                        }
+                        "OK".to_string()
+                    },
+                    (Some("0" | "1"), _) => "E01".to_string(),
+                    // Watchpoints aren't supported.
+                    _ => String::new(),
+                }
+            },
+            "c" | "s" => {
+                if let Some(address) = parse_hex(args) {
+                    chip8.set_pc(address);
+                }
+                if command == "c" {
+                    debugger.resume(chip8);
+                } else {
+                    debugger.step(chip8);
+                }
+                self.running = true;
+                return None;
+            },
+            "D" | "k" => {
+                self.detached = true;
+                debugger.clear_breakpoints();
+                debugger.resume(chip8);
+                return (command == "D").then(|| "OK".to_string());
+            },
+            "H" => "OK".to_string(),
+            _ => self.query(packet),
+        };
+        Some(reply)
+    }
+
+    // General queries and settings.
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", PACKET_SIZE);
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:") {
+            let read = args.split_once(':').and_then(|(annex, range)| Some((annex, parse_range(range)?)));
+            return match read {
+                Some(("target.xml", (offset, len))) => {
+                    let xml = TARGET_XML.as_bytes();
+                    let chunk = &xml[offset.min(xml.len())..(offset + len).min(xml.len())];
+                    let more = offset + len < xml.len();
+                    format!("{}{}", if more { 'm' } else { 'l' }, String::from_utf8_lossy(chunk))
+                },
+                Some(_) => "E00".to_string(),
+                None => "E01".to_string(),
+            };
+        }
+        match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            },
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ if packet.starts_with('T') => "OK".to_string(),
+            // The empty reply means "not supported".
+            _ => String::new(),
+        }
+    }
+}
+
+/// A GDB remote serial protocol server on a localhost TCP port, serving
+/// one client at a time. The machine pauses when a client attaches, and
+/// its breakpoints go when it disconnects.
+#[derive(Debug)]
+pub struct GdbServer {
+    listener: TcpListener,
+    address: SocketAddr,
+    client: Option<(TcpStream, GdbSession)>,
+}
+
+impl GdbServer {
+    /// Listens on `port`, or any free port if it's 0.
+    pub fn listen(port: u16) -> Result<Self, String> {
+        let error = |e: io::Error| format!("localhost:{}: {}", port, e);
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).map_err(error)?;
+        listener.set_nonblocking(true).map_err(error)?;
+        let address = listener.local_addr().map_err(error)?;
+        Ok(Self { listener, address, client: None })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    fn accept(&mut self, debugger: &mut Debugger) -> Result<(), String> {
+        match self.listener.accept() {
+            Ok((stream, _)) => {
+                let _ = stream.set_nodelay(true);
+                debugger.pause(StopReason::Interrupt);
+                self.client = Some((stream, GdbSession::default()));
+                Ok(())
+            },
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(format!("{}: {}", self.address, e)),
+        }
+    }
+}
+
+// Everything the client has sent so far, or `None` once it hung up.
+fn read_available(stream: &mut TcpStream) -> Option<Vec<u8>> {
+    stream.set_nonblocking(true).ok()?;
+    let mut bytes = Vec::new();
+    let mut buffer = [0; PACKET_SIZE];
+    let open = loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => break false,
+            Ok(n) => bytes.extend_from_slice(&buffer[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break true,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(_) => break false,
+        }
+    };
+    (open && stream.set_nonblocking(false).is_ok()).then_some(bytes)
+}
+
+impl DebugServer for GdbServer {
+    fn poll(&mut self, chip8: &mut Chip8, debugger: &mut Debugger, _commands: &mut Vec<Command>) -> Result<(), String> {
+        if self.client.is_none() {
+            self.accept(debugger)?;
+        }
+        let Some((stream, session)) = self.client.as_mut() else { return Ok(()) };
+
+        let connected = match read_available(stream) {
+            Some(bytes) => {
+                let reply = session.process(&bytes, chip8, debugger);
+                stream.write_all(&reply).is_ok() && !session.is_detached()
+            },
+            None => false,
+        };
+        if !connected {
+            if !session.is_detached() {
+                debugger.clear_breakpoints();
+                debugger.resume(chip8);
+            }
+            self.client = None;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::capture;
+use crate::chip8::{Chip8, KEYBOARD_SIZE, VIDEO_HEIGHT, VIDEO_WIDTH};
+use crate::coverage::Coverage;
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::scheduler::{Scheduler, DEFAULT_CYCLES_PER_FRAME};
+
+/// Set this environment variable to rewrite golden images from the current
+/// output instead of comparing against them.
+pub const UPDATE_ENV: &str = "CHIP8_UPDATE_GOLDENS";
+
+const ON: char = '#';
+const OFF: char = '.';
+
+/// A key going down or up at the start of `frame` (counting from 0).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: usize,
+    pub pressed: bool,
+}
+
+/// How to run a ROM for a golden-image test. CXKK draws from a generator
+/// seeded with `seed`, so runs are reproducible.
+#[derive(Debug, Clone)]
+pub struct RomTest {
+    pub frames: u32,
+    pub cycles_per_frame: u32,
+    pub quirks: Quirks,
+    pub seed: u32,
+    pub input: Vec<KeyEvent>,
+    /// Record coverage, for `Chip8::coverage` on the machine `run` returns
+    pub coverage: bool,
+}
+
+impl Default for RomTest {
+    fn default() -> Self {
+        Self {
+            frames: 60,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            quirks: Quirks::default(),
+            seed: 1,
+            input: Vec::new(),
+            coverage: false,
+        }
+    }
+}
+
+impl RomTest {
+    pub fn new(frames: u32) -> Self {
+        Self {
+            frames,
+            ..Self::default()
+        }
+    }
+
+    /// Holds `key` from `frame` for `duration` frames.
+    pub fn tap(mut self, key: usize, frame: u32, duration: u32) -> Self {
+        self.input.push(KeyEvent { frame, key, pressed: true });
+        self.input.push(KeyEvent { frame: frame + duration, key, pressed: false });
+        self
+    }
+
+    /// Runs `rom` for `frames` frames and returns the machine as it ended up.
+    pub fn run(&self, rom: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::from_bytes(rom);
+        chip8.set_quirks(self.quirks);
+        chip8.set_rng(Rng::seeded(self.seed));
+        if self.coverage {
+            chip8.set_coverage(Some(Coverage::default()));
+        }
+        let scheduler = Scheduler::new(self.cycles_per_frame, Instant::now());
+
+        for frame in 0..self.frames {
+            for event in self.input.iter().filter(|e| e.frame == frame && e.key < KEYBOARD_SIZE) {
+                chip8.keyboard[event.key] = event.pressed;
+            }
+            scheduler.run_frame(&mut chip8);
+        }
+        chip8
+    }
+
+    /// Runs the ROM at `rom` and checks the final display against `golden`.
+    pub fn check(&self, rom: &Path, golden: &Path) -> Result<(), String> {
+        let bytes = fs::read(rom).map_err(|e| format!("{}: {}", rom.display(), e))?;
+        check_golden(golden, &self.run(&bytes).video)
+    }
+}
+
+/// The display as text art: one line per row, `#` for lit pixels.
+pub fn to_text(video: &[u32]) -> String {
+    let mut text = String::with_capacity((VIDEO_WIDTH + 1) * VIDEO_HEIGHT);
+    for row in video.chunks(VIDEO_WIDTH) {
+        text.extend(row.iter().map(|&p| if p != 0 { ON } else { OFF }));
+        text.push('\n');
+    }
+    text
+}
+
+/// Parses text art written by `to_text` back into lit (`true`) pixels.
+pub fn parse_text(text: &str) -> Result<Vec<bool>, String> {
+    let rows: Vec<&str> = text.lines().collect();
+    if rows.len() != VIDEO_HEIGHT {
+        return Err(format!("expected {} rows, found {}", VIDEO_HEIGHT, rows.len()));
+    }
+
+    let mut pixels = Vec::with_capacity(VIDEO_WIDTH * VIDEO_HEIGHT);
+    for (y, row) in rows.iter().enumerate() {
+        if row.chars().count() != VIDEO_WIDTH {
+            return Err(format!("row {}: expected {} columns", y + 1, VIDEO_WIDTH));
+        }
+        for (x, c) in row.chars().enumerate() {
+            match c {
+                ON => pixels.push(true),
+                OFF => pixels.push(false),
+                _ => return Err(format!("row {}, column {}: unexpected {:?}", y + 1, x + 1, c)),
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+// Any non-black pixel counts as lit, whatever the PNG's scale or palette.
+fn read_png(path: &Path) -> io::Result<Vec<bool>> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0; reader.output_buffer_size().unwrap_or_default()];
+    let info = reader.next_frame(&mut data)?;
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    if width % VIDEO_WIDTH != 0 || width / VIDEO_WIDTH != height / VIDEO_HEIGHT || height % VIDEO_HEIGHT != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}x{} is not a multiple of {}x{}", width, height, VIDEO_WIDTH, VIDEO_HEIGHT),
+        ));
+    }
+
+    let scale = width / VIDEO_WIDTH;
+    let channels = info.color_type.samples();
+    let color = if channels >= 3 { 3 } else { 1 };
+    let mut pixels = Vec::with_capacity(VIDEO_WIDTH * VIDEO_HEIGHT);
+    for y in 0..VIDEO_HEIGHT {
+        for x in 0..VIDEO_WIDTH {
+            let i = (y * scale * width + x * scale) * channels;
+            pixels.push(data[i..i + color].iter().any(|&c| c != 0));
+        }
+    }
+    Ok(pixels)
+}
+
+fn write_golden(path: &Path, video: &[u32]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("png") => capture::save_png(path, video, 1),
+        _ => fs::write(path, to_text(video)),
+    }
+}
+
+fn read_golden(path: &Path) -> Result<Vec<bool>, String> {
+    let pixels = match path.extension().and_then(|e| e.to_str()) {
+        Some("png") => read_png(path).map_err(|e| e.to_string()),
+        _ => fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|t| parse_text(&t)),
+    };
+    pixels.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Compares the display against the golden image at `path`, unless
+/// `CHIP8_UPDATE_GOLDENS` is set, in which case it writes the golden.
+pub fn check_golden(path: &Path, video: &[u32]) -> Result<(), String> {
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        return write_golden(path, video).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    if !path.exists() {
+        return Err(format!("{}: no golden yet; run with {}=1 to create it", path.display(), UPDATE_ENV));
+    }
+    compare_golden(path, video)
+}
+
+/// Compares the display against the golden image at `path`: text art, or a
+/// PNG at any integer scale.
+pub fn compare_golden(path: &Path, video: &[u32]) -> Result<(), String> {
+    let expected = read_golden(path)?;
+    let differing: Vec<(usize, usize)> = video
+        .iter()
+        .zip(&expected)
+        .enumerate()
+        .filter(|(_, (&actual, &expected))| (actual != 0) != expected)
+        .map(|(i, _)| (i % VIDEO_WIDTH, i / VIDEO_WIDTH))
+        .collect();
+
+    if differing.is_empty() {
+        return Ok(());
+    }
+    let (x, y) = differing[0];
+    Err(format!(
+        "{}: {} pixels differ, the first at ({}, {}); rerun with {}=1 to accept. Actual display:\n{}",
+        path.display(),
+        differing.len(),
+        x,
+        y,
+        UPDATE_ENV,
+        to_text(video)
+    ))
+}
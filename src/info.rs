@@ -0,0 +1,169 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::chip8::{MAX_ROM_SIZE, MEMORY_SIZE, PROGRAM_MEM_START};
+use crate::database::{sha1_hex, Database};
+use crate::detect::{self, Detection, Platform};
+
+// Tallest sprite DXYN draws, used when a ROM never says.
+const MAX_SPRITE_BYTES: usize = 15;
+// A 16x16 SUPER-CHIP sprite from DXY0.
+const LARGE_SPRITE_BYTES: usize = 32;
+
+/// What can be learned about a ROM without running it, for the `info`
+/// subcommand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    pub size: usize,
+    pub sha1: [u8; 20],
+    /// From the ROM database, if it has an entry.
+    pub title: Option<String>,
+    pub detection: Detection,
+    /// Where the program's own code starts: `PROGRAM_MEM_START`, or the
+    /// target of the jump there if the ROM opens with one.
+    pub entry_point: u16,
+    /// Patterns (as from `detect::opcode_pattern`) of the reachable
+    /// instructions.
+    pub opcodes: BTreeSet<&'static str>,
+    /// Bytes of instructions `detect::trace` reaches.
+    pub code_bytes: usize,
+    /// Bytes that ANNN points at and that look like sprites: up to the next
+    /// code or pointer, and no more than the largest sprite drawn.
+    pub sprite_bytes: usize,
+    pub sprite_count: usize,
+    pub warnings: Vec<String>,
+}
+
+fn opcode_at(rom: &[u8], address: usize) -> u16 {
+    let offset = address - PROGRAM_MEM_START;
+    u16::from_be_bytes([rom[offset], rom[offset + 1]])
+}
+
+impl RomInfo {
+    pub fn analyse(rom: &[u8], database: Option<&Database>) -> Self {
+        let sha1 = sha1_smol::Sha1::from(rom).digest().bytes();
+        let detection = detect::detect(rom);
+        let code = detect::trace(rom);
+        let end = PROGRAM_MEM_START + rom.len();
+
+        let mut opcodes = BTreeSet::new();
+        let mut occupied = [false; MEMORY_SIZE];
+        let mut sprites = BTreeSet::new();
+        let mut sprite_size = 0;
+        let mut invalid = Vec::new();
+        let mut outside = Vec::new();
+        for address in (0..MEMORY_SIZE).filter(|&a| code[a]) {
+            let opcode = opcode_at(rom, address);
+            let nnn = (opcode & 0x0FFF) as usize;
+            let Some(pattern) = detect::opcode_pattern(opcode) else {
+                invalid.push(address);
+                continue;
+            };
+            opcodes.insert(pattern);
+
+            let len = if opcode == 0xF000 { 4 } else { 2 };
+            for byte in &mut occupied[address..(address + len).min(MEMORY_SIZE)] {
+                *byte = true;
+            }
+            match pattern {
+                "1NNN" | "2NNN" if !(PROGRAM_MEM_START..end).contains(&nnn) => outside.push(address),
+                "ANNN" if (PROGRAM_MEM_START..end).contains(&nnn) => {
+                    sprites.insert(nnn);
+                },
+                "DXYN" => sprite_size = sprite_size.max((opcode & 0xF) as usize),
+                "DXY0" => sprite_size = LARGE_SPRITE_BYTES,
+                _ => {},
+            }
+        }
+        let code_bytes = occupied.iter().filter(|&&o| o).count();
+        if sprite_size == 0 {
+            sprite_size = MAX_SPRITE_BYTES;
+        }
+
+        let mut sprite_bytes = 0;
+        let mut sprite_count = 0;
+        for &start in &sprites {
+            let stop = (start + 1..end.min(MEMORY_SIZE))
+                .find(|&a| occupied[a] || sprites.contains(&a))
+                .unwrap_or(end);
+            if !occupied[start] {
+                sprite_bytes += (stop - start).min(sprite_size);
+                sprite_count += 1;
+            }
+        }
+
+        let entry_point = match rom {
+            [first, second, ..] if first >> 4 == 0x1 => u16::from_be_bytes([first & 0xF, *second]),
+            _ => PROGRAM_MEM_START as u16,
+        };
+
+        let mut warnings = Vec::new();
+        if rom.is_empty() {
+            warnings.push("ROM is empty".to_string());
+        }
+        if rom.len() > MAX_ROM_SIZE {
+            warnings.push(format!("ROM exceeds {} bytes", MAX_ROM_SIZE));
+        }
+        if detection.platform != Platform::Chip8 {
+            warnings.push(format!("uses {} opcodes, which aren't fully supported", detection.platform.name()));
+        }
+        if let Some(&address) = invalid.first() {
+            warnings.push(format!(
+                "{} reachable word(s) aren't instructions, the first at 0x{:03X}",
+                invalid.len(),
+                address
+            ));
+        }
+        for address in outside {
+            let target = opcode_at(rom, address) & 0x0FFF;
+            warnings.push(format!("0x{:03X} jumps to 0x{:03X}, outside the ROM", address, target));
+        }
+        if opcodes.contains("BNNN") {
+            warnings.push("computed jumps (BNNN) aren't followed, so some code may be missed".to_string());
+        }
+        if opcodes.contains("0NNN") {
+            warnings.push("calls to machine code (0NNN) are ignored".to_string());
+        }
+
+        Self {
+            size: rom.len(),
+            sha1,
+            title: database.and_then(|db| db.lookup(&sha1)).map(|profile| profile.title),
+            detection,
+            entry_point,
+            opcodes,
+            code_bytes,
+            sprite_bytes,
+            sprite_count,
+            warnings,
+        }
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Size:        {} bytes", self.size)?;
+        writeln!(f, "SHA-1:       {}", sha1_hex(&self.sha1))?;
+        if let Some(title) = &self.title {
+            writeln!(f, "Title:       {}", title)?;
+        }
+        writeln!(
+            f,
+            "Platform:    {} ({} confidence)",
+            self.detection.platform.name(),
+            self.detection.confidence.name()
+        )?;
+        if self.detection.quirks().display_wait {
+            writeln!(f, "Quirks:      try --quirks vip, as it draws without setting the delay timer")?;
+        }
+        writeln!(f, "Entry point: 0x{:03X}", self.entry_point)?;
+        writeln!(f, "Code:        {} bytes reachable", self.code_bytes)?;
+        writeln!(f, "Sprite data: ~{} bytes at {} address(es)", self.sprite_bytes, self.sprite_count)?;
+        let opcodes: Vec<&str> = self.opcodes.iter().copied().collect();
+        writeln!(f, "Opcodes:     {}", opcodes.join(" "))?;
+        for warning in &self.warnings {
+            writeln!(f, "warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
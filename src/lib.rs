@@ -1 +1,46 @@
-pub mod chip8;
\ No newline at end of file
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "std")]
+pub mod browser;
+#[cfg(feature = "std")]
+pub mod capture;
+pub mod chip8;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod coverage;
+#[cfg(feature = "std")]
+pub mod dap;
+#[cfg(feature = "std")]
+pub mod database;
+#[cfg(feature = "std")]
+pub mod debugger;
+pub mod detect;
+#[cfg(feature = "std")]
+pub mod disasm;
+#[cfg(feature = "std")]
+pub mod expr;
+#[cfg(feature = "std")]
+pub mod frontend;
+#[cfg(feature = "std")]
+pub mod gdb;
+#[cfg(feature = "std")]
+pub mod harness;
+#[cfg(feature = "std")]
+pub mod info;
+#[cfg(feature = "std")]
+pub mod profiler;
+pub mod quirks;
+pub mod rng;
+#[cfg(feature = "std")]
+pub mod runner;
+#[cfg(feature = "std")]
+pub mod scheduler;
+#[cfg(feature = "std")]
+pub mod symbols;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod watch;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
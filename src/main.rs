@@ -1,14 +1,54 @@
+use std::io;
+use std::path::{Path, PathBuf};
 
-use afoley_chip8::chip8::Chip8;
-use sdl2::pixels::PixelFormatEnum;
+use afoley_chip8::browser::RomBrowser;
+use afoley_chip8::chip8::{Chip8, KEYBOARD_SIZE, MEMORY_SIZE, VIDEO_HEIGHT, VIDEO_WIDTH};
+use afoley_chip8::cli::{self, RunArgs};
+use afoley_chip8::coverage::Coverage;
+use afoley_chip8::dap::DapServer;
+use afoley_chip8::debugger::Watch;
+use afoley_chip8::frontend::{AudioSink, Command, DisplaySink, InputSource, Keymap, Palette, Status, SystemClock};
+use afoley_chip8::runner::Runner;
+use afoley_chip8::scheduler::Speed;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use bytemuck;
-use clap::Parser;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
 
-#[derive(Debug, Parser)]
-struct Args {
-    #[arg(short, long)]
-    rom: String,
+const WINDOW_TITLE: &str = "Chip-8 Emulator";
+const WINDOW_WIDTH: u32 = 640;
+const WINDOW_HEIGHT: u32 = 320;
+
+const TEXT_SCALE: i32 = 2;
+const GLYPH_WIDTH: i32 = 5;
+const GLYPH_HEIGHT: i32 = 7;
+const TEXT_ADVANCE: i32 = (GLYPH_WIDTH + 1) * TEXT_SCALE;
+const LINE_HEIGHT: i32 = (GLYPH_HEIGHT + 2) * TEXT_SCALE;
+const TEXT_MARGIN: i32 = 8;
+
+// The memory heatmap is a square of one cell per address.
+const HEATMAP_SIZE: usize = 64;
+
+const BEEP_FREQUENCY: f32 = 440.0;
+const BEEP_VOLUME: f32 = 0.1;
+
+// Options only the window has.
+#[derive(Debug, clap::Args)]
+struct SdlArgs {
+    /// Serve the Debug Adapter Protocol over stdin and stdout, for editors.
+    /// The client's launch request picks the ROM
+    #[arg(long, conflicts_with = "gdb")]
+    dap: bool,
+
+    /// Save a PNG of the display to this path on exit
+    #[arg(long)]
+    screenshot: Option<PathBuf>,
+
+    /// Record from startup until exit. `.gif` and `.y4m` paths are written as
+    /// that format; any other path becomes a directory of PNG frames
+    #[arg(long)]
+    record: Option<PathBuf>,
 }
 
 fn map_sdl_keycode_to_chip8_key(keycode: sdl2::keyboard::Keycode) -> Option<usize> {
@@ -33,61 +73,395 @@ fn map_sdl_keycode_to_chip8_key(keycode: sdl2::keyboard::Keycode) -> Option<usiz
     }
 }
 
+fn map_sdl_keycode_to_command(keycode: sdl2::keyboard::Keycode) -> Option<Command> {
+    match keycode {
+        sdl2::keyboard::Keycode::Tab => Some(Command::ToggleTurbo),
+        sdl2::keyboard::Keycode::Backquote => Some(Command::ToggleSlowMotion),
+        sdl2::keyboard::Keycode::P => Some(Command::TogglePause),
+        sdl2::keyboard::Keycode::N => Some(Command::FrameAdvance),
+        sdl2::keyboard::Keycode::F5 => Some(Command::Reset),
+        sdl2::keyboard::Keycode::F6 => Some(Command::Reload),
+        sdl2::keyboard::Keycode::F2 => Some(Command::SaveState),
+        sdl2::keyboard::Keycode::F3 => Some(Command::LoadState),
+        sdl2::keyboard::Keycode::O => Some(Command::OpenBrowser),
+        sdl2::keyboard::Keycode::F12 => Some(Command::Screenshot),
+        sdl2::keyboard::Keycode::F9 => Some(Command::ToggleRecording),
+        sdl2::keyboard::Keycode::M => Some(Command::ToggleHeatmap),
+        sdl2::keyboard::Keycode::Escape => Some(Command::CloseBrowser),
+        sdl2::keyboard::Keycode::Return => Some(Command::BrowserSelect),
+        _ => None,
+    }
+}
 
+// The ROM's direction pad and buttons, from its database entry.
+fn map_sdl_keycode_to_pad(keycode: sdl2::keyboard::Keycode, keymap: &Keymap) -> Option<usize> {
+    match keycode {
+        sdl2::keyboard::Keycode::Up => keymap.up,
+        sdl2::keyboard::Keycode::Down => keymap.down,
+        sdl2::keyboard::Keycode::Left => keymap.left,
+        sdl2::keyboard::Keycode::Right => keymap.right,
+        sdl2::keyboard::Keycode::Space => keymap.a,
+        sdl2::keyboard::Keycode::LShift | sdl2::keyboard::Keycode::RShift => keymap.b,
+        _ => None,
+    }
+}
 
-fn main() -> Result<(), String> {
-    let args = Args::parse();
+// Browser navigation, which unlike the other hotkeys should auto-repeat.
+fn map_sdl_keycode_to_navigation(keycode: sdl2::keyboard::Keycode) -> Option<Command> {
+    match keycode {
+        sdl2::keyboard::Keycode::Up => Some(Command::BrowserPrevious),
+        sdl2::keyboard::Keycode::Down => Some(Command::BrowserNext),
+        _ => None,
+    }
+}
 
-    let sdl_context = sdl2::init()?;
-    let video_subsystem = sdl_context.video()?;
+// 5x7 glyphs for the ROM browser, one byte per row with the leftmost pixel
+// in bit 4. Lowercase letters are drawn as uppercase.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match c.to_ascii_uppercase() {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '>' => [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
 
-    let window = video_subsystem
-        .window("Chip-8 Emulator", 640, 320)
-        .position_centered()
-        .build()
-        .map_err(|e| e.to_string())?;
+fn draw_text(canvas: &mut Canvas<Window>, x: i32, y: i32, text: &str) -> Result<(), String> {
+    let mut pixels = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as i32 * TEXT_ADVANCE;
+        if left >= WINDOW_WIDTH as i32 {
+            break;
+        }
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0x10 >> col) != 0 {
+                    pixels.push(Rect::new(
+                        left + col * TEXT_SCALE,
+                        y + row as i32 * TEXT_SCALE,
+                        TEXT_SCALE as u32,
+                        TEXT_SCALE as u32,
+                    ));
+                }
+            }
+        }
+    }
+    canvas.fill_rects(&pixels)
+}
 
-    let mut canvas = window.into_canvas().present_vsync().build().map_err(|e| e.to_string())?;
-    let texture_creator = canvas.texture_creator();
+fn draw_browser(canvas: &mut Canvas<Window>, browser: &RomBrowser) -> Result<(), String> {
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
 
-    let mut texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::RGB888, 64u32, 32u32)
-        .map_err(|e| e.to_string())?;
+    canvas.set_draw_color(Color::WHITE);
+    draw_text(canvas, TEXT_MARGIN, TEXT_MARGIN, &format!("ROMS IN {}", browser.dir().display()))?;
+
+    if browser.entries().is_empty() {
+        return draw_text(canvas, TEXT_MARGIN, TEXT_MARGIN + 2 * LINE_HEIGHT, "NO ROMS FOUND");
+    }
+
+    let top = TEXT_MARGIN + 2 * LINE_HEIGHT;
+    let rows = ((WINDOW_HEIGHT as i32 - top - TEXT_MARGIN) / LINE_HEIGHT) as usize;
+    let offset = browser.scroll_offset(rows);
+
+    for (i, path) in browser.entries().iter().enumerate().skip(offset).take(rows) {
+        let y = top + (i - offset) as i32 * LINE_HEIGHT;
+        let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+
+        if i == browser.selected_index() {
+            canvas.set_draw_color(Color::WHITE);
+            canvas.fill_rect(Rect::new(0, y - TEXT_SCALE, WINDOW_WIDTH, LINE_HEIGHT as u32))?;
+            canvas.set_draw_color(Color::BLACK);
+        } else {
+            canvas.set_draw_color(Color::WHITE);
+        }
+        draw_text(canvas, TEXT_MARGIN, y, &name)?;
+    }
+
+    Ok(())
+}
+
+fn window_title(status: Status<'_>) -> String {
+    let mut title = WINDOW_TITLE.to_string();
+    if let Some(name) = status.rom.and_then(Path::file_name) {
+        title.push_str(&format!(" - {}", name.to_string_lossy()));
+    }
+    match status.speed {
+        Speed::Normal => {}
+        Speed::Turbo => title.push_str(" [turbo]"),
+        Speed::SlowMotion => title.push_str(" [slow]"),
+    }
+    if status.paused {
+        title.push_str(" [paused]");
+    }
+    if status.recording {
+        title.push_str(" [rec]");
+    }
+    title
+}
+
+struct SdlDisplay<'a> {
+    canvas: Canvas<Window>,
+    texture: Texture<'a>,
+    title: String,
+    palette: Palette,
+}
 
-    let mut chip8 = Chip8::load_rom(&args.rom);
+impl DisplaySink for SdlDisplay<'_> {
+    fn present(&mut self, chip8: &Chip8) -> Result<(), String> {
+        let pixels = chip8.video.map(|p| self.palette.color(p));
+        self.texture
+            .update(None, bytemuck::cast_slice(&pixels), VIDEO_WIDTH * 4)
+            .map_err(|e| e.to_string())?;
 
-    let mut event_pump = sdl_context.event_pump()?;
-    'running: loop {
-        for event in event_pump.poll_iter() {
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, Some(Rect::new(0, 0, WINDOW_WIDTH, WINDOW_HEIGHT)))?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn present_heatmap(&mut self, coverage: &Coverage) -> Result<(), String> {
+        let cell_width = WINDOW_WIDTH / HEATMAP_SIZE as u32;
+        let cell_height = WINDOW_HEIGHT / HEATMAP_SIZE as u32;
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        for address in 0..MEMORY_SIZE {
+            let color = coverage.color(address);
+            if color == 0 {
+                continue;
+            }
+            let x = (address % HEATMAP_SIZE) as u32 * cell_width;
+            let y = (address / HEATMAP_SIZE) as u32 * cell_height;
+            self.canvas.set_draw_color(Color::RGB((color >> 16) as u8, (color >> 8) as u8, color as u8));
+            self.canvas.fill_rect(Rect::new(x as i32, y as i32, cell_width, cell_height))?;
+        }
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn present_browser(&mut self, browser: &RomBrowser) -> Result<(), String> {
+        draw_browser(&mut self.canvas, browser)?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn set_status(&mut self, status: Status<'_>) -> Result<(), String> {
+        let title = window_title(status);
+        if title != self.title {
+            self.canvas.window_mut().set_title(&title).map_err(|e| e.to_string())?;
+            self.title = title;
+        }
+        Ok(())
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    // The window has no room for text beyond the browser, so messages and
+    // the watch list go to the terminal it was started from.
+    fn set_watches(&mut self, watches: &[Watch]) {
+        let line: Vec<String> = watches.iter().map(Watch::to_string).collect();
+        eprintln!("{}", line.join("  "));
+    }
+
+    fn notify(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+struct SdlInput {
+    event_pump: sdl2::EventPump,
+    keymap: Keymap,
+}
+
+impl InputSource for SdlInput {
+    fn poll(&mut self, keyboard: &mut [bool; KEYBOARD_SIZE], commands: &mut Vec<Command>) {
+        for event in self.event_pump.poll_iter() {
             match event {
-                sdl2::event::Event::Quit { .. } => break 'running,
-                sdl2::event::Event::KeyDown { keycode, .. } => {
-                    if let Some(keycode) = keycode {
-                        if let Some(chip8_key) = map_sdl_keycode_to_chip8_key(keycode) {
-                            chip8.keyboard[chip8_key] = true;
+                sdl2::event::Event::Quit { .. } => commands.push(Command::Quit),
+                sdl2::event::Event::DropFile { filename, .. } => commands.push(Command::Load(PathBuf::from(filename))),
+                sdl2::event::Event::KeyDown { keycode: Some(keycode), repeat, .. } => {
+                    let pad_key = map_sdl_keycode_to_pad(keycode, &self.keymap);
+                    if let Some(chip8_key) = map_sdl_keycode_to_chip8_key(keycode).or(pad_key) {
+                        keyboard[chip8_key] = true;
+                    }
+
+                    // The arrow keys navigate the ROM browser even when
+                    // they are bound to the pad.
+                    if let Some(command) = map_sdl_keycode_to_navigation(keycode) {
+                        commands.push(command);
+                    } else if let Some(command) = map_sdl_keycode_to_command(keycode) {
+                        if !repeat {
+                            commands.push(command);
                         }
                     }
                 },
-                sdl2::event::Event::KeyUp { keycode, .. } => {
-                    if let Some(keycode) = keycode {
-                        if let Some(chip8_key) = map_sdl_keycode_to_chip8_key(keycode) {
-                            chip8.keyboard[chip8_key] = false;
-                        }
+                sdl2::event::Event::KeyUp { keycode: Some(keycode), .. } => {
+                    let pad_key = map_sdl_keycode_to_pad(keycode, &self.keymap);
+                    if let Some(chip8_key) = map_sdl_keycode_to_chip8_key(keycode).or(pad_key) {
+                        keyboard[chip8_key] = false;
                     }
                 },
                 _ => {}
             }
         }
+    }
+
+    fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+}
+
+struct SquareWave {
+    phase: f32,
+    step: f32,
+}
 
-        chip8.cycle();
+impl AudioCallback for SquareWave {
+    type Channel = f32;
 
-        texture
-            .update(None, bytemuck::cast_slice(&chip8.video), 64 * 4)
-            .map_err(|e| e.to_string())?;
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.phase < 0.5 { BEEP_VOLUME } else { -BEEP_VOLUME };
+            self.phase = (self.phase + self.step) % 1.0;
+        }
+    }
+}
+
+// Audio is optional: machines without a sound device still get a picture.
+struct SdlAudio {
+    device: Option<AudioDevice<SquareWave>>,
+    beeping: bool,
+}
+
+impl SdlAudio {
+    fn open(sdl_context: &sdl2::Sdl) -> Self {
+        let desired = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = sdl_context
+            .audio()
+            .and_then(|audio| {
+                audio.open_playback(None, &desired, |spec| SquareWave {
+                    phase: 0.0,
+                    step: BEEP_FREQUENCY / spec.freq as f32,
+                })
+            })
+            .map_err(|e| eprintln!("audio disabled: {}", e))
+            .ok();
+        Self { device, beeping: false }
+    }
+}
+
+impl AudioSink for SdlAudio {
+    fn set_beeping(&mut self, beeping: bool) {
+        if beeping == self.beeping {
+            return;
+        }
+        self.beeping = beeping;
+        if let Some(device) = &self.device {
+            if beeping {
+                device.resume();
+            } else {
+                device.pause();
+            }
+        }
+    }
+}
+
+fn main() -> Result<(), String> {
+    cli::main(run)
+}
+
+fn run(args: RunArgs<SdlArgs>) -> Result<(), String> {
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+
+    let window = video_subsystem
+        .window(WINDOW_TITLE, WINDOW_WIDTH, WINDOW_HEIGHT)
+        .position_centered()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let texture_creator = canvas.texture_creator();
+
+    let texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB888, VIDEO_WIDTH as u32, VIDEO_HEIGHT as u32)
+        .map_err(|e| e.to_string())?;
+
+    let display = SdlDisplay {
+        canvas,
+        texture,
+        title: String::from(WINDOW_TITLE),
+        palette: Palette::default(),
+    };
+    let input = SdlInput {
+        event_pump: sdl_context.event_pump()?,
+        keymap: Keymap::default(),
+    };
+    let audio = SdlAudio::open(&sdl_context);
+
+    let mut runner = Runner::new(args.options()?, display, input, audio, SystemClock);
+    args.start(&mut runner, !args.frontend.dap)?;
+
+    if args.frontend.dap {
+        runner.attach_debugger(Box::new(DapServer::new(io::stdin(), io::stdout())));
+    }
+
+    if let Some(path) = &args.frontend.record {
+        runner.start_recording(path)?;
+    }
+
+    runner.run()?;
 
-        canvas.clear();
-        canvas.copy(&texture, None, Some(Rect::new(0, 0, 640, 320)))?;
-        canvas.present();
+    if let Some(path) = &args.frontend.screenshot {
+        runner.save_screenshot(path)?;
     }
 
     Ok(())
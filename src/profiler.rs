@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::chip8::{Chip8, MEMORY_SIZE, PROGRAM_MEM_START};
+use crate::symbols::Symbols;
+
+/// Instruction counts per address and per call stack, for finding where a
+/// ROM spends its time. Call `record` before each `Chip8::cycle`, or let the
+/// `Scheduler` do it with `run_frame_with`.
+///
+/// Stacks are read off the machine's own return addresses: each one follows
+/// the 2NNN that made the call, whose target names the subroutine. Code
+/// outside any subroutine belongs to the entry point, `PROGRAM_MEM_START`.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    counts: Vec<u64>,
+    /// Instructions run with exactly this call stack, outermost first
+    stacks: HashMap<Vec<u16>, u64>,
+    calls: HashMap<u16, u64>,
+    total: u64,
+    symbols: Symbols,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; MEMORY_SIZE],
+            stacks: HashMap::new(),
+            calls: HashMap::new(),
+            total: 0,
+            symbols: Symbols::default(),
+        }
+    }
+}
+
+/// Time spent in one subroutine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub address: u16,
+    /// Instructions run in the subroutine itself
+    pub self_count: u64,
+    /// Instructions run in it and everything it called
+    pub total_count: u64,
+    pub calls: u64,
+}
+
+/// The label the profiler gives a subroutine without symbols, matching
+/// `Disassembly`'s.
+pub fn function_name(address: u16) -> String {
+    if address as usize == PROGRAM_MEM_START {
+        "start".to_string()
+    } else {
+        format!("sub_{:03X}", address)
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+impl Profiler {
+    /// Names subroutines and addresses in reports after `symbols`, such as
+    /// `draw_player` and `draw_player+0x4`.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    fn name(&self, address: u16) -> String {
+        self.symbols.symbolize(address).unwrap_or_else(|| function_name(address))
+    }
+
+    /// Counts the instruction `chip8` is about to run.
+    pub fn record(&mut self, chip8: &Chip8) {
+        let pc = chip8.pc();
+        let memory = chip8.memory();
+        let word = |address: usize| u16::from_be_bytes([memory[address], memory[(address + 1) % MEMORY_SIZE]]);
+
+        let mut stack = vec![PROGRAM_MEM_START as u16];
+        stack.extend(chip8.stack().iter().map(|&ret| match word((ret + MEMORY_SIZE - 2) % MEMORY_SIZE) {
+            call if call >> 12 == 0x2 => call & 0x0FFF,
+            // Whatever made this call has since been overwritten.
+            _ => ret as u16,
+        }));
+
+        self.counts[pc] += 1;
+        *self.stacks.entry(stack).or_default() += 1;
+        let opcode = word(pc);
+        if opcode >> 12 == 0x2 {
+            *self.calls.entry(opcode & 0x0FFF).or_default() += 1;
+        }
+        self.total += 1;
+    }
+
+    /// Instructions recorded so far.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// How many times the instruction at `address` ran.
+    pub fn count(&self, address: usize) -> u64 {
+        self.counts.get(address).copied().unwrap_or(0)
+    }
+
+    /// Every subroutine that ran, busiest (by self count) first.
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let mut functions: BTreeMap<u16, FunctionProfile> = BTreeMap::new();
+        for (stack, &count) in &self.stacks {
+            let mut seen = Vec::new();
+            for &address in stack {
+                let function = functions.entry(address).or_insert(FunctionProfile {
+                    address,
+                    self_count: 0,
+                    total_count: 0,
+                    calls: self.calls.get(&address).copied().unwrap_or(0),
+                });
+                // Recursion mustn't count the same instructions twice.
+                if !seen.contains(&address) {
+                    function.total_count += count;
+                    seen.push(address);
+                }
+            }
+            if let Some(function) = stack.last().and_then(|a| functions.get_mut(a)) {
+                function.self_count += count;
+            }
+        }
+        let mut functions: Vec<FunctionProfile> = functions.into_values().collect();
+        functions.sort_by(|a, b| b.self_count.cmp(&a.self_count).then(a.address.cmp(&b.address)));
+        functions
+    }
+
+    /// The busiest instruction addresses with their counts, most first.
+    pub fn hot_addresses(&self, limit: usize) -> Vec<(u16, u64)> {
+        let mut addresses: Vec<(u16, u64)> = (0..MEMORY_SIZE)
+            .filter(|&a| self.counts[a] > 0)
+            .map(|a| (a as u16, self.counts[a]))
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addresses.truncate(limit);
+        addresses
+    }
+
+    /// A gprof-style table of subroutines, then the busiest addresses.
+    pub fn flat_profile(&self) -> String {
+        let mut out = String::from("  self%      self     total     calls  function\n");
+        for function in self.functions() {
+            let _ = writeln!(
+                out,
+                "{:6.2}% {:9} {:9} {:9}  {}",
+                percent(function.self_count, self.total),
+                function.self_count,
+                function.total_count,
+                function.calls,
+                self.name(function.address)
+            );
+        }
+        out.push_str("\n  count%     count  address\n");
+        for (address, count) in self.hot_addresses(20) {
+            let _ = write!(out, "{:7.2}% {:9}  0x{:03X}", percent(count, self.total), count, address);
+            let _ = match self.symbols.symbolize(address) {
+                Some(name) => writeln!(out, "  {}", name),
+                None => writeln!(out),
+            };
+        }
+        out
+    }
+
+    /// Each call path with the share of instructions spent in it and below,
+    /// callees indented under their callers.
+    pub fn call_tree(&self) -> String {
+        // Inclusive counts for every prefix of every stack.
+        let mut paths: BTreeMap<&[u16], u64> = BTreeMap::new();
+        for (stack, &count) in &self.stacks {
+            for depth in 1..=stack.len() {
+                *paths.entry(&stack[..depth]).or_default() += count;
+            }
+        }
+        let mut out = String::new();
+        for (path, count) in paths {
+            let _ = writeln!(
+                out,
+                "{:6.2}% {:9}  {:indent$}{}",
+                percent(count, self.total),
+                count,
+                "",
+                self.name(*path.last().unwrap()),
+                indent = (path.len() - 1) * 2
+            );
+        }
+        out
+    }
+
+    /// One line per call stack, `start;sub_2A0;sub_3C4 1234`, as
+    /// flamegraph.pl, inferno and speedscope read.
+    pub fn collapsed_stacks(&self) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(stack, count)| {
+                let names: Vec<String> = stack.iter().map(|&a| self.name(a)).collect();
+                format!("{} {}", names.join(";"), count)
+            })
+            .collect();
+        lines.sort();
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// Writes collapsed stacks if `path` ends in `.folded`, else the flat
+    /// profile followed by the call tree.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = match path.extension().and_then(|e| e.to_str()) {
+            Some("folded") => self.collapsed_stacks(),
+            _ => format!(
+                "{} instructions\n\nFlat profile:\n\n{}\nCall tree:\n\n{}",
+                self.total,
+                self.flat_profile(),
+                self.call_tree()
+            ),
+        };
+        std::fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,47 @@
+/// Behaviours that differ between CHIP-8 interpreters. The default is the
+/// reference behaviour; each flag switches to a variant some ROMs expect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks {
+    /// 8XY4-8XYE write VF before VX, so when X is F the result replaces
+    /// the flag. This emulator behaved this way until the flag order was fixed.
+    pub flag_overwritten: bool,
+    /// FX0A finishes as soon as a key is down instead of waiting for it to
+    /// be released, so a held key repeats through menus.
+    pub fx0a_on_press: bool,
+    /// DXYN stalls the CPU until the next 60 Hz frame after drawing, as the
+    /// COSMAC VIP did while waiting for vertical blank. Limits games to one
+    /// sprite per frame, which some rely on for their speed.
+    pub display_wait: bool,
+}
+
+/// Names accepted by `Quirks::from_profile`.
+pub const PROFILES: [&str; 3] = ["modern", "vip", "legacy"];
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub fn cosmac_vip() -> Self {
+        Self {
+            display_wait: true,
+            ..Self::default()
+        }
+    }
+
+    /// This emulator before its quirks were made configurable.
+    pub fn legacy() -> Self {
+        Self {
+            flag_overwritten: true,
+            fx0a_on_press: true,
+            ..Self::default()
+        }
+    }
+
+    /// Looks up one of `PROFILES`; `modern` is the default.
+    pub fn from_profile(name: &str) -> Option<Self> {
+        match name {
+            "modern" => Some(Self::default()),
+            "vip" => Some(Self::cosmac_vip()),
+            "legacy" => Some(Self::legacy()),
+            _ => None,
+        }
+    }
+}
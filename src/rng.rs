@@ -0,0 +1,52 @@
+/// Where CXKK gets its random bytes from.
+///
+/// The built-in generator is a seedable xorshift whose state is part of the
+/// `Chip8`, so save states and test runs replay the same numbers. Boards
+/// with a hardware RNG can plug it in with `Rng::from_fn` instead.
+#[derive(Debug, Clone, Copy)]
+pub struct Rng(Source);
+
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    Xorshift(u32),
+    Function(fn() -> u8),
+}
+
+impl Rng {
+    /// A deterministic generator. Equal seeds give equal byte sequences.
+    pub fn seeded(seed: u32) -> Self {
+        // Xorshift gets stuck on a zero state.
+        Self(Source::Xorshift(if seed == 0 { 0x9E37_79B9 } else { seed }))
+    }
+
+    /// Takes every byte from `f`, e.g. a hardware RNG register read.
+    pub fn from_fn(f: fn() -> u8) -> Self {
+        Self(Source::Function(f))
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        match &mut self.0 {
+            Source::Xorshift(state) => {
+                *state ^= *state << 13;
+                *state ^= *state >> 17;
+                *state ^= *state << 5;
+                (*state >> 24) as u8
+            },
+            Source::Function(f) => f(),
+        }
+    }
+}
+
+/// Seeded from the OS with `std`; without it, a fixed seed, so boards
+/// without an entropy source should inject one.
+impl Default for Rng {
+    #[cfg(feature = "std")]
+    fn default() -> Self {
+        Self::seeded(rand::random())
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn default() -> Self {
+        Self::seeded(0x2A2A_2A2A)
+    }
+}
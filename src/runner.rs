@@ -0,0 +1,611 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::browser::RomBrowser;
+use crate::capture::{self, Recorder};
+use crate::chip8::{Chip8, RomTooLarge, MAX_ROM_SIZE};
+use crate::coverage::Coverage;
+use crate::database::{Database, RomProfile, SUPPORTED_PLATFORMS};
+use crate::debugger::{DebugServer, Debugger, Watch};
+use crate::detect::{self, Confidence, Platform};
+use crate::expr::Expr;
+use crate::frontend::{AudioSink, Command, DisplaySink, InputSource, Status, TimeSource};
+use crate::profiler::Profiler;
+use crate::quirks::Quirks;
+use crate::scheduler::{Scheduler, DEFAULT_CYCLES_PER_FRAME};
+use crate::symbols::Symbols;
+use crate::trace::Tracer;
+use crate::watch::RomWatcher;
+
+#[derive(Debug, Clone)]
+pub struct RunnerOptions {
+    /// Instructions per frame. `None` takes the speed from the ROM's
+    /// database entry, or `DEFAULT_CYCLES_PER_FRAME`.
+    pub cycles_per_frame: Option<u32>,
+    /// `None` takes the quirks from the ROM's database entry, or those
+    /// `detect` suggests for ROMs it doesn't list.
+    pub quirks: Option<Quirks>,
+    /// Looked up by SHA-1 whenever a ROM loads
+    pub database: Option<Database>,
+    /// Directory listed by the ROM browser
+    pub rom_dir: PathBuf,
+    /// Reload the ROM whenever the file changes on disk
+    pub watch: bool,
+    /// After a watched reload, restore the quick save state on top of the
+    /// new ROM instead of restarting
+    pub watch_restore: bool,
+    pub capture_dir: PathBuf,
+    pub capture_scale: u32,
+    /// Record coverage from startup and merge it into this file when the
+    /// runner finishes
+    pub coverage: Option<PathBuf>,
+    /// Profile from startup and write the report here when the runner
+    /// finishes (see `Profiler::save`)
+    pub profile: Option<PathBuf>,
+    /// Names for the profile and trace to use instead of bare addresses
+    pub symbols: Option<Symbols>,
+    /// Expressions to show while running, re-evaluated every frame and
+    /// after every debugger step
+    pub watches: Vec<Expr>,
+}
+
+impl Default for RunnerOptions {
+    fn default() -> Self {
+        Self {
+            cycles_per_frame: None,
+            quirks: None,
+            database: None,
+            rom_dir: PathBuf::from("roms"),
+            watch: false,
+            watch_restore: false,
+            capture_dir: PathBuf::from("."),
+            capture_scale: capture::DEFAULT_SCALE,
+            coverage: None,
+            profile: None,
+            symbols: None,
+            watches: Vec::new(),
+        }
+    }
+}
+
+/// The emulator main loop, shared by every frontend. Each `step` polls
+/// input, handles commands, runs whatever frames are due and presents the
+/// result.
+pub struct Runner<D, I, A, T> {
+    options: RunnerOptions,
+    chip8: Chip8,
+    rom: Option<Vec<u8>>,
+    rom_path: Option<PathBuf>,
+    scheduler: Scheduler,
+    paused: bool,
+    saved: Option<Chip8>,
+    watcher: Option<RomWatcher>,
+    recorder: Option<Recorder>,
+    browser: Option<RomBrowser>,
+    heatmap: bool,
+    profiler: Option<Profiler>,
+    tracer: Option<Tracer<BufWriter<File>>>,
+    debugger: Debugger,
+    debug_server: Option<Box<dyn DebugServer>>,
+    watches: Vec<Watch>,
+    commands: Vec<Command>,
+    display: D,
+    input: I,
+    audio: A,
+    clock: T,
+}
+
+// Restarts the machine with `rom`, keeping the keys that are currently held,
+// the quirks it runs with and the coverage recorded so far.
+fn restart(chip8: &Chip8, rom: &[u8]) -> Chip8 {
+    let mut restarted = Chip8::from_bytes(rom);
+    restarted.keyboard = chip8.keyboard;
+    restarted.set_quirks(chip8.quirks());
+    restarted.set_coverage(chip8.coverage().copied());
+    restarted
+}
+
+// Like `restart`, but lands back at `saved` with the new program image.
+fn restore(chip8: &Chip8, rom: &[u8], saved: &Chip8) -> Result<Chip8, RomTooLarge> {
+    let mut restored = saved.clone();
+    restored.patch_rom(rom)?;
+    restored.keyboard = chip8.keyboard;
+    restored.set_coverage(chip8.coverage().copied());
+    Ok(restored)
+}
+
+fn check_rom_size(rom: &[u8]) -> Result<(), String> {
+    match rom.len() {
+        len if len > MAX_ROM_SIZE => Err(RomTooLarge { len }.to_string()),
+        _ => Ok(()),
+    }
+}
+
+fn read_rom(path: &Path) -> Result<Vec<u8>, String> {
+    let rom = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    check_rom_size(&rom).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(rom)
+}
+
+impl<D, I, A, T> Runner<D, I, A, T>
+where
+    D: DisplaySink,
+    I: InputSource,
+    A: AudioSink,
+    T: TimeSource,
+{
+    pub fn new(options: RunnerOptions, display: D, input: I, audio: A, clock: T) -> Self {
+        let cycles_per_frame = options.cycles_per_frame.unwrap_or(DEFAULT_CYCLES_PER_FRAME);
+        let scheduler = Scheduler::new(cycles_per_frame, clock.now());
+        let mut chip8 = Chip8::new();
+        chip8.set_quirks(options.quirks.unwrap_or_default());
+        if options.coverage.is_some() {
+            chip8.set_coverage(Some(Coverage::default()));
+        }
+        let profiler = options.profile.as_ref().map(|_| {
+            let mut profiler = Profiler::default();
+            profiler.set_symbols(options.symbols.clone().unwrap_or_default());
+            profiler
+        });
+        let watches = options.watches.iter().cloned().map(Watch::new).collect();
+        Self {
+            options,
+            chip8,
+            rom: None,
+            rom_path: None,
+            scheduler,
+            paused: false,
+            saved: None,
+            watcher: None,
+            recorder: None,
+            browser: None,
+            heatmap: false,
+            profiler,
+            tracer: None,
+            debugger: Debugger::default(),
+            debug_server: None,
+            watches,
+            commands: Vec::new(),
+            display,
+            input,
+            audio,
+            clock,
+        }
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    pub fn display(&self) -> &D {
+        &self.display
+    }
+
+    pub fn display_mut(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    pub fn rom_path(&self) -> Option<&Path> {
+        self.rom_path.as_deref()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Loads a ROM from disk and starts it from the beginning.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let rom = read_rom(path)?;
+        if self.rom_path.as_deref() != Some(path) {
+            self.saved = None;
+            if self.options.watch {
+                self.watcher = Some(RomWatcher::new(path, self.clock.now()));
+            }
+        }
+        self.rom_path = Some(path.to_path_buf());
+        self.load_bytes(rom)
+    }
+
+    /// Starts `rom` from the beginning without associating it with a file.
+    /// A ROM too big for memory leaves the current one running.
+    pub fn load_bytes(&mut self, rom: Vec<u8>) -> Result<(), String> {
+        check_rom_size(&rom)?;
+        self.chip8 = restart(&self.chip8, &rom);
+        self.apply_profile(&rom);
+        self.rom = Some(rom);
+        self.browser = None;
+        self.scheduler.resync(self.clock.now());
+        Ok(())
+    }
+
+    // Sets speed, quirks, colours and keys for the loaded ROM from its
+    // database entry, unless the options pin them. ROMs the database doesn't
+    // know get the quirks `detect` suggests, if it's reasonably sure.
+    fn apply_profile(&mut self, rom: &[u8]) {
+        let sha1 = self.chip8.rom_sha1();
+        let profile = self.options.database.as_ref().and_then(|db| db.lookup(&sha1));
+        let profile = match profile {
+            Some(profile) => profile,
+            None if self.options.quirks.is_none() => self.detect_profile(rom),
+            None => RomProfile::default(),
+        };
+
+        let cycles_per_frame = self.options.cycles_per_frame.or(profile.cycles_per_frame);
+        self.scheduler.set_cycles_per_frame(cycles_per_frame.unwrap_or(DEFAULT_CYCLES_PER_FRAME));
+        self.chip8.set_quirks(self.options.quirks.or(profile.quirks).unwrap_or_default());
+        self.display.set_palette(profile.palette.unwrap_or_default());
+        self.input.set_keymap(profile.keymap.unwrap_or_default());
+
+        if !profile.title.is_empty() {
+            let mut message = format!("identified {}", profile.title);
+            if let Some(platform) = profile.platform {
+                if !SUPPORTED_PLATFORMS.contains(&platform.as_str()) {
+                    message.push_str(&format!(" ({} isn't fully supported)", platform));
+                }
+            }
+            self.display.notify(&message);
+        }
+    }
+
+    fn detect_profile(&mut self, rom: &[u8]) -> RomProfile {
+        let detection = detect::detect(rom);
+        if detection.platform != Platform::Chip8 {
+            self.display.notify(&format!(
+                "looks like {} ({} confidence), which isn't fully supported",
+                detection.platform.name(),
+                detection.confidence.name()
+            ));
+        }
+        RomProfile {
+            platform: Some(detection.platform.database_id().to_string()),
+            // Only a sure guess changes how the ROM runs; `info` reports the rest.
+            quirks: (detection.confidence >= Confidence::High).then(|| detection.quirks()),
+            ..RomProfile::default()
+        }
+    }
+
+    pub fn open_browser(&mut self) -> Result<(), String> {
+        let browser = RomBrowser::open(&self.options.rom_dir)
+            .map_err(|e| format!("{}: {}", self.options.rom_dir.display(), e))?;
+        self.browser = Some(browser);
+        Ok(())
+    }
+
+    pub fn start_recording(&mut self, path: &Path) -> Result<(), String> {
+        let recorder = Recorder::create(path, self.options.capture_scale)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            match recorder.finish() {
+                Ok(path) => self.display.notify(&format!("saved recording to {}", path.display())),
+                Err(e) => self.display.notify(&format!("failed to finish recording: {}", e)),
+            }
+        }
+    }
+
+    /// Writes every instruction run from now on to `path`, see `Tracer`.
+    pub fn start_trace(&mut self, path: &Path) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let symbols = self.options.symbols.clone().unwrap_or_default();
+        self.tracer = Some(Tracer::new(BufWriter::new(file), symbols));
+        Ok(())
+    }
+
+    /// Flushes the trace, if one is running, and stops it.
+    pub fn stop_trace(&mut self) -> Result<(), String> {
+        match self.tracer.take() {
+            Some(tracer) => tracer.finish().map(drop).map_err(|e| format!("failed to finish trace: {}", e)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn save_screenshot(&mut self, path: &Path) -> Result<(), String> {
+        capture::save_png(path, &self.chip8.video, self.options.capture_scale)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn capture_path(&self, extension: &str) -> PathBuf {
+        let stem = self
+            .rom_path
+            .as_deref()
+            .and_then(Path::file_stem)
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "chip8".to_string());
+        capture::capture_path(&self.options.capture_dir, &stem, extension)
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn watches(&self) -> &[Watch] {
+        &self.watches
+    }
+
+    pub fn add_watch(&mut self, expr: Expr) {
+        self.watches.push(Watch::new(expr));
+    }
+
+    /// Hands execution control to a remote debugger, such as a
+    /// `GdbServer`, which is polled every step from now on.
+    pub fn attach_debugger(&mut self, server: Box<dyn DebugServer>) {
+        self.debug_server = Some(server);
+    }
+
+    /// Runs one 60 Hz frame and hands the result to the recorder, if any.
+    /// The frame ends early if the debugger pauses at a breakpoint.
+    pub fn run_frame(&mut self) {
+        let (debugger, profiler, tracer) = (&mut self.debugger, &mut self.profiler, &mut self.tracer);
+        let mut trace_error = None;
+        self.scheduler.run_frame_until(&mut self.chip8, |chip8| {
+            if debugger.should_stop(chip8) {
+                return true;
+            }
+            if let Some(profiler) = profiler.as_mut() {
+                profiler.record(chip8);
+            }
+            if let Some(Err(e)) = tracer.as_mut().map(|tracer| tracer.record(chip8)) {
+                *tracer = None;
+                trace_error = Some(e);
+            }
+            false
+        });
+        if let Some(e) = trace_error {
+            self.display.notify(&format!("trace stopped: {}", e));
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.record_frame(&self.chip8.video) {
+                let message = format!("recording to {} stopped: {}", recorder.path().display(), e);
+                self.recorder = None;
+                self.display.notify(&message);
+            }
+        }
+    }
+
+    fn handle(&mut self, command: Command) {
+        let now = self.clock.now();
+        match command {
+            Command::Quit => {},
+            Command::TogglePause => {
+                self.paused = !self.paused;
+                self.scheduler.resync(now);
+            },
+            Command::FrameAdvance => {
+                if self.paused && self.rom.is_some() && self.browser.is_none() {
+                    self.run_frame();
+                }
+            },
+            Command::ToggleTurbo => self.scheduler.toggle_turbo(now),
+            Command::ToggleSlowMotion => self.scheduler.toggle_slow_motion(now),
+            Command::Reset => {
+                if let Some(rom) = &self.rom {
+                    self.chip8 = restart(&self.chip8, rom);
+                }
+            },
+            Command::Reload => {
+                if let Some(path) = self.rom_path.clone() {
+                    self.load_or_notify(&path);
+                }
+            },
+            Command::Load(path) => self.load_or_notify(&path),
+            Command::SaveState => self.saved = Some(self.chip8.clone()),
+            Command::LoadState => {
+                if let Some(state) = &self.saved {
+                    let keyboard = self.chip8.keyboard;
+                    let coverage = self.chip8.coverage().copied();
+                    self.chip8 = state.clone();
+                    self.chip8.keyboard = keyboard;
+                    self.chip8.set_coverage(coverage);
+                }
+            },
+            Command::OpenBrowser => {
+                if let Err(e) = self.open_browser() {
+                    self.display.notify(&e);
+                }
+            },
+            Command::CloseBrowser => {
+                if self.rom.is_some() {
+                    self.browser = None;
+                    self.scheduler.resync(now);
+                }
+            },
+            Command::BrowserNext => {
+                if let Some(browser) = self.browser.as_mut() {
+                    browser.select_next();
+                }
+            },
+            Command::BrowserPrevious => {
+                if let Some(browser) = self.browser.as_mut() {
+                    browser.select_previous();
+                }
+            },
+            Command::BrowserSelect => {
+                if let Some(path) = self.browser.as_ref().and_then(|b| b.selected()).map(Path::to_path_buf) {
+                    self.load_or_notify(&path);
+                }
+            },
+            Command::Screenshot => {
+                let path = self.capture_path("png");
+                match self.save_screenshot(&path) {
+                    Ok(()) => self.display.notify(&format!("saved screenshot to {}", path.display())),
+                    Err(e) => self.display.notify(&format!("failed to save {}", e)),
+                }
+            },
+            Command::ToggleHeatmap => {
+                self.heatmap = !self.heatmap;
+                if self.heatmap && self.chip8.coverage().is_none() {
+                    self.chip8.set_coverage(Some(Coverage::default()));
+                }
+            },
+            Command::ToggleRecording => {
+                if self.recorder.is_some() {
+                    self.stop_recording();
+                } else {
+                    let path = self.capture_path("gif");
+                    if let Err(e) = self.start_recording(&path) {
+                        self.display.notify(&format!("failed to record to {}", e));
+                    }
+                }
+            },
+        }
+    }
+
+    fn load_or_notify(&mut self, path: &Path) {
+        if let Err(e) = self.load(path) {
+            self.display.notify(&format!("failed to load {}", e));
+        }
+    }
+
+    fn poll_watcher(&mut self) {
+        let Some(watcher) = self.watcher.as_mut() else { return };
+        if !watcher.poll(self.clock.now()) {
+            return;
+        }
+
+        let path = watcher.path().to_path_buf();
+        let reloaded = read_rom(&path).and_then(|rom| {
+            let chip8 = match (&self.saved, self.options.watch_restore) {
+                (Some(state), true) => restore(&self.chip8, &rom, state).map_err(|e| format!("{}: {}", path.display(), e))?,
+                _ => restart(&self.chip8, &rom),
+            };
+            Ok((chip8, rom))
+        });
+        match reloaded {
+            Ok((chip8, rom)) => {
+                self.chip8 = chip8;
+                self.rom = Some(rom);
+                self.display.notify(&format!("reloaded {}", path.display()));
+            },
+            Err(e) => self.display.notify(&format!("failed to reload {}", e)),
+        }
+    }
+
+    fn poll_debugger(&mut self, commands: &mut Vec<Command>) {
+        let Some(server) = self.debug_server.as_mut() else { return };
+        let was_paused = self.debugger.is_paused();
+        if let Err(e) = server.poll(&mut self.chip8, &mut self.debugger, commands) {
+            self.debug_server = None;
+            self.debugger = Debugger::default();
+            self.display.notify(&format!("debugger stopped: {}", e));
+        }
+        if was_paused && !self.debugger.is_paused() {
+            self.scheduler.resync(self.clock.now());
+        }
+    }
+
+    /// Runs one iteration of the main loop. Returns `Ok(false)` once the
+    /// frontend asked to quit.
+    pub fn step(&mut self) -> Result<bool, String> {
+        let mut commands = std::mem::take(&mut self.commands);
+        self.input.poll(&mut self.chip8.keyboard, &mut commands);
+        self.poll_debugger(&mut commands);
+
+        let mut running = true;
+        for command in commands.drain(..) {
+            if command == Command::Quit {
+                running = false;
+            }
+            self.handle(command);
+        }
+        self.commands = commands;
+
+        if !running {
+            self.audio.set_beeping(false);
+            return Ok(false);
+        }
+
+        self.poll_watcher();
+        let status = Status {
+            rom: self.rom_path.as_deref(),
+            speed: self.scheduler.speed(),
+            paused: self.paused || self.debugger.is_paused(),
+            recording: self.recorder.is_some(),
+        };
+        self.display.set_status(status)?;
+
+        if let Some(browser) = &self.browser {
+            self.audio.set_beeping(false);
+            self.display.present_browser(browser)?;
+            self.clock.sleep(self.scheduler.frame_duration());
+            return Ok(true);
+        }
+
+        let emulating = !self.paused && !self.debugger.is_paused() && self.rom.is_some();
+        if emulating {
+            for _ in 0..self.scheduler.frames_due(self.clock.now()) {
+                self.run_frame();
+                if self.debugger.is_paused() {
+                    break;
+                }
+            }
+        }
+
+        let changed = self.watches.iter_mut().fold(false, |changed, watch| watch.update(&self.chip8) | changed);
+        if changed {
+            self.display.set_watches(&self.watches);
+        }
+
+        self.audio.set_beeping(emulating && self.chip8.is_beeping());
+        match self.chip8.coverage() {
+            Some(coverage) if self.heatmap => self.display.present_heatmap(coverage)?,
+            _ => self.display.present(&self.chip8)?,
+        }
+
+        if emulating {
+            self.clock.sleep(self.scheduler.time_until_next_frame(self.clock.now()));
+        } else {
+            self.clock.sleep(self.scheduler.frame_duration());
+        }
+
+        Ok(true)
+    }
+
+    /// Steps until the frontend quits, then finishes any recording and
+    /// trace and saves coverage and the profile.
+    pub fn run(&mut self) -> Result<(), String> {
+        while self.step()? {}
+        self.stop_recording();
+        self.stop_trace()?;
+        self.save_coverage()?;
+        self.save_profile()
+    }
+
+    /// Merges the coverage recorded so far into the `coverage` file from
+    /// the options, if there is one.
+    pub fn save_coverage(&mut self) -> Result<(), String> {
+        match (&self.options.coverage, self.chip8.coverage()) {
+            (Some(path), Some(coverage)) => coverage.save_merged(path),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the profile so far to the `profile` file from the options, if
+    /// there is one.
+    pub fn save_profile(&mut self) -> Result<(), String> {
+        match (&self.options.profile, &self.profiler) {
+            (Some(path), Some(profiler)) => profiler.save(path),
+            _ => Ok(()),
+        }
+    }
+}
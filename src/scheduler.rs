@@ -0,0 +1,148 @@
+use std::time::{Duration, Instant};
+
+use crate::chip8::Chip8;
+
+pub const FRAME_RATE: u32 = 60;
+pub const DEFAULT_CYCLES_PER_FRAME: u32 = 10;
+
+const SLOW_MOTION_FACTOR: u32 = 4;
+const TURBO_FRAMES_PER_UPDATE: u32 = 20;
+const MAX_CATCH_UP_FRAMES: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    Normal,
+    Turbo,
+    SlowMotion,
+}
+
+/// Fixed-timestep scheduler: runs `cycles_per_frame` instructions and one
+/// timer tick per 60 Hz frame, regardless of how often the frontend renders.
+#[derive(Debug)]
+pub struct Scheduler {
+    cycles_per_frame: u32,
+    speed: Speed,
+    next_frame: Instant,
+}
+
+impl Scheduler {
+    pub fn new(cycles_per_frame: u32, now: Instant) -> Self {
+        Self {
+            cycles_per_frame,
+            speed: Speed::Normal,
+            next_frame: now,
+        }
+    }
+
+    pub fn cycles_per_frame(&self) -> u32 {
+        self.cycles_per_frame
+    }
+
+    pub fn set_cycles_per_frame(&mut self, cycles_per_frame: u32) {
+        self.cycles_per_frame = cycles_per_frame;
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: Speed, now: Instant) {
+        self.speed = speed;
+        self.resync(now);
+    }
+
+    /// Forgets any accumulated time, e.g. after the emulator was paused.
+    pub fn resync(&mut self, now: Instant) {
+        self.next_frame = now;
+    }
+
+    pub fn toggle_turbo(&mut self, now: Instant) {
+        let speed = if self.speed == Speed::Turbo { Speed::Normal } else { Speed::Turbo };
+        self.set_speed(speed, now);
+    }
+
+    pub fn toggle_slow_motion(&mut self, now: Instant) {
+        let speed = if self.speed == Speed::SlowMotion { Speed::Normal } else { Speed::SlowMotion };
+        self.set_speed(speed, now);
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        let frame = Duration::from_secs(1) / FRAME_RATE;
+        match self.speed {
+            Speed::SlowMotion => frame * SLOW_MOTION_FACTOR,
+            _ => frame,
+        }
+    }
+
+    /// Returns how many frames should be emulated at `now`. If the frontend
+    /// fell far behind (e.g. the window was dragged), the backlog is dropped
+    /// instead of fast-forwarding to catch up.
+    pub fn frames_due(&mut self, now: Instant) -> u32 {
+        if self.speed == Speed::Turbo {
+            self.next_frame = now;
+            return TURBO_FRAMES_PER_UPDATE;
+        }
+
+        if now < self.next_frame {
+            return 0;
+        }
+
+        let frame = self.frame_duration();
+        let behind = (now - self.next_frame).as_nanos() / frame.as_nanos();
+
+        if behind >= MAX_CATCH_UP_FRAMES as u128 {
+            self.next_frame = now + frame;
+            return MAX_CATCH_UP_FRAMES;
+        }
+
+        let due = behind as u32 + 1;
+        self.next_frame += frame * due;
+        due
+    }
+
+    pub fn time_until_next_frame(&self, now: Instant) -> Duration {
+        self.next_frame.saturating_duration_since(now)
+    }
+
+    /// Runs up to `cycles_per_frame` instructions, fewer if a draw waits for
+    /// vertical blank, then ends the frame with a timer tick.
+    pub fn run_frame(&self, chip8: &mut Chip8) {
+        run_frame(chip8, self.cycles_per_frame);
+    }
+
+    /// Like `run_frame`, calling `before_cycle` ahead of each instruction,
+    /// e.g. to profile it.
+    pub fn run_frame_with(&self, chip8: &mut Chip8, mut before_cycle: impl FnMut(&Chip8)) {
+        self.run_frame_until(chip8, |chip8| {
+            before_cycle(chip8);
+            false
+        });
+    }
+
+    /// Like `run_frame`, but stops ahead of the first instruction `stop`
+    /// returns true for, e.g. at a breakpoint. A stopped frame doesn't end,
+    /// so the timers don't tick. Returns whether it stopped.
+    pub fn run_frame_until(&self, chip8: &mut Chip8, stop: impl FnMut(&Chip8) -> bool) -> bool {
+        run_frame_until(chip8, self.cycles_per_frame, stop)
+    }
+}
+
+/// One frame as `Scheduler::run_frame` runs it, for callers that pace frames
+/// themselves, such as a web page without a usable `Instant`.
+pub fn run_frame(chip8: &mut Chip8, cycles_per_frame: u32) {
+    run_frame_until(chip8, cycles_per_frame, |_| false);
+}
+
+fn run_frame_until(chip8: &mut Chip8, cycles_per_frame: u32, mut stop: impl FnMut(&Chip8) -> bool) -> bool {
+    for _ in 0..cycles_per_frame {
+        if chip8.is_waiting_for_vblank() {
+            break;
+        }
+        if stop(chip8) {
+            return true;
+        }
+        chip8.cycle();
+    }
+    chip8.tick_timers();
+    false
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::chip8::{MEMORY_SIZE, PROGRAM_MEM_START};
+
+const FILE_MAGIC: &str = "chip8-symbols";
+const FILE_VERSION: u32 = 1;
+
+/// A line of assembler source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u32,
+}
+
+/// What an assembler knows about a ROM that the ROM itself doesn't say:
+/// the names of its labels, and which source line each address came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+    lines: BTreeMap<u16, SourceLine>,
+}
+
+// Whether `path`, as a client spells it, names `file` from a symbol file,
+// which is usually relative to wherever the assembler ran.
+fn same_file(path: &str, file: &str) -> bool {
+    let (path, file) = (Path::new(path), Path::new(file));
+    path == file || path.ends_with(file) || file.ends_with(path)
+}
+
+// A number as Octo writes one: decimal, `0x` hex or `0b` binary.
+fn parse_octo_number(text: &str) -> Option<i64> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+impl Symbols {
+    /// The symbol file format, version 1: a `chip8-symbols 1` header line,
+    /// then one record per line, with addresses in hex:
+    ///
+    /// - `label <address> <name>`, such as `label 2A0 draw_player`
+    /// - `line <address> <line> <file>`, such as `line 2A4 17 src/player.8o`,
+    ///   for the source line the code at and after the address came from
+    ///
+    /// Blank lines and lines starting with `#` are ignored. Files with a
+    /// newer version are refused rather than half understood.
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+        let header = lines.next().map(|(_, line)| line).unwrap_or_default();
+        match header.strip_prefix(FILE_MAGIC).map(str::trim) {
+            Some(version) if version == FILE_VERSION.to_string() => {},
+            Some(version) if !version.is_empty() => return Err(format!("line 1: unsupported version {}", version)),
+            _ => return Err(format!("line 1: expected `{} {}`", FILE_MAGIC, FILE_VERSION)),
+        }
+
+        let mut symbols = Symbols::default();
+        for (number, line) in lines.filter(|(_, line)| !line.is_empty() && !line.starts_with('#')) {
+            let error = |message: &str| format!("line {}: {}", number, message);
+            let mut fields = line.splitn(4, ' ');
+            let kind = fields.next().unwrap_or_default();
+            let address = fields
+                .next()
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                .filter(|&a| (a as usize) < MEMORY_SIZE);
+            match kind {
+                "label" => {
+                    let address = address.ok_or_else(|| error("expected an address"))?;
+                    let name = fields.next().filter(|n| !n.is_empty() && fields.next().is_none());
+                    let name = name.ok_or_else(|| error("expected a name without spaces"))?;
+                    symbols.add_label(address, name);
+                },
+                "line" => {
+                    let address = address.ok_or_else(|| error("expected an address"))?;
+                    let line = fields.next().and_then(|n| n.parse().ok()).ok_or_else(|| error("expected a line number"))?;
+                    let file = fields.next().map(str::trim).filter(|f| !f.is_empty());
+                    let file = file.ok_or_else(|| error("expected a file name"))?;
+                    symbols.add_line(address, SourceLine { file: file.to_string(), line });
+                },
+                _ => return Err(error(&format!("unknown record `{}`", kind))),
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Reads `octo-cli --symbols` output, which is Octo source: a
+    /// `:const <name> <value>` line per label and constant, and
+    /// `:breakpoint <name> <address>` and `:monitor` lines. Constants that
+    /// fall in program memory are taken as labels, as Octo doesn't tell the
+    /// two apart; breakpoints name their address if nothing else does.
+    pub fn from_octo(text: &str) -> Result<Self, String> {
+        let mut symbols = Symbols::default();
+        let mut breakpoints = Vec::new();
+        for (number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}", number, message);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[0] {
+                ":const" | ":breakpoint" => {
+                    let [_, name, value] = fields[..] else { return Err(error("expected a name and a value")) };
+                    let value = parse_octo_number(value).ok_or_else(|| error(&format!("bad number `{}`", value)))?;
+                    if !(PROGRAM_MEM_START as i64..MEMORY_SIZE as i64).contains(&value) {
+                        continue;
+                    }
+                    match fields[0] {
+                        ":const" => symbols.add_label(value as u16, name),
+                        _ => breakpoints.push((value as u16, name)),
+                    }
+                },
+                keyword if keyword.starts_with(':') => {},
+                _ => return Err(error("expected an Octo directive")),
+            }
+        }
+        for (address, name) in breakpoints {
+            if symbols.label(address).is_none() {
+                symbols.add_label(address, name);
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Reads either format, telling Octo's apart by its first directive.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let symbols = match lines.next().is_some_and(|line| line.starts_with(':')) {
+            true => Self::from_octo(&text),
+            false => Self::from_text(&text),
+        };
+        symbols.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Names `address`, unless it already has a name.
+    pub fn add_label(&mut self, address: u16, name: &str) {
+        self.labels.entry(address).or_insert_with(|| name.to_string());
+    }
+
+    pub fn add_line(&mut self, address: u16, line: SourceLine) {
+        self.lines.insert(address, line);
+    }
+
+    /// The name of exactly `address`.
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.labels.iter().map(|(&address, name)| (address, name.as_str()))
+    }
+
+    pub fn label_address(&self, name: &str) -> Option<u16> {
+        self.labels.iter().find(|(_, n)| *n == name).map(|(&address, _)| address)
+    }
+
+    /// `address` relative to the nearest label at or below it, such as
+    /// `draw_player+0x4`, or just the label's name if it's exact.
+    pub fn symbolize(&self, address: u16) -> Option<String> {
+        let (&label, name) = self.labels.range(..=address).next_back()?;
+        Some(match address - label {
+            0 => name.clone(),
+            offset => format!("{}+0x{:X}", name, offset),
+        })
+    }
+
+    /// The source line `address` falls in: that of the nearest address at
+    /// or below it.
+    pub fn line(&self, address: u16) -> Option<&SourceLine> {
+        self.lines.range(..=address).next_back().map(|(_, line)| line)
+    }
+
+    /// The first address assembled from `line` of `file`, or from the next
+    /// line that produced any code, as debuggers move breakpoints.
+    pub fn line_address(&self, file: &str, line: u32) -> Option<(u16, &SourceLine)> {
+        self.lines
+            .iter()
+            .filter(|(_, l)| l.line >= line && same_file(file, &l.file))
+            .min_by_key(|(&address, l)| (l.line, address))
+            .map(|(&address, l)| (address, l))
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::{self, Write};
+
+use crate::chip8::{Chip8, MEMORY_SIZE};
+use crate::disasm;
+use crate::symbols::Symbols;
+
+/// Writes a line for every instruction run, as the `disasm` listing shows
+/// it, with the address relative to the nearest label after a `;`:
+///
+/// ```text
+/// 0x2A4  D015  DRW V0, V1, 5  ; draw_player+0x4
+/// ```
+///
+/// Call `record` before each `Chip8::cycle`, as with the `Profiler`.
+pub struct Tracer<W: Write> {
+    w: W,
+    symbols: Symbols,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(w: W, symbols: Symbols) -> Self {
+        Self { w, symbols }
+    }
+
+    fn address(&self, address: u16) -> String {
+        self.symbols.symbolize(address).unwrap_or_else(|| format!("0x{:03X}", address))
+    }
+
+    /// Writes the instruction `chip8` is about to run.
+    pub fn record(&mut self, chip8: &Chip8) -> io::Result<()> {
+        let pc = chip8.pc();
+        let memory = chip8.memory();
+        let word = |address: usize| u16::from_be_bytes([memory[address % MEMORY_SIZE], memory[(address + 1) % MEMORY_SIZE]]);
+        let opcode = word(pc);
+        let text = disasm::mnemonic(opcode, word(pc + 2), &|a| self.address(a));
+        match self.symbols.symbolize(pc as u16) {
+            Some(name) => writeln!(self.w, "0x{:03X}  {:04X}  {}  ; {}", pc, opcode, text, name),
+            None => writeln!(self.w, "0x{:03X}  {:04X}  {}", pc, opcode, text),
+        }
+    }
+
+    /// Flushes the trace, handing back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
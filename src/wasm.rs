@@ -0,0 +1,89 @@
+use wasm_bindgen::prelude::*;
+
+use crate::chip8::{Chip8, RomTooLarge, KEYBOARD_SIZE, MAX_ROM_SIZE, VIDEO_HEIGHT, VIDEO_WIDTH};
+use crate::scheduler::{self, DEFAULT_CYCLES_PER_FRAME};
+
+/// JavaScript handle to an emulator. The page drives it by calling
+/// `runFrame()` from `requestAnimationFrame` (ideally throttled to 60 Hz)
+/// and drawing `framebuffer()` into an `ImageData`.
+#[wasm_bindgen]
+pub struct Emulator {
+    chip8: Chip8,
+    cycles_per_frame: u32,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            chip8: Chip8::new(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width() -> usize {
+        VIDEO_WIDTH
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height() -> usize {
+        VIDEO_HEIGHT
+    }
+
+    #[wasm_bindgen(js_name = cyclesPerFrame, getter)]
+    pub fn cycles_per_frame(&self) -> u32 {
+        self.cycles_per_frame
+    }
+
+    #[wasm_bindgen(js_name = cyclesPerFrame, setter)]
+    pub fn set_cycles_per_frame(&mut self, cycles: u32) {
+        self.cycles_per_frame = cycles;
+    }
+
+    /// Restarts the emulator with the given program bytes.
+    #[wasm_bindgen(js_name = loadRom)]
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsError> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(JsError::new(&RomTooLarge { len: rom.len() }.to_string()));
+        }
+        self.chip8 = Chip8::from_bytes(rom);
+        Ok(())
+    }
+
+    /// Runs one 60 Hz frame: up to `cyclesPerFrame` instructions, fewer if
+    /// a draw waits for vertical blank, and a timer tick.
+    #[wasm_bindgen(js_name = runFrame)]
+    pub fn run_frame(&mut self) {
+        scheduler::run_frame(&mut self.chip8, self.cycles_per_frame);
+    }
+
+    /// The display as RGBA bytes, row by row, ready for `new ImageData()`.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.chip8
+            .video
+            .iter()
+            .flat_map(|&p| [(p >> 16) as u8, (p >> 8) as u8, p as u8, 0xFF])
+            .collect()
+    }
+
+    /// Presses or releases key `0x0`-`0xF`. Other keys are ignored.
+    #[wasm_bindgen(js_name = setKey)]
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        if key < KEYBOARD_SIZE {
+            self.chip8.keyboard[key] = pressed;
+        }
+    }
+
+    #[wasm_bindgen(js_name = isBeeping)]
+    pub fn is_beeping(&self) -> bool {
+        self.chip8.is_beeping()
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
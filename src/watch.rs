@@ -0,0 +1,70 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Stamp {
+        modified: metadata.modified().ok(),
+        len: metadata.len(),
+    })
+}
+
+/// Polls a ROM file for changes. A change is only reported once the file
+/// has looked the same for two polls in a row, so an assembler that is still
+/// writing the output doesn't trigger a reload of a half-written ROM.
+#[derive(Debug)]
+pub struct RomWatcher {
+    path: PathBuf,
+    current: Option<Stamp>,
+    pending: Option<Stamp>,
+    last_poll: Instant,
+}
+
+impl RomWatcher {
+    pub fn new(path: impl Into<PathBuf>, now: Instant) -> Self {
+        let path = path.into();
+        let current = stamp(&path);
+        Self {
+            path,
+            current,
+            pending: None,
+            last_poll: now,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true when the file has changed since the last reported change.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.last_poll) < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = now;
+
+        let latest = stamp(&self.path);
+        if latest.is_none() || latest == self.current {
+            self.pending = None;
+            return false;
+        }
+
+        if self.pending != latest {
+            self.pending = latest;
+            return false;
+        }
+
+        self.current = latest;
+        self.pending = None;
+        true
+    }
+}
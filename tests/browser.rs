@@ -0,0 +1,76 @@
+#![cfg(feature = "std")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use afoley_chip8::browser::{is_rom, list_roms, RomBrowser};
+
+// A fresh directory of ROMs and other files, named after the test.
+fn rom_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chip8-browser-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("nested.ch8")).unwrap();
+    for file in ["pong.ch8", "Blitz.CH8", "car.sc8", "alien.xo8", "readme.txt", "tetris.ch8.bak", "ch8"] {
+        fs::write(dir.join(file), [0x12, 0x00]).unwrap();
+    }
+    dir
+}
+
+fn names(paths: &[PathBuf]) -> Vec<&str> {
+    paths.iter().map(|p| p.file_name().unwrap().to_str().unwrap()).collect()
+}
+
+#[test]
+fn recognises_rom_extensions() {
+    for rom in ["a.ch8", "a.sc8", "a.xo8", "A.SC8", "dir/a.b.ch8"] {
+        assert!(is_rom(Path::new(rom)), "{}", rom);
+    }
+    for other in ["a.txt", "a.ch8.bak", "ch8", "a", "a.c8"] {
+        assert!(!is_rom(Path::new(other)), "{}", other);
+    }
+}
+
+#[test]
+fn lists_rom_files_sorted_by_name() {
+    let dir = rom_dir("list");
+    let roms = list_roms(&dir).unwrap();
+    assert_eq!(names(&roms), ["alien.xo8", "Blitz.CH8", "car.sc8", "pong.ch8"]);
+    assert!(roms.iter().all(|p| p.starts_with(&dir)));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(list_roms(&dir).is_err());
+}
+
+#[test]
+fn navigates_with_wrap_around() {
+    let dir = rom_dir("navigate");
+    let mut browser = RomBrowser::open(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(browser.dir(), dir);
+    assert_eq!(browser.entries().len(), 4);
+    assert_eq!(browser.selected(), Some(dir.join("alien.xo8").as_path()));
+
+    browser.select_previous();
+    assert_eq!(browser.selected_index(), 3);
+    browser.select_next();
+    browser.select_next();
+    browser.select_next();
+    assert_eq!(browser.selected(), Some(dir.join("car.sc8").as_path()));
+
+    // The window of visible rows follows the selection down.
+    assert_eq!(browser.scroll_offset(3), 0);
+    browser.select_next();
+    assert_eq!((browser.scroll_offset(3), browser.scroll_offset(2), browser.scroll_offset(0)), (1, 2, 0));
+}
+
+#[test]
+fn an_empty_directory_has_nothing_to_select() {
+    let dir = std::env::temp_dir().join(format!("chip8-browser-empty-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut browser = RomBrowser::open(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    browser.select_next();
+    browser.select_previous();
+    assert_eq!((browser.selected(), browser.selected_index(), browser.scroll_offset(5)), (None, 0, 0));
+}
@@ -0,0 +1,74 @@
+#![cfg(feature = "std")]
+
+use std::io::ErrorKind;
+
+use afoley_chip8::capture::{RecordFormat, Recorder, VideoWriter};
+use afoley_chip8::chip8::{VIDEO_HEIGHT, VIDEO_WIDTH};
+
+const OFF: u32 = 0x000000;
+const ON: u32 = 0xFFFFFF;
+
+// A blank display with the top-left pixel lit, if `lit`.
+fn video(lit: bool) -> Vec<u32> {
+    let mut video = vec![OFF; VIDEO_WIDTH * VIDEO_HEIGHT];
+    if lit {
+        video[0] = ON;
+    }
+    video
+}
+
+#[test]
+fn writes_a_y4m_header_and_frames() {
+    let mut writer = VideoWriter::new(Vec::new(), RecordFormat::Y4m, 2).unwrap();
+    writer.write_frame(&video(false)).unwrap();
+    writer.write_frame(&video(true)).unwrap();
+    let bytes = writer.finish().unwrap();
+
+    let header = b"YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C444\n";
+    assert!(bytes.starts_with(header));
+    let plane = 128 * 64;
+    let frame = |n: usize| &bytes[header.len() + n * (6 + 3 * plane)..][..6 + 3 * plane];
+    assert_eq!(bytes.len(), header.len() + 2 * (6 + 3 * plane));
+    assert!(frame(0).starts_with(b"FRAME\n") && frame(1).starts_with(b"FRAME\n"));
+    // Planar Y, then Cb, then Cr: black is (16, 128, 128), white (235, 128, 128).
+    assert_eq!([frame(0)[6], frame(0)[6 + plane], frame(0)[6 + 2 * plane]], [16, 128, 128]);
+    assert_eq!(frame(1)[6..9], [235, 235, 16]);
+    assert_eq!(frame(1)[6 + 128..6 + 130], [235, 235]);
+}
+
+#[test]
+fn merges_gif_frames_shorter_than_two_centiseconds() {
+    let mut writer = VideoWriter::new(Vec::new(), RecordFormat::Gif, 1).unwrap();
+    // Frame 1 comes 1.7 cs after frame 0, so it replaces it; frame 2 is the
+    // same image; frame 3 is a change 5 cs in.
+    for lit in [false, true, true, false] {
+        writer.write_frame(&video(lit)).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+
+    let mut decoder = gif::DecodeOptions::new().read_info(bytes.as_slice()).unwrap();
+    assert_eq!((decoder.width(), decoder.height()), (64, 32));
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        let palette = frame.palette.as_ref().unwrap();
+        let first = frame.buffer[0] as usize * 3;
+        frames.push((frame.delay, palette[first]));
+    }
+    // The last frame is held for the minimum delay.
+    assert_eq!(frames, [(5, 0xFF), (2, 0x00)]);
+}
+
+#[test]
+fn rejects_scales_out_of_range() {
+    for scale in [0, 65] {
+        let error = VideoWriter::new(Vec::new(), RecordFormat::Gif, scale).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), format!("capture scale {} isn't between 1 and 64", scale));
+
+        let path = std::env::temp_dir().join(format!("chip8-capture-{}-{}.y4m", scale, std::process::id()));
+        assert_eq!(Recorder::create(&path, scale).err().unwrap().kind(), ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+    let frames = VideoWriter::new(Vec::new(), RecordFormat::Frames, 1).err().unwrap();
+    assert_eq!(frames.kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,102 @@
+#![cfg(feature = "std")]
+
+use afoley_chip8::coverage::{Access, Coverage};
+use afoley_chip8::chip8::KEYBOARD_SIZE;
+use afoley_chip8::frontend::{Command, InputSource, ManualClock};
+use afoley_chip8::harness::RomTest;
+use afoley_chip8::runner::{Runner, RunnerOptions};
+
+// LD I, 0x20A; DRW V0, V0, 2; LD B, V0; JP 0x206, and a sprite at 0x20A
+const ROM: [u8; 12] = [0xA2, 0x0A, 0xD0, 0x02, 0xF0, 0x33, 0x12, 0x06, 0x00, 0x00, 0xF0, 0x90];
+
+// Hands out one batch of commands per poll, from the back.
+struct Script(Vec<Vec<Command>>);
+
+impl InputSource for Script {
+    fn poll(&mut self, _keyboard: &mut [bool; KEYBOARD_SIZE], commands: &mut Vec<Command>) {
+        commands.extend(self.0.pop().unwrap_or_default());
+    }
+}
+
+fn sample() -> Coverage {
+    let mut coverage = Coverage::default();
+    coverage.record(Access::Execute, 0x200, 8);
+    coverage.record(Access::Read, 0x20A, 2);
+    coverage.record(Access::Write, 0x700, 1);
+    coverage
+}
+
+#[test]
+fn writes_and_reads_files() {
+    let text = sample().to_text();
+    assert_eq!(text, "chip8-coverage 1\nexecute 200-207\nread 20A-20B\nwrite 700\n");
+    assert_eq!(Coverage::from_text(&text), Ok(sample()));
+    assert_eq!(Coverage::from_text("chip8-coverage 1\n\n# notes\nread 300\n").unwrap().count(Access::Read), 1);
+}
+
+#[test]
+fn reports_bad_lines() {
+    assert_eq!(Coverage::from_text("coverage"), Err("line 1: expected `chip8-coverage 1`".to_string()));
+    assert_eq!(
+        Coverage::from_text("chip8-coverage 1\nfetch 200"),
+        Err("line 2: unknown access `fetch`".to_string())
+    );
+    assert_eq!(
+        Coverage::from_text("chip8-coverage 1\nread 200-1000"),
+        Err("line 2: bad address `1000`".to_string())
+    );
+    assert_eq!(
+        Coverage::from_text("chip8-coverage 1\nwrite 300-200"),
+        Err("line 2: range ends before it starts".to_string())
+    );
+}
+
+#[test]
+fn merges_runs() {
+    let mut coverage = sample();
+    let mut other = Coverage::default();
+    other.record(Access::Execute, 0x206, 6);
+    coverage.merge(&other);
+    assert_eq!(coverage.ranges(Access::Execute).collect::<Vec<_>>(), [(0x200, 0x20B)]);
+    assert_eq!(coverage.count(Access::Write), 1);
+    assert_eq!(coverage.color(0x200), 0x00C000);
+    assert_eq!(coverage.color(0x20A), 0x00E0FF);
+    assert_eq!(coverage.color(0x800), 0);
+}
+
+#[test]
+fn harness_records_coverage() {
+    let test = RomTest {
+        frames: 1,
+        coverage: true,
+        ..RomTest::default()
+    };
+    let chip8 = test.run(&ROM);
+    let coverage = chip8.coverage().unwrap();
+    assert_eq!(coverage.ranges(Access::Execute).collect::<Vec<_>>(), [(0x200, 0x207)]);
+    assert_eq!(coverage.ranges(Access::Read).collect::<Vec<_>>(), [(0x20A, 0x20B)]);
+    assert_eq!(coverage.ranges(Access::Write).collect::<Vec<_>>(), [(0x20A, 0x20C)]);
+}
+
+#[test]
+fn runner_merges_into_the_coverage_file() {
+    let path = std::env::temp_dir().join(format!("chip8-coverage-{}.txt", std::process::id()));
+    std::fs::write(&path, "chip8-coverage 1\nwrite 700\n").unwrap();
+
+    let options = RunnerOptions {
+        coverage: Some(path.clone()),
+        ..RunnerOptions::default()
+    };
+    let script = Script(vec![vec![Command::Quit], vec![Command::Reset]]);
+    let mut runner = Runner::new(options, (), script, (), ManualClock::default());
+    runner.load_bytes(ROM.to_vec()).unwrap();
+    runner.run_frame();
+    // Resets keep what was recorded.
+    runner.run().unwrap();
+    assert_eq!(runner.chip8().coverage().unwrap().count(Access::Execute), 8);
+
+    let saved = Coverage::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved.count(Access::Execute), 8);
+    assert!(saved.contains(Access::Write, 0x700));
+}
@@ -0,0 +1,297 @@
+#![cfg(feature = "std")]
+
+use std::cell::RefCell;
+use std::io::{self, Cursor, PipeWriter, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use afoley_chip8::chip8::Chip8;
+use afoley_chip8::dap::{self, DapServer, DapSession};
+use afoley_chip8::debugger::Debugger;
+use afoley_chip8::frontend::{Command, ManualClock};
+use afoley_chip8::runner::{Runner, RunnerOptions};
+use serde_json::{json, Value};
+
+// start: LD V0, 5; CALL sub; ADD V0, 1; halt: JP halt
+// sub:   LD V1, 3; RET
+const ROM: [u8; 12] = [0x60, 0x05, 0x22, 0x08, 0x70, 0x01, 0x12, 0x06, 0x61, 0x03, 0x00, 0xEE];
+
+const SYMBOLS: &str = "chip8-symbols 1
+label 200 start
+label 208 sub
+line 200 3 main.8o
+line 202 4 main.8o
+line 204 5 main.8o
+line 206 6 main.8o
+line 208 9 main.8o
+line 20A 10 main.8o
+";
+
+// Writes ROM and SYMBOLS to a directory of their own.
+fn project(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chip8-dap-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.ch8"), ROM).unwrap();
+    std::fs::write(dir.join("main.sym"), SYMBOLS).unwrap();
+    dir
+}
+
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Drives a runner through its DAP server the way an editor would.
+struct Client {
+    runner: Runner<(), (), (), ManualClock>,
+    requests: PipeWriter,
+    output: Shared,
+    read: usize,
+    seq: u64,
+    events: Vec<Value>,
+    // Whether the last step left the runner going.
+    running: bool,
+}
+
+impl Client {
+    fn new() -> Self {
+        let (reader, requests) = io::pipe().unwrap();
+        let output = Shared::default();
+        let mut runner = Runner::new(RunnerOptions::default(), (), (), (), ManualClock::default());
+        runner.attach_debugger(Box::new(DapServer::new(reader, output.clone())));
+        Self { runner, requests, output, read: 0, seq: 0, events: Vec::new(), running: true }
+    }
+
+    // Steps the runner until the next message arrives.
+    fn receive(&mut self) -> Value {
+        for _ in 0..1000 {
+            let bytes = self.output.0.borrow()[self.read..].to_vec();
+            let mut cursor = Cursor::new(&bytes);
+            if let Ok(Some(message)) = dap::read_message(&mut cursor) {
+                self.read += cursor.position() as usize;
+                return message;
+            }
+            self.running = self.runner.step().unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        panic!("no reply from the server");
+    }
+
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let request = json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments });
+        dap::write_message(&mut self.requests, &request).unwrap();
+        loop {
+            let message = self.receive();
+            if message["type"] == "response" && message["request_seq"] == self.seq {
+                return message;
+            }
+            self.events.push(message);
+        }
+    }
+
+    fn event(&mut self, name: &str) -> Value {
+        if let Some(i) = self.events.iter().position(|e| e["event"] == name) {
+            return self.events.remove(i);
+        }
+        loop {
+            let message = self.receive();
+            if message["event"] == name {
+                return message;
+            }
+            self.events.push(message);
+        }
+    }
+
+    fn top_frame(&mut self) -> Value {
+        let trace = self.request("stackTrace", json!({ "threadId": 1 }));
+        trace["body"]["stackFrames"][0].clone()
+    }
+}
+
+#[test]
+fn debugs_a_rom_from_source() {
+    let dir = project("source");
+    let mut client = Client::new();
+    let initialize = client.request("initialize", json!({ "adapterID": "chip8" }));
+    assert_eq!(initialize["body"]["supportsReadMemoryRequest"], true);
+    client.event("initialized");
+
+    let launch = json!({
+        "program": dir.join("main.ch8"),
+        "symbols": dir.join("main.sym"),
+        "stopOnEntry": true,
+    });
+    assert_eq!(client.request("launch", launch)["success"], true);
+    let breakpoints = json!({
+        "source": { "path": dir.join("main.8o") },
+        "breakpoints": [{ "line": 8 }, { "line": 20 }],
+    });
+    let breakpoints = client.request("setBreakpoints", breakpoints);
+    assert_eq!(
+        breakpoints["body"]["breakpoints"],
+        json!([
+            { "verified": true, "line": 9, "instructionReference": "0x208" },
+            { "verified": false, "line": 20, "message": "no code at or after this line" },
+        ])
+    );
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.event("stopped")["body"]["reason"], "entry");
+    assert_eq!(client.top_frame()["line"], 3);
+
+    client.request("continue", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["body"]["reason"], "breakpoint");
+    let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+    let frames = &trace["body"]["stackFrames"];
+    assert_eq!((frames[0]["name"].as_str(), frames[0]["line"].as_u64()), (Some("sub"), Some(9)));
+    assert_eq!((frames[1]["name"].as_str(), frames[1]["line"].as_u64()), (Some("start+0x2"), Some(4)));
+    assert_eq!(frames[0]["source"]["path"], json!(dir.join("main.8o")));
+
+    client.request("stepOut", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["body"]["reason"], "step");
+    assert_eq!(client.top_frame()["line"], 5);
+
+    assert_eq!(client.request("disconnect", json!({}))["success"], true);
+    assert!(!client.running);
+}
+
+#[test]
+fn shows_and_edits_registers_and_memory() {
+    let dir = project("memory");
+    let mut client = Client::new();
+    client.request("initialize", json!({}));
+    client.request("launch", json!({ "program": dir.join("main.ch8"), "stopOnEntry": true }));
+    client.request("setInstructionBreakpoints", json!({ "breakpoints": [{ "instructionReference": "0x204" }] }));
+    client.request("configurationDone", json!({}));
+    client.event("stopped");
+    client.request("continue", json!({ "threadId": 1 }));
+    client.event("stopped");
+
+    let scopes = client.request("scopes", json!({ "frameId": 0 }));
+    let names: Vec<&str> = scopes["body"]["scopes"].as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["Registers", "Timers", "Stack"]);
+    let registers = client.request("variables", json!({ "variablesReference": 1 }));
+    let registers = registers["body"]["variables"].as_array().unwrap().clone();
+    assert_eq!((&registers[0]["name"], &registers[0]["value"]), (&json!("V0"), &json!("0x05")));
+    assert_eq!((&registers[1]["name"], &registers[1]["value"]), (&json!("V1"), &json!("0x03")));
+    assert_eq!(registers[17]["memoryReference"], "0x204");
+
+    let set = client.request("setVariable", json!({ "variablesReference": 1, "name": "V0", "value": "0x10" }));
+    assert_eq!(set["body"]["value"], "0x10");
+    let set = client.request("setVariable", json!({ "variablesReference": 2, "name": "DT", "value": "300" }));
+    assert_eq!(set["message"], "300 doesn't fit in DT");
+    assert_eq!(client.runner.chip8().registers()[0], 0x10);
+
+    let read = client.request("readMemory", json!({ "memoryReference": "0x200", "offset": 2, "count": 2 }));
+    assert_eq!(read["body"], json!({ "address": "0x202", "data": "Igg=", "unreadableBytes": 0 }));
+    let read = client.request("readMemory", json!({ "memoryReference": "0xFFE", "count": 4 }));
+    assert_eq!(read["body"]["unreadableBytes"], 2);
+    let read = client.request("readMemory", json!({ "memoryReference": "0x200", "count": u64::MAX }));
+    assert_eq!(read["body"]["unreadableBytes"], u64::MAX - 0xE00);
+    let read = client.request("readMemory", json!({ "memoryReference": "0x200", "offset": i64::MIN }));
+    assert_eq!(read["message"], "address out of range");
+    let read = client.request("readMemory", json!({ "memoryReference": "0x200", "offset": -0x100, "count": 1 }));
+    assert_eq!(read["body"]["address"], "0x100");
+    let write = client.request("writeMemory", json!({ "memoryReference": "0x300", "data": "AQID" }));
+    assert_eq!(write["body"]["bytesWritten"], 3);
+    assert_eq!(&client.runner.chip8().memory()[0x300..0x303], [1, 2, 3]);
+}
+
+// Runs `chip8` until the debugger pauses it.
+fn run(chip8: &mut Chip8, debugger: &mut Debugger) {
+    while !debugger.should_stop(chip8) {
+        chip8.cycle();
+    }
+}
+
+fn handle(session: &mut DapSession, command: &str, arguments: Value, chip8: &mut Chip8, debugger: &mut Debugger) -> Value {
+    let request = json!({ "seq": 1, "type": "request", "command": command, "arguments": arguments });
+    session.handle(&request, chip8, debugger, &mut Vec::new());
+    session.poll_stop(debugger);
+    session.take_messages().remove(0)
+}
+
+#[test]
+fn steps_over_calls() {
+    let (mut chip8, mut debugger) = (Chip8::from_bytes(&ROM), Debugger::default());
+    let mut session = DapSession::default();
+    handle(&mut session, "stepIn", json!({}), &mut chip8, &mut debugger);
+    assert_eq!(chip8.pc(), 0x202);
+
+    handle(&mut session, "next", json!({}), &mut chip8, &mut debugger);
+    assert!(!debugger.is_paused());
+    run(&mut chip8, &mut debugger);
+    assert_eq!((chip8.pc(), chip8.registers()[1]), (0x204, 3));
+    assert!(session.take_messages().is_empty());
+    session.poll_stop(&mut debugger);
+    assert_eq!(session.take_messages()[0]["body"]["reason"], "step");
+
+    let out = handle(&mut session, "stepOut", json!({}), &mut chip8, &mut debugger);
+    assert_eq!((&out["success"], &out["message"]), (&json!(false), &json!("not in a subroutine")));
+}
+
+#[test]
+fn rejects_bad_requests() {
+    let (mut chip8, mut debugger) = (Chip8::from_bytes(&ROM), Debugger::default());
+    let mut session = DapSession::default();
+    let mut commands = Vec::new();
+    let request = json!({ "seq": 7, "type": "request", "command": "launch", "arguments": { "program": "/no/such.ch8" } });
+    session.handle(&request, &mut chip8, &mut debugger, &mut commands);
+    let response = &session.take_messages()[0];
+    assert_eq!((&response["request_seq"], &response["success"]), (&json!(7), &json!(false)));
+    assert!(response["message"].as_str().unwrap().starts_with("/no/such.ch8: "));
+    assert_eq!(commands, []);
+
+    let out = handle(&mut session, "goto", json!({}), &mut chip8, &mut debugger);
+    assert_eq!(out["message"], "unsupported request `goto`");
+    let out = handle(&mut session, "writeMemory", json!({ "memoryReference": "0xFFF", "data": "AQI=" }), &mut chip8, &mut debugger);
+    assert_eq!(out["message"], "2 bytes at 0xFFF run past the end of memory");
+
+    session.handle(&json!({ "seq": 9, "command": "terminate" }), &mut chip8, &mut debugger, &mut commands);
+    assert_eq!(commands, [Command::Quit]);
+    assert_eq!(session.take_messages()[1]["event"], "terminated");
+}
+
+#[test]
+fn evaluates_conditions_and_watches() {
+    let (mut chip8, mut debugger) = (Chip8::from_bytes(&ROM), Debugger::default());
+    let mut session = DapSession::default();
+    let breakpoints = json!({ "breakpoints": [
+        { "instructionReference": "0x206", "condition": "V0 == 7" },
+        { "instructionReference": "0x208", "condition": "V0 ==" },
+    ] });
+    let out = handle(&mut session, "setInstructionBreakpoints", breakpoints, &mut chip8, &mut debugger);
+    assert_eq!(out["body"]["breakpoints"][1]["message"], "bad condition: column 6: expected a value, found end of expression");
+    assert_eq!(debugger.breakpoints().collect::<Vec<_>>(), [0x206]);
+
+    let info = handle(&mut session, "dataBreakpointInfo", json!({ "name": "V0" }), &mut chip8, &mut debugger);
+    assert_eq!(info["body"]["dataId"], "V0");
+    let info = handle(&mut session, "dataBreakpointInfo", json!({ "name": "V0 +" }), &mut chip8, &mut debugger);
+    assert_eq!(info["body"]["dataId"], Value::Null);
+    let watch = json!({ "breakpoints": [{ "dataId": "V0", "condition": "V0 > 5" }] });
+    let out = handle(&mut session, "setDataBreakpoints", watch, &mut chip8, &mut debugger);
+    assert_eq!(out["body"]["breakpoints"][0]["verified"], true);
+
+    // V0 becomes 5 first, which the condition skips, then 6.
+    run(&mut chip8, &mut debugger);
+    session.poll_stop(&mut debugger);
+    assert_eq!(session.take_messages()[0]["body"]["reason"], "data breakpoint");
+    assert_eq!((chip8.pc(), chip8.registers()[0]), (0x206, 6));
+
+    let result = handle(&mut session, "evaluate", json!({ "expression": "V0 * 2" }), &mut chip8, &mut debugger);
+    assert_eq!(result["body"]["result"], "12 (0xC)");
+    let result = handle(&mut session, "evaluate", json!({ "expression": "mem[PC] >> 4" }), &mut chip8, &mut debugger);
+    assert_eq!(result["body"]["result"], "1");
+    let result = handle(&mut session, "evaluate", json!({ "expression": "V1 / 0" }), &mut chip8, &mut debugger);
+    assert_eq!(result["message"], "division by zero");
+}
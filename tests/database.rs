@@ -0,0 +1,153 @@
+#![cfg(feature = "std")]
+
+use std::path::Path;
+
+use afoley_chip8::chip8::Chip8;
+use afoley_chip8::database::{sha1_hex, Database};
+use afoley_chip8::detect::{detect, Confidence};
+use afoley_chip8::frontend::{Keymap, ManualClock, Palette};
+use afoley_chip8::quirks::Quirks;
+use afoley_chip8::runner::{Runner, RunnerOptions};
+
+const TETRIS: &str = "5f518084744bf3cb8733f6e5454dfd1634320563";
+
+fn bundled(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("roms").join(name)
+}
+
+fn sha1_of(name: &str) -> [u8; 20] {
+    Chip8::from_bytes(&std::fs::read(bundled(name)).unwrap()).rom_sha1()
+}
+
+#[test]
+fn hashes_the_loaded_rom() {
+    assert_eq!(sha1_hex(&sha1_of("tetris.ch8")), TETRIS);
+    assert_eq!(sha1_hex(&Chip8::new().rom_sha1()), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+}
+
+#[test]
+fn identifies_the_bundled_roms() {
+    let database = Database::bundled();
+    for (name, title) in [("tetris.ch8", "Tetris"), ("superpong.ch8", "Super Pong"), ("SpaceInvaders.ch8", "Space Invaders")] {
+        let profile = database.lookup(&sha1_of(name)).unwrap_or_else(|| panic!("{} not found", name));
+        assert_eq!(profile.title, title);
+    }
+    assert_eq!(database.lookup(&[0; 20]), None);
+}
+
+#[test]
+fn applies_platform_defaults() {
+    let profile = Database::bundled().lookup(&sha1_of("tetris.ch8")).unwrap();
+    assert_eq!(profile.platform.as_deref(), Some("originalChip8"));
+    assert_eq!(profile.cycles_per_frame, Some(15));
+    assert_eq!(profile.quirks, Some(Quirks::cosmac_vip()));
+    assert_eq!(profile.palette, None);
+    assert_eq!(
+        profile.keymap,
+        Some(Keymap {
+            left: Some(5),
+            right: Some(6),
+            down: Some(7),
+            a: Some(4),
+            ..Keymap::default()
+        })
+    );
+}
+
+#[test]
+fn overrides_replace_fields() {
+    let mut database = Database::bundled();
+    database
+        .add_overrides(&format!(
+            r##"{{
+                "{}": {{
+                    "tickrate": 30,
+                    "colors": {{ "pixels": ["#102030", "#fc0"] }},
+                    "quirkyPlatforms": {{ "originalChip8": {{ "vblank": false }} }}
+                }},
+                "0000000000000000000000000000000000000000": {{ "title": "Blank", "platforms": ["modernChip8"] }}
+            }}"##,
+            TETRIS.to_uppercase()
+        ))
+        .unwrap();
+
+    let profile = database.lookup(&sha1_of("tetris.ch8")).unwrap();
+    assert_eq!(profile.title, "Tetris");
+    assert_eq!(profile.cycles_per_frame, Some(30));
+    assert_eq!(profile.quirks, Some(Quirks::default()));
+    assert_eq!(profile.palette, Some(Palette { background: 0x102030, foreground: 0xFFCC00 }));
+    assert!(profile.keymap.is_some());
+
+    let blank = database.lookup(&[0; 20]).unwrap();
+    assert_eq!(blank.title, "Blank");
+    assert_eq!(blank.cycles_per_frame, Some(12));
+}
+
+#[test]
+fn rejects_bad_files() {
+    assert!(Database::bundled().add_overrides("[]").is_err());
+    assert!(Database::from_json(r#"{"ab": 3}"#, "[]", "[]").is_err());
+    assert!(Database::load_dir(Path::new("/nonexistent")).is_err());
+}
+
+#[test]
+fn runner_applies_the_profile_on_load() {
+    let options = RunnerOptions {
+        database: Some(Database::bundled()),
+        ..RunnerOptions::default()
+    };
+    let mut runner = Runner::new(options, (), (), (), ManualClock::default());
+    runner.load(&bundled("tetris.ch8")).unwrap();
+    assert_eq!(runner.scheduler().cycles_per_frame(), 15);
+    assert!(runner.chip8().quirks().display_wait);
+
+    // Unknown ROMs go back to the defaults.
+    runner.load_bytes(vec![0x12, 0x00]).unwrap();
+    assert_eq!(runner.scheduler().cycles_per_frame(), 10);
+    assert_eq!(runner.chip8().quirks(), Quirks::default());
+}
+
+#[test]
+fn options_win_over_the_profile() {
+    let options = RunnerOptions {
+        cycles_per_frame: Some(7),
+        quirks: Some(Quirks::legacy()),
+        database: Some(Database::bundled()),
+        ..RunnerOptions::default()
+    };
+    let mut runner = Runner::new(options, (), (), (), ManualClock::default());
+    runner.load(&bundled("tetris.ch8")).unwrap();
+    assert_eq!(runner.scheduler().cycles_per_frame(), 7);
+    assert_eq!(runner.chip8().quirks(), Quirks::legacy());
+}
+
+#[test]
+fn runner_falls_back_to_detection() {
+    // DRW V0, V0, 5 with no delay timer to pace it, then enough ADD V0, 1
+    // for the detector to be fairly confident, and JP 0x200.
+    let mut rom = vec![0xD0, 0x05];
+    for _ in 0..16 {
+        rom.extend([0x70, 0x01]);
+    }
+    rom.extend([0x12, 0x00]);
+
+    let options = RunnerOptions {
+        database: Some(Database::bundled()),
+        ..RunnerOptions::default()
+    };
+    let mut runner = Runner::new(options, (), (), (), ManualClock::default());
+    runner.load_bytes(rom.clone()).unwrap();
+    // Medium confidence isn't enough to slow the ROM down with the display
+    // wait, which `info` only suggests.
+    assert_eq!(detect(&rom).confidence, Confidence::Medium);
+    assert_eq!(detect(&rom).quirks(), Quirks::cosmac_vip());
+    assert_eq!(runner.chip8().quirks(), Quirks::default());
+
+    let options = RunnerOptions {
+        quirks: Some(Quirks::default()),
+        ..RunnerOptions::default()
+    };
+    let mut runner = Runner::new(options, (), (), (), ManualClock::default());
+    runner.load_bytes(rom).unwrap();
+    assert_eq!(runner.chip8().quirks(), Quirks::default());
+}
@@ -0,0 +1,102 @@
+use afoley_chip8::detect::{detect, trace, Confidence, Platform};
+use afoley_chip8::quirks::Quirks;
+
+// CLS; LD V0, 0; LD I, 0x300; DRW V0, V0, 5; LD DT, V0; then ADD V1, 1
+// enough times for the detector to be confident, and JP 0x200.
+fn chip8_program() -> Vec<u8> {
+    let mut rom = vec![0x00, 0xE0, 0x60, 0x00, 0xA3, 0x00, 0xD0, 0x05, 0xF0, 0x15];
+    for _ in 0..16 {
+        rom.extend([0x71, 0x01]);
+    }
+    rom.extend([0x12, 0x00]);
+    rom
+}
+
+#[test]
+fn plain_programs_are_chip8() {
+    let detection = detect(&chip8_program());
+    assert_eq!(detection.platform, Platform::Chip8);
+    assert_eq!(detection.confidence, Confidence::Medium);
+    assert_eq!(detection.superchip_opcodes, 0);
+    assert_eq!(detection.quirks(), Quirks::default());
+
+    // Too little to go on.
+    assert_eq!(detect(&[0x12, 0x00]).confidence, Confidence::Low);
+}
+
+#[test]
+fn ignores_opcodes_in_data() {
+    // JP 0x206; a sprite that reads as HIGH and DRW V1, V2, 0; then an
+    // infinite loop.
+    let rom = [0x12, 0x06, 0x00, 0xFF, 0xD1, 0x20, 0x12, 0x06];
+    assert_eq!(detect(&rom).platform, Platform::Chip8);
+
+    let code = trace(&rom);
+    let reached: Vec<usize> = (0..code.len()).filter(|&a| code[a]).collect();
+    assert_eq!(reached, [0x200, 0x206]);
+}
+
+#[test]
+fn traces_calls_and_skips() {
+    // CALL 0x208; SE V0, 1; JP 0x20C; EXIT; RET; (never reached) HIGH
+    let rom = [0x22, 0x08, 0x30, 0x01, 0x12, 0x0C, 0x00, 0xFD, 0x00, 0xEE, 0x00, 0xFF];
+    let code = trace(&rom);
+    let reached: Vec<usize> = (0..code.len()).filter(|&a| code[a]).collect();
+    assert_eq!(reached, [0x200, 0x202, 0x204, 0x206, 0x208]);
+}
+
+#[test]
+fn finds_superchip_opcodes() {
+    // HIGH; DRW V1, V2, 0; LD HF, V3; LD R, V4; EXIT
+    let rom = [0x00, 0xFF, 0xD1, 0x20, 0xF3, 0x30, 0xF4, 0x75, 0x00, 0xFD];
+    let detection = detect(&rom);
+    assert_eq!(detection.platform, Platform::SuperChip);
+    assert_eq!(detection.confidence, Confidence::High);
+    assert_eq!(detection.superchip_opcodes, 5);
+
+    // A lone DXY0 could be sprite data.
+    let detection = detect(&[0xD1, 0x20]);
+    assert_eq!(detection.platform, Platform::SuperChip);
+    assert_eq!(detection.confidence, Confidence::Low);
+}
+
+#[test]
+fn finds_xochip_opcodes() {
+    // HIGH; LD I, long 0x1234; PLANE 3; SAVE V1-V2
+    let rom = [0x00, 0xFF, 0xF0, 0x00, 0x12, 0x34, 0xF3, 0x01, 0x51, 0x22];
+    let detection = detect(&rom);
+    assert_eq!(detection.platform, Platform::XoChip);
+    assert_eq!(detection.confidence, Confidence::High);
+    assert_eq!(detection.xochip_opcodes, 3);
+}
+
+#[test]
+fn oversized_roms_are_xochip() {
+    let detection = detect(&vec![0x11; 4000]);
+    assert_eq!(detection.platform, Platform::XoChip);
+    assert_eq!(detection.confidence, Confidence::High);
+}
+
+#[test]
+fn reports_quirk_patterns() {
+    // DRW V0, V0, 5; LD V0, K; SHR V1, V2; ADD VF, V1; JP V0, 0x300
+    let detection = detect(&[0xD0, 0x05, 0xF0, 0x0A, 0x81, 0x26, 0x8F, 0x14, 0xB3, 0x00]);
+    let hints = detection.hints;
+    assert!(hints.unpaced_drawing);
+    assert!(hints.waits_for_key);
+    assert!(hints.shifts_other_register);
+    assert!(hints.vf_destination);
+    assert!(hints.jumps_with_offset);
+
+    // Setting the delay timer means the game paces itself.
+    assert!(!detect(&chip8_program()).hints.unpaced_drawing);
+}
+
+#[test]
+fn suggests_the_display_wait_for_unpaced_chip8() {
+    let mut rom = chip8_program();
+    rom[8..10].copy_from_slice(&[0x60, 0x01]);
+    let detection = detect(&rom);
+    assert_eq!(detection.platform, Platform::Chip8);
+    assert_eq!(detection.quirks(), Quirks::cosmac_vip());
+}
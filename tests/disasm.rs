@@ -0,0 +1,95 @@
+#![cfg(feature = "std")]
+
+use afoley_chip8::disasm::{mnemonic, Disassembly, Edge};
+use afoley_chip8::symbols::Symbols;
+
+// start: LD I, sprite; CALL draw; SE V0, 0; JP start; EXIT
+// sprite: 0xF0, 0x90
+// draw: DRW V0, V1, 2; RET
+const ROM: [u8; 16] = [
+    0xA2, 0x0A, 0x22, 0x0C, 0x30, 0x00, 0x12, 0x00, 0x00, 0xFD, 0xF0, 0x90, 0xD0, 0x12, 0x00, 0xEE,
+];
+
+fn hex(address: u16) -> String {
+    format!("0x{:03X}", address)
+}
+
+#[test]
+fn formats_instructions() {
+    assert_eq!(mnemonic(0x00E0, 0, &hex), "CLS");
+    assert_eq!(mnemonic(0x1234, 0, &hex), "JP 0x234");
+    assert_eq!(mnemonic(0x8AB4, 0, &hex), "ADD VA, VB");
+    assert_eq!(mnemonic(0xD125, 0, &hex), "DRW V1, V2, 5");
+    assert_eq!(mnemonic(0xF355, 0, &hex), "LD [I], V3");
+    assert_eq!(mnemonic(0x00FF, 0, &hex), "HIGH");
+    assert_eq!(mnemonic(0xF000, 0x1234, &hex), "LD I, long 0x1234");
+    assert_eq!(mnemonic(0x5121, 0, &hex), "db 0x51, 0x21");
+    assert_eq!(mnemonic(0xA300, 0, &|_| "sprite".to_string()), "LD I, sprite");
+}
+
+#[test]
+fn separates_code_from_data() {
+    let disassembly = Disassembly::analyse(&ROM);
+    let code: Vec<u16> = disassembly.instructions().map(|i| i.address).collect();
+    assert_eq!(code, [0x200, 0x202, 0x204, 0x206, 0x208, 0x20C, 0x20E]);
+    assert_eq!(disassembly.data().collect::<Vec<_>>(), [0x20A]);
+}
+
+#[test]
+fn builds_the_control_flow_graph() {
+    let disassembly = Disassembly::analyse(&ROM);
+    let blocks: Vec<_> = disassembly.blocks().map(|b| (b.start, b.end, b.successors.clone())).collect();
+    assert_eq!(
+        blocks,
+        [
+            (0x200, 0x204, vec![(0x20C, Edge::Call), (0x204, Edge::Next)]),
+            (0x204, 0x206, vec![(0x206, Edge::Next), (0x208, Edge::Skip)]),
+            (0x206, 0x208, vec![(0x200, Edge::Jump)]),
+            (0x208, 0x20A, vec![]),
+            (0x20C, 0x210, vec![]),
+        ]
+    );
+
+    let dot = Disassembly::analyse(&ROM).to_dot();
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.contains("    \"0x200\" -> \"0x20C\" [label=\"call\", style=dashed];\n"));
+    assert!(dot.contains("    \"0x20C\" [label=\"sub_20C:\\l0x20C  DRW V0, V1, 2\\l0x20E  RET\\l\"];\n"));
+}
+
+#[test]
+fn labels_the_listing() {
+    assert_eq!(
+        Disassembly::analyse(&ROM).listing(),
+        "\
+start:
+  0x200  A20A  LD I, data_20A
+  0x202  220C  CALL sub_20C
+  0x204  3000  SE V0, 0x00
+  0x206  1200  JP start
+loc_208:
+  0x208  00FD  EXIT
+data_20A:
+  0x20A        db 0xF0, 0x90
+sub_20C:
+  0x20C  D012  DRW V0, V1, 2
+  0x20E  00EE  RET
+"
+    );
+}
+
+#[test]
+fn names_addresses_after_symbols() {
+    let mut symbols = Symbols::default();
+    symbols.add_label(0x200, "main");
+    symbols.add_label(0x20A, "ball");
+    symbols.add_label(0x20C, "draw_ball");
+    let mut disassembly = Disassembly::analyse(&ROM);
+    disassembly.apply_symbols(&symbols);
+    assert_eq!(disassembly.label(0x20C), Some("draw_ball"));
+    assert_eq!(disassembly.address(0x20B), "ball+0x1");
+    assert_eq!(disassembly.address(0x100), "0x100");
+
+    let listing = disassembly.listing();
+    assert!(listing.starts_with("main:\n  0x200  A20A  LD I, ball\n  0x202  220C  CALL draw_ball\n"), "{}", listing);
+    assert!(!listing.contains("sub_20C"));
+}
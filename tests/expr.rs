@@ -0,0 +1,187 @@
+#![cfg(feature = "std")]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use afoley_chip8::chip8::Chip8;
+use afoley_chip8::debugger::{Debugger, StopReason, Watch};
+use afoley_chip8::expr::{Expr, ParseError};
+use afoley_chip8::frontend::{DisplaySink, ManualClock};
+use afoley_chip8::runner::{Runner, RunnerOptions};
+
+// loop: ADD V0, 1; LD I, 0x300; LD [I], V0; JP loop
+const ROM: [u8; 8] = [0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00];
+
+fn machine() -> Chip8 {
+    let mut chip8 = Chip8::with_state(&ROM, [0; 16], 0x2F0);
+    chip8.set_register(3, 0x10);
+    chip8.set_register(0xF, 1);
+    chip8.set_delay_timer(0);
+    chip8.set_sound_timer(7);
+    chip8.write_memory(0x2F0, &[0xAB, 0xCD]);
+    chip8.keyboard[5] = true;
+    chip8
+}
+
+fn eval(text: &str) -> Result<i64, String> {
+    Expr::parse(text).unwrap_or_else(|e| panic!("{}: {}", text, e)).eval(&machine())
+}
+
+fn parse_error(text: &str) -> (usize, String) {
+    let ParseError { position, message } = Expr::parse(text).unwrap_err();
+    (position, message)
+}
+
+#[test]
+fn evaluates_machine_state() {
+    let cases = [
+        ("V3", 0x10),
+        ("vf", 1),
+        ("I", 0x2F0),
+        ("PC", 0x200),
+        ("sp", 0),
+        ("delay", 0),
+        ("DT", 0),
+        ("sound", 7),
+        ("st", 7),
+        ("mem[0x2F0]", 0xAB),
+        ("mem[I + 1]", 0xCD),
+        ("key[5]", 1),
+        ("key[0]", 0),
+        ("0b1010", 10),
+        ("42", 42),
+    ];
+    for (text, value) in cases {
+        assert_eq!(eval(text), Ok(value), "{}", text);
+    }
+}
+
+#[test]
+fn follows_c_precedence() {
+    let cases = [
+        ("1 + 2 * 3", 7),
+        ("(1 + 2) * 3", 9),
+        ("10 - 4 - 3", 3),
+        ("7 / 2", 3),
+        ("7 % 4", 3),
+        ("1 << 4 + 1", 32),
+        ("0xF0 >> 4", 0xF),
+        ("6 & 3", 2),
+        ("6 | 3", 7),
+        ("6 ^ 3", 5),
+        ("1 | 2 == 2", 1),
+        ("-V3", -16),
+        ("~0", -1),
+        ("!V3", 0),
+        ("!!V3", 1),
+        ("- -3", 3),
+    ];
+    for (text, value) in cases {
+        assert_eq!(eval(text), Ok(value), "{}", text);
+    }
+}
+
+#[test]
+fn compares_and_combines_conditions() {
+    let cases = [
+        ("V3 == 0x10 && I > 0x2E0", 1),
+        ("V3 == 0x10 && I > 0x300", 0),
+        ("mem[0x2F0] != 0", 1),
+        ("delay == 0", 1),
+        ("V3 < 16 || V3 >= 16", 1),
+        ("V3 <= 15", 0),
+        ("V3 != 0x10 || sound > 8", 0),
+        // The right-hand side of `&&` and `||` only runs when it matters.
+        ("0 && 1 / 0", 0),
+        ("1 || mem[0x1000]", 1),
+    ];
+    for (text, value) in cases {
+        assert_eq!(eval(text), Ok(value), "{}", text);
+    }
+}
+
+#[test]
+fn reports_evaluation_errors() {
+    assert_eq!(eval("V3 / (V0 - V0)"), Err("division by zero".to_string()));
+    assert_eq!(eval("5 % 0"), Err("division by zero".to_string()));
+    assert_eq!(eval("mem[0x1000]"), Err("mem[4096] is out of range".to_string()));
+    assert_eq!(eval("key[-1]"), Err("key[-1] is out of range".to_string()));
+    assert_eq!(eval("1 << 64"), Err("shift by 64".to_string()));
+
+    // A condition that can't be evaluated still stops.
+    assert!(Expr::parse("1 / 0").unwrap().is_true(&machine()));
+    assert!(!Expr::parse("V0").unwrap().is_true(&machine()));
+}
+
+#[test]
+fn reports_parse_errors_with_positions() {
+    assert_eq!(parse_error(""), (0, "expected a value, found end of expression".to_string()));
+    assert_eq!(parse_error("V3 =="), (5, "expected a value, found end of expression".to_string()));
+    assert_eq!(parse_error("V3 = 4"), (3, "unexpected `=`".to_string()));
+    assert_eq!(parse_error("V3 == 0x1G"), (6, "bad number `0x1G`".to_string()));
+    assert_eq!(parse_error("VG > 1"), (0, "unknown name `VG`".to_string()));
+    assert_eq!(parse_error("(V3 + 1"), (7, "expected `)`, found end of expression".to_string()));
+    assert_eq!(parse_error("mem 0x200"), (4, "expected `[`, found `512`".to_string()));
+    assert_eq!(parse_error("mem[I] V3"), (7, "unexpected `V3`".to_string()));
+    assert_eq!(Expr::parse("V3 ==").unwrap_err().to_string(), "column 6: expected a value, found end of expression");
+}
+
+#[test]
+fn stops_at_conditional_breakpoints_and_watchpoints() {
+    let mut chip8 = Chip8::from_bytes(&ROM);
+    let mut debugger = Debugger::default();
+    debugger.set_conditional_breakpoint(0x200, Expr::parse("V0 == 3").unwrap());
+    let run = |chip8: &mut Chip8, debugger: &mut Debugger| {
+        while !debugger.should_stop(chip8) {
+            chip8.cycle();
+        }
+    };
+    run(&mut chip8, &mut debugger);
+    assert_eq!(debugger.take_stop(), Some(StopReason::Breakpoint));
+    assert_eq!((chip8.pc(), chip8.registers()[0]), (0x200, 3));
+
+    // Changes made while paused don't trigger a watchpoint.
+    debugger.clear_breakpoints();
+    debugger.add_watchpoint(&chip8, Expr::parse("mem[0x300]").unwrap(), Some(Expr::parse("V0 % 2 == 1").unwrap()));
+    chip8.write_memory(0x300, &[9]);
+    debugger.resume(&chip8);
+    run(&mut chip8, &mut debugger);
+    assert_eq!(debugger.take_stop(), Some(StopReason::Watchpoint));
+    // It changed to 4 first, but V0 was even then.
+    assert_eq!((chip8.pc(), chip8.memory()[0x300]), (0x206, 5));
+}
+
+#[derive(Clone, Default)]
+struct Watches(Rc<RefCell<Vec<String>>>);
+
+impl DisplaySink for Watches {
+    fn present(&mut self, _chip8: &Chip8) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn set_watches(&mut self, watches: &[Watch]) {
+        *self.0.borrow_mut() = watches.iter().map(Watch::to_string).collect();
+    }
+}
+
+#[test]
+fn runner_shows_the_watch_list_every_frame() {
+    let path = std::env::temp_dir().join(format!("chip8-expr-{}.ch8", std::process::id()));
+    std::fs::write(&path, ROM).unwrap();
+    let options = RunnerOptions {
+        cycles_per_frame: Some(8),
+        watches: vec![Expr::parse("V0").unwrap(), Expr::parse("mem[I]").unwrap()],
+        ..RunnerOptions::default()
+    };
+    let display = Watches::default();
+    let mut runner = Runner::new(options, display.clone(), (), (), ManualClock::default());
+    runner.load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    runner.add_watch(Expr::parse("V0 / (V0 - 2)").unwrap());
+
+    runner.step().unwrap();
+    assert_eq!(*display.0.borrow(), ["V0 = 2", "mem[I] = 2", "V0 / (V0 - 2): division by zero"]);
+    runner.step().unwrap();
+    assert_eq!(*display.0.borrow(), ["V0 = 4", "mem[I] = 4", "V0 / (V0 - 2) = 2"]);
+    assert_eq!(runner.watches()[0].value(), Some(&Ok(4)));
+}
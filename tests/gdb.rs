@@ -0,0 +1,191 @@
+#![cfg(feature = "std")]
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use afoley_chip8::chip8::Chip8;
+use afoley_chip8::debugger::Debugger;
+use afoley_chip8::frontend::ManualClock;
+use afoley_chip8::gdb::{GdbServer, GdbSession, TARGET_XML};
+use afoley_chip8::runner::{Runner, RunnerOptions};
+
+// ADD V0, 1; ADD V1, 2; JP 0x200
+const ROM: [u8; 6] = [0x70, 0x01, 0x71, 0x02, 0x12, 0x00];
+
+fn packet(data: &str) -> Vec<u8> {
+    let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    format!("${}#{:02x}", data, checksum).into_bytes()
+}
+
+// A session with acks turned off, so replies are just packets.
+fn session(chip8: &mut Chip8, debugger: &mut Debugger) -> GdbSession {
+    let mut session = GdbSession::default();
+    let reply = session.process(&packet("QStartNoAckMode"), chip8, debugger);
+    assert_eq!(reply, [b"+".as_slice(), &packet("OK")].concat());
+    session
+}
+
+fn ask(session: &mut GdbSession, data: &str, chip8: &mut Chip8, debugger: &mut Debugger) -> String {
+    let reply = session.process(&packet(data), chip8, debugger);
+    String::from_utf8(reply).unwrap()
+}
+
+#[test]
+fn acks_and_checks_packets() {
+    let (mut chip8, mut debugger) = (Chip8::from_bytes(&ROM), Debugger::default());
+    let mut session = GdbSession::default();
+    assert_eq!(session.process(b"$qAttached#00", &mut chip8, &mut debugger), b"-");
+    // Split across reads, with an ack from the client in front.
+    assert_eq!(session.process(b"+$qAtt", &mut chip8, &mut debugger), b"");
+    assert_eq!(session.process(b"ached#8f", &mut chip8, &mut debugger), b"+$1#31");
+    // A nak asks for the last reply again.
+    assert_eq!(session.process(b"-", &mut chip8, &mut debugger), b"$1#31");
+    assert_eq!(ask(&mut session, "vMustReplyEmpty", &mut chip8, &mut debugger), "+$#00");
+}
+
+#[test]
+fn reads_and_writes_registers() {
+    let mut registers = [0; 16];
+    registers[0] = 0x12;
+    registers[15] = 0xFF;
+    let mut chip8 = Chip8::with_state(&ROM, registers, 0x2F0);
+    let mut debugger = Debugger::default();
+    let mut session = session(&mut chip8, &mut debugger);
+
+    let expected = format!("12{}ff02f0020000{}", "00".repeat(14), "0000");
+    assert_eq!(ask(&mut session, "g", &mut chip8, &mut debugger), String::from_utf8(packet(&expected)).unwrap());
+    assert_eq!(ask(&mut session, "p11", &mut chip8, &mut debugger), String::from_utf8(packet("0200")).unwrap());
+
+    assert_eq!(ask(&mut session, "P3=7f", &mut chip8, &mut debugger), "$OK#9a");
+    assert_eq!(ask(&mut session, "P10=0300", &mut chip8, &mut debugger), "$OK#9a");
+    assert_eq!(ask(&mut session, "P13=3c", &mut chip8, &mut debugger), "$OK#9a");
+    assert_eq!((chip8.registers()[3], chip8.index(), chip8.delay_timer()), (0x7F, 0x300, 0x3C));
+    // SP counts a stack outside memory, so it can't change.
+    assert_eq!(ask(&mut session, "P12=01", &mut chip8, &mut debugger), "$E01#a6");
+    assert_eq!(ask(&mut session, "P3=7f7f", &mut chip8, &mut debugger), "$E01#a6");
+    // I has to stay inside memory.
+    assert_eq!(ask(&mut session, "P10=1000", &mut chip8, &mut debugger), "$E01#a6");
+    assert_eq!(ask(&mut session, "P10=ffff", &mut chip8, &mut debugger), "$E01#a6");
+    assert_eq!(chip8.index(), 0x300);
+
+    let all = format!("{}0400021000000a", "01".repeat(16));
+    assert_eq!(ask(&mut session, &format!("G{}", all), &mut chip8, &mut debugger), "$OK#9a");
+    assert_eq!((chip8.registers()[15], chip8.index(), chip8.pc(), chip8.sound_timer()), (1, 0x400, 0x210, 10));
+    let bad_index = format!("{}ffff021000000a", "01".repeat(16));
+    assert_eq!(ask(&mut session, &format!("G{}", bad_index), &mut chip8, &mut debugger), "$E01#a6");
+    assert_eq!(chip8.index(), 0x400);
+}
+
+#[test]
+fn reads_and_writes_memory() {
+    let (mut chip8, mut debugger) = (Chip8::from_bytes(&ROM), Debugger::default());
+    let mut session = session(&mut chip8, &mut debugger);
+    assert_eq!(ask(&mut session, "m200,6", &mut chip8, &mut debugger), String::from_utf8(packet("700171021200")).unwrap());
+    assert_eq!(ask(&mut session, "mffe,8", &mut chip8, &mut debugger), String::from_utf8(packet("0000")).unwrap());
+    assert_eq!(ask(&mut session, "m1000,1", &mut chip8, &mut debugger), "$E01#a6");
+    assert_eq!(ask(&mut session, "m200,ffffffffffffffff", &mut chip8, &mut debugger), "$E01#a6");
+
+    assert_eq!(ask(&mut session, "M300,3:0a0b0c", &mut chip8, &mut debugger), "$OK#9a");
+    assert_eq!(&chip8.memory()[0x300..0x303], [0x0A, 0x0B, 0x0C]);
+    assert_eq!(ask(&mut session, "M300,2:0a0b0c", &mut chip8, &mut debugger), "$E01#a6");
+    assert_eq!(ask(&mut session, "Mfff,2:0102", &mut chip8, &mut debugger), "$E01#a6");
+    assert_eq!(ask(&mut session, "M2,ffffffffffffffff:01", &mut chip8, &mut debugger), "$E01#a6");
+}
+
+#[test]
+fn serves_the_target_description() {
+    let (mut chip8, mut debugger) = (Chip8::from_bytes(&ROM), Debugger::default());
+    let mut session = session(&mut chip8, &mut debugger);
+    let supported = ask(&mut session, "qSupported:multiprocess+;swbreak+", &mut chip8, &mut debugger);
+    assert!(supported.contains("qXfer:features:read+"));
+
+    let mut xml = String::new();
+    loop {
+        let query = format!("qXfer:features:read:target.xml:{:x},40", xml.len());
+        let reply = ask(&mut session, &query, &mut chip8, &mut debugger);
+        let data = &reply[1..reply.len() - 3];
+        xml.push_str(&data[1..]);
+        if data.starts_with('l') {
+            break;
+        }
+        assert!(data.starts_with('m'));
+    }
+    assert_eq!(xml, TARGET_XML);
+    assert!(xml.contains(r#"<reg name="pc" bitsize="16" type="code_ptr" regnum="17"/>"#));
+    let other = ask(&mut session, "qXfer:features:read:other.xml:0,40", &mut chip8, &mut debugger);
+    assert_eq!(other, "$E00#a5");
+    let overflow = ask(&mut session, "qXfer:features:read:target.xml:1,ffffffffffffffff", &mut chip8, &mut debugger);
+    assert_eq!(overflow, "$E01#a6");
+}
+
+#[test]
+fn steps_and_stops_at_breakpoints() {
+    let (mut chip8, mut debugger) = (Chip8::from_bytes(&ROM), Debugger::default());
+    let mut session = session(&mut chip8, &mut debugger);
+
+    assert_eq!(ask(&mut session, "s", &mut chip8, &mut debugger), "$S05#b8");
+    assert_eq!((chip8.pc(), chip8.registers()[0]), (0x202, 1));
+    assert_eq!(ask(&mut session, "Z0,204,2", &mut chip8, &mut debugger), "$OK#9a");
+    assert_eq!(ask(&mut session, "c", &mut chip8, &mut debugger), "");
+    assert!(!debugger.is_paused());
+    while !debugger.should_stop(&chip8) {
+        chip8.cycle();
+    }
+    assert_eq!(session.process(b"", &mut chip8, &mut debugger), packet("S05"));
+    assert_eq!(chip8.pc(), 0x204);
+    assert_eq!(ask(&mut session, "?", &mut chip8, &mut debugger), "$S05#b8");
+
+    // Continuing from a breakpoint runs past it.
+    assert_eq!(ask(&mut session, "c", &mut chip8, &mut debugger), "");
+    assert!(!debugger.should_stop(&chip8));
+    assert_eq!(session.process(&[0x03], &mut chip8, &mut debugger), packet("S02"));
+    assert_eq!(ask(&mut session, "z0,204,2", &mut chip8, &mut debugger), "$OK#9a");
+    assert_eq!(debugger.breakpoints().count(), 0);
+}
+
+// Sends `data` and steps the runner until a whole reply has arrived.
+fn exchange(runner: &mut Runner<(), (), (), ManualClock>, client: &mut TcpStream, data: &[u8]) -> String {
+    client.write_all(data).unwrap();
+    let mut reply = Vec::new();
+    let mut buffer = [0; 256];
+    for _ in 0..200 {
+        runner.step().unwrap();
+        if let Ok(n) = client.read(&mut buffer) {
+            reply.extend_from_slice(&buffer[..n]);
+        }
+        if reply.len() >= 3 && reply[reply.len() - 3] == b'#' {
+            break;
+        }
+    }
+    String::from_utf8(reply).unwrap()
+}
+
+#[test]
+fn runner_serves_clients_over_tcp() {
+    let mut runner = Runner::new(RunnerOptions::default(), (), (), (), ManualClock::default());
+    runner.load_bytes(ROM.to_vec()).unwrap();
+    let server = GdbServer::listen(0).unwrap();
+    let address = server.address();
+    runner.attach_debugger(Box::new(server));
+
+    let mut client = TcpStream::connect(address).unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    assert_eq!(exchange(&mut runner, &mut client, &packet("?")), "+$S02#b5");
+    assert!(runner.debugger().is_paused());
+
+    let pc = runner.chip8().pc();
+    runner.step().unwrap();
+    assert_eq!(runner.chip8().pc(), pc);
+
+    assert_eq!(exchange(&mut runner, &mut client, &packet("Z0,202,2")), "+$OK#9a");
+    assert_eq!(exchange(&mut runner, &mut client, &packet("c")), "+$S05#b8");
+    assert_eq!(runner.chip8().pc(), 0x202);
+
+    // Hanging up lets the ROM run on without its breakpoints.
+    drop(client);
+    runner.step().unwrap();
+    runner.step().unwrap();
+    assert!(!runner.debugger().is_paused());
+    assert_eq!(runner.debugger().breakpoints().count(), 0);
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "std")]
+
+use afoley_chip8::database::Database;
+use afoley_chip8::detect::Platform;
+use afoley_chip8::info::RomInfo;
+
+// JP 0x205; a 3-byte sprite; LD I, 0x202; DRW V0, V1, 3; JP 0x209
+const SPRITE_ROM: [u8; 11] = [0x12, 0x05, 0xF0, 0x90, 0xF0, 0xA2, 0x02, 0xD0, 0x13, 0x12, 0x09];
+
+#[test]
+fn summarises_the_rom() {
+    // JP 0x204; a 2-byte sprite; LD I, 0x202; DRW V1, V2, 2; JP 0x208
+    let rom = [0x12, 0x04, 0xF0, 0x90, 0xA2, 0x02, 0xD1, 0x22, 0x12, 0x08];
+    let info = RomInfo::analyse(&rom, None);
+    assert_eq!(info.size, 10);
+    assert_eq!(info.entry_point, 0x204);
+    assert_eq!(info.code_bytes, 8);
+    assert_eq!(info.sprite_bytes, 2);
+    assert_eq!(info.sprite_count, 1);
+    assert_eq!(info.opcodes.iter().copied().collect::<Vec<_>>(), ["1NNN", "ANNN", "DXYN"]);
+    assert_eq!(info.detection.platform, Platform::Chip8);
+    assert!(info.warnings.is_empty(), "{:?}", info.warnings);
+    assert_eq!(info.title, None);
+}
+
+#[test]
+fn limits_sprites_to_the_tallest_drawn() {
+    // A sprite runs up to the next code, and no further than DXYN draws.
+    let info = RomInfo::analyse(&SPRITE_ROM, None);
+    assert_eq!(info.code_bytes, 8);
+    assert_eq!(info.sprite_bytes, 3);
+}
+
+#[test]
+fn warns_about_problems() {
+    let mut rom = vec![0x0F, 0xFF, 0xB3, 0x00];
+    rom.resize(4000, 0);
+    let warnings = RomInfo::analyse(&rom, None).warnings;
+    assert!(warnings.contains(&"ROM exceeds 3584 bytes".to_string()), "{:?}", warnings);
+    assert!(warnings.iter().any(|w| w.contains("XO-CHIP")));
+    assert!(warnings.iter().any(|w| w.contains("BNNN")));
+    assert!(warnings.iter().any(|w| w.contains("0NNN")));
+
+    let warnings = RomInfo::analyse(&[0x80, 0x08, 0x10, 0x00], None).warnings;
+    assert!(warnings.contains(&"1 reachable word(s) aren't instructions, the first at 0x200".to_string()));
+    assert!(warnings.contains(&"0x202 jumps to 0x000, outside the ROM".to_string()));
+
+    assert_eq!(RomInfo::analyse(&[], None).warnings, ["ROM is empty"]);
+}
+
+#[test]
+fn prints_a_report() {
+    let rom = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/roms/tetris.ch8")).unwrap();
+    let report = RomInfo::analyse(&rom, Some(&Database::bundled())).to_string();
+    assert!(report.starts_with("Size:        494 bytes\nSHA-1:       5f518084744bf3cb8733f6e5454dfd1634320563\nTitle:       Tetris\n"));
+    assert!(report.contains("Platform:    CHIP-8 (medium confidence)\nEntry point: 0x200\n"));
+    assert!(!report.contains("warning"));
+    assert!(!report.contains("Quirks:"));
+
+    // Drawing without a delay timer to pace it suggests the display wait.
+    let report = RomInfo::analyse(&SPRITE_ROM, None).to_string();
+    assert!(report.contains("Quirks:      try --quirks vip, as it draws without setting the delay timer\n"));
+}
@@ -0,0 +1,106 @@
+#![cfg(feature = "std")]
+
+use afoley_chip8::chip8::Chip8;
+use afoley_chip8::frontend::ManualClock;
+use afoley_chip8::profiler::{FunctionProfile, Profiler};
+use afoley_chip8::runner::{Runner, RunnerOptions};
+use afoley_chip8::symbols::Symbols;
+
+// start: CALL outer; JP start
+// outer: CALL inner; CALL inner; RET
+// inner: ADD V0, 1; RET
+const ROM: [u8; 14] = [0x22, 0x04, 0x12, 0x00, 0x22, 0x0A, 0x22, 0x0A, 0x00, 0xEE, 0x70, 0x01, 0x00, 0xEE];
+
+// Profiles `cycles` instructions of ROM.
+fn profile(cycles: usize) -> Profiler {
+    let mut chip8 = Chip8::from_bytes(&ROM);
+    let mut profiler = Profiler::default();
+    for _ in 0..cycles {
+        profiler.record(&chip8);
+        chip8.cycle();
+    }
+    profiler
+}
+
+#[test]
+fn counts_each_address() {
+    // One pass round the loop is 9 instructions.
+    let profiler = profile(18);
+    assert_eq!(profiler.total(), 18);
+    assert_eq!(profiler.count(0x200), 2);
+    assert_eq!(profiler.count(0x20A), 4);
+    assert_eq!(profiler.count(0x300), 0);
+    assert_eq!(profiler.hot_addresses(2), [(0x20A, 4), (0x20C, 4)]);
+}
+
+#[test]
+fn attributes_instructions_to_subroutines() {
+    let profiler = profile(18);
+    assert_eq!(
+        profiler.functions(),
+        [
+            FunctionProfile { address: 0x20A, self_count: 8, total_count: 8, calls: 4 },
+            FunctionProfile { address: 0x204, self_count: 6, total_count: 14, calls: 2 },
+            FunctionProfile { address: 0x200, self_count: 4, total_count: 18, calls: 0 },
+        ]
+    );
+}
+
+#[test]
+fn profiles_an_instruction_in_the_last_byte() {
+    // CALL 0x300, split across 0xFFF and 0x000.
+    let mut chip8 = Chip8::from_bytes(&ROM);
+    chip8.set_pc(0xFFF);
+    chip8.write_memory(0xFFF, &[0x23]);
+    let mut profiler = Profiler::default();
+    profiler.record(&chip8);
+    chip8.cycle();
+    profiler.record(&chip8);
+    assert_eq!((profiler.count(0xFFF), profiler.count(0x300)), (1, 1));
+    assert!(profiler.functions().iter().any(|f| f.address == 0x300 && f.calls == 1));
+}
+
+#[test]
+fn writes_reports() {
+    let profiler = profile(9);
+    assert_eq!(profiler.collapsed_stacks(), "start 2\nstart;sub_204 3\nstart;sub_204;sub_20A 4\n");
+    assert_eq!(
+        profiler.call_tree(),
+        "100.00%         9  start\n 77.78%         7    sub_204\n 44.44%         4      sub_20A\n"
+    );
+    let flat = profiler.flat_profile();
+    assert!(flat.starts_with("  self%      self     total     calls  function\n 44.44%         4         4         2  sub_20A\n"));
+}
+
+#[test]
+fn names_functions_after_symbols() {
+    let mut profiler = profile(9);
+    let mut symbols = Symbols::default();
+    symbols.add_label(0x200, "main");
+    symbols.add_label(0x204, "update");
+    symbols.add_label(0x20A, "tick");
+    profiler.set_symbols(symbols);
+    assert_eq!(profiler.collapsed_stacks(), "main 2\nmain;update 3\nmain;update;tick 4\n");
+    assert!(profiler.flat_profile().contains("      2  0x20C  tick+0x2\n"));
+}
+
+#[test]
+fn runner_profiles_when_asked() {
+    let path = std::env::temp_dir().join(format!("chip8-profile-{}.folded", std::process::id()));
+    let options = RunnerOptions {
+        profile: Some(path.clone()),
+        ..RunnerOptions::default()
+    };
+    let mut runner = Runner::new(options, (), (), (), ManualClock::default());
+    runner.load_bytes(ROM.to_vec()).unwrap();
+    runner.run_frame();
+    assert_eq!(runner.profiler().unwrap().total(), 10);
+    runner.save_profile().unwrap();
+
+    let folded = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(folded.starts_with("start 3\n"), "{}", folded);
+
+    let runner = Runner::new(RunnerOptions::default(), (), (), (), ManualClock::default());
+    assert!(runner.profiler().is_none());
+}
@@ -0,0 +1,183 @@
+#![cfg(feature = "std")]
+
+use std::path::{Path, PathBuf};
+
+use afoley_chip8::harness::{self, RomTest};
+use afoley_chip8::quirks::Quirks;
+
+// Goldens live in tests/golden; regenerate them with
+// `CHIP8_UPDATE_GOLDENS=1 cargo test --test roms`.
+fn golden(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name)
+}
+
+fn assert_golden(test: &RomTest, rom: &[u8], name: &str) {
+    let chip8 = test.run(rom);
+    if let Err(e) = harness::check_golden(&golden(name), &chip8.video) {
+        panic!("{}", e);
+    }
+}
+
+// F00A: V0 = next key, F029: I = font sprite for V0, 6105: V1 = 5,
+// D115: draw it at (5, 5), 1208: loop forever.
+const SHOW_KEY: [u8; 10] = [0xF0, 0x0A, 0xF0, 0x29, 0x61, 0x05, 0xD1, 0x15, 0x12, 0x08];
+
+// Draws the sixteen font sprites in two rows of eight.
+const FONT: [u8; 28] = [
+    0x60, 0x00, // 200: V0 = 0 (digit)
+    0x61, 0x00, // 202: V1 = 0 (x)
+    0x62, 0x00, // 204: V2 = 0 (y)
+    0xF0, 0x29, // 206: I = sprite for V0
+    0xD1, 0x25, // 208: draw it at (V1, V2)
+    0x70, 0x01, // 20A: V0 += 1
+    0x71, 0x06, // 20C: V1 += 6
+    0x30, 0x08, // 20E: unless V0 == 8,
+    0x12, 0x16, // 210:   skip the line break
+    0x61, 0x00, // 212: V1 = 0
+    0x62, 0x08, // 214: V2 = 8
+    0x30, 0x10, // 216: unless V0 == 16,
+    0x12, 0x06, // 218:   draw the next digit
+    0x12, 0x1A, // 21A: loop forever
+];
+
+#[test]
+fn font_sprites() {
+    assert_golden(&RomTest::new(10), &FONT, "font.txt");
+}
+
+#[test]
+fn display_wait_draws_one_sprite_per_frame() {
+    let test = RomTest {
+        frames: 5,
+        quirks: Quirks::cosmac_vip(),
+        ..RomTest::default()
+    };
+    assert_golden(&test, &FONT, "font-vip.txt");
+}
+
+#[test]
+fn waits_for_scripted_key() {
+    let test = RomTest::new(20).tap(0xA, 5, 3);
+    assert_golden(&test, &SHOW_KEY, "show-key.txt");
+
+    let idle = RomTest::new(20).run(&SHOW_KEY);
+    assert!(idle.video.iter().all(|&p| p == 0));
+}
+
+#[test]
+fn seeded_runs_are_reproducible() {
+    // C0FF: V0 = random byte, 1202: loop forever.
+    let rom = [0xC0, 0xFF, 0x12, 0x02];
+    let test = RomTest::new(1);
+    assert_eq!(test.run(&rom).registers(), test.run(&rom).registers());
+}
+
+#[test]
+fn text_art_round_trips() {
+    let chip8 = RomTest::new(10).run(&SHOW_KEY);
+    let text = harness::to_text(&chip8.video);
+    let pixels = harness::parse_text(&text).unwrap();
+    assert!(pixels.iter().zip(&chip8.video).all(|(&lit, &p)| lit == (p != 0)));
+
+    assert!(harness::parse_text("#.\n").is_err());
+}
+
+#[test]
+fn png_goldens_are_compared_by_pixel() {
+    let dir = std::env::temp_dir().join(format!("chip8-golden-{}", std::process::id()));
+    let path = dir.join("font.png");
+    let chip8 = RomTest::new(10).run(&FONT);
+
+    std::fs::create_dir_all(&dir).unwrap();
+    afoley_chip8::capture::save_png(&path, &chip8.video, 3).unwrap();
+    assert_eq!(harness::compare_golden(&path, &chip8.video), Ok(()));
+
+    let blank = RomTest::new(10).run(&SHOW_KEY);
+    assert!(harness::compare_golden(&path, &blank.video).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn bundled(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("roms").join(name)
+}
+
+#[test]
+fn tetris() {
+    let test = RomTest::new(120).tap(0x5, 60, 10);
+    test.check(&bundled("tetris.ch8"), &golden("tetris.txt")).unwrap();
+}
+
+#[test]
+fn superpong() {
+    let test = RomTest::new(120).tap(0x1, 30, 30);
+    test.check(&bundled("superpong.ch8"), &golden("superpong.txt")).unwrap();
+}
+
+#[test]
+fn space_invaders() {
+    let test = RomTest::new(240).tap(0x5, 120, 10);
+    test.check(&bundled("SpaceInvaders.ch8"), &golden("space-invaders.txt")).unwrap();
+}
+
+// Timendus' CHIP-8 test suite (https://github.com/Timendus/chip8-test-suite)
+// isn't redistributed here, so these are ignored by default. Drop its ROMs
+// into tests/roms and run them with `cargo test --test roms -- --ignored`.
+//
+// Their goldens in tests/golden/suite are drawn by hand from the suite's
+// documented pass screens, never written from this emulator's output, so
+// CHIP8_UPDATE_GOLDENS doesn't touch them.
+fn suite_test(rom: &str, test: RomTest) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms").join(rom);
+    let bytes = std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    let chip8 = test.run(&bytes);
+    let name = Path::new(rom).with_extension("txt");
+    let golden = golden("suite").join(name);
+    if !golden.exists() {
+        panic!(
+            "{}: no golden; if this matches the suite's pass screen, save it there by hand:\n{}",
+            golden.display(),
+            harness::to_text(&chip8.video)
+        );
+    }
+    if let Err(e) = harness::compare_golden(&golden, &chip8.video) {
+        panic!("{}", e);
+    }
+}
+
+#[test]
+#[ignore = "needs tests/roms/1-chip8-logo.ch8"]
+fn suite_chip8_logo() {
+    suite_test("1-chip8-logo.ch8", RomTest::new(60));
+}
+
+#[test]
+#[ignore = "needs tests/roms/2-ibm-logo.ch8"]
+fn suite_ibm_logo() {
+    suite_test("2-ibm-logo.ch8", RomTest::new(60));
+}
+
+#[test]
+#[ignore = "needs tests/roms/3-corax+.ch8"]
+fn suite_corax() {
+    suite_test("3-corax+.ch8", RomTest::new(60));
+}
+
+#[test]
+#[ignore = "needs tests/roms/4-flags.ch8"]
+fn suite_flags() {
+    suite_test("4-flags.ch8", RomTest::new(120));
+}
+
+#[test]
+#[ignore = "needs tests/roms/5-quirks.ch8"]
+fn suite_quirks() {
+    // The menu asks for a platform; key 1 picks CHIP-8.
+    suite_test("5-quirks.ch8", RomTest::new(600).tap(0x1, 30, 10));
+}
+
+#[test]
+#[ignore = "needs tests/roms/6-keypad.ch8"]
+fn suite_keypad() {
+    // Key 1 runs the FX0A "press and release" test; then tap key 5 for it.
+    suite_test("6-keypad.ch8", RomTest::new(240).tap(0x1, 30, 10).tap(0x5, 120, 10));
+}
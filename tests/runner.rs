@@ -0,0 +1,120 @@
+#![cfg(feature = "std")]
+
+use std::path::Path;
+
+use afoley_chip8::chip8::{Chip8, KEYBOARD_SIZE, MAX_ROM_SIZE};
+use afoley_chip8::frontend::{Command, DisplaySink, InputSource, ManualClock};
+use afoley_chip8::runner::{Runner, RunnerOptions};
+
+// JP 0x200
+const ROM: [u8; 2] = [0x12, 0x00];
+
+// ADD V0, 1; JP 0x200, so V0 counts the frames run at 2 cycles a frame.
+const COUNTER: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+fn runner() -> Runner<(), (), (), ManualClock> {
+    Runner::new(RunnerOptions::default(), (), (), (), ManualClock::default())
+}
+
+// Hands the runner one batch of commands per step.
+#[derive(Default)]
+struct Script(Vec<Vec<Command>>);
+
+impl InputSource for Script {
+    fn poll(&mut self, _keyboard: &mut [bool; KEYBOARD_SIZE], commands: &mut Vec<Command>) {
+        if !self.0.is_empty() {
+            commands.extend(self.0.remove(0));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Messages(Vec<String>);
+
+impl DisplaySink for Messages {
+    fn present(&mut self, _chip8: &Chip8) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn notify(&mut self, message: &str) {
+        self.0.push(message.to_string());
+    }
+}
+
+fn counter(script: Vec<Vec<Command>>) -> Runner<Messages, Script, (), ManualClock> {
+    let options = RunnerOptions { cycles_per_frame: Some(2), ..RunnerOptions::default() };
+    let mut runner = Runner::new(options, Messages::default(), Script(script), (), ManualClock::default());
+    runner.load_bytes(COUNTER.to_vec()).unwrap();
+    runner
+}
+
+// Steps once per batch, returning the frame count after each.
+fn frames(runner: &mut Runner<Messages, Script, (), ManualClock>, steps: usize) -> Vec<u8> {
+    (0..steps)
+        .map(|_| {
+            assert!(runner.step().unwrap());
+            runner.chip8().registers()[0]
+        })
+        .collect()
+}
+
+#[test]
+fn rejects_roms_too_big_for_memory() {
+    let mut runner = runner();
+    runner.load_bytes(ROM.to_vec()).unwrap();
+    assert_eq!(runner.load_bytes(vec![0; 4000]), Err(format!("ROM is 4000 bytes, at most {} fit", MAX_ROM_SIZE)));
+    assert_eq!(&runner.chip8().memory()[0x200..0x204], [0x12, 0x00, 0x00, 0x00]);
+
+    let path = std::env::temp_dir().join(format!("chip8-runner-big-{}.xo8", std::process::id()));
+    std::fs::write(&path, vec![0; 4000]).unwrap();
+    let loaded = runner.load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, Err(format!("{}: ROM is 4000 bytes, at most 3584 fit", path.display())));
+    assert_eq!(runner.chip8().memory()[0x200], 0x12);
+}
+
+#[test]
+fn pauses_and_advances_single_frames() {
+    use Command::{FrameAdvance, TogglePause};
+    let script = vec![
+        vec![],
+        vec![TogglePause],
+        vec![],
+        vec![FrameAdvance],
+        vec![FrameAdvance, FrameAdvance],
+        vec![TogglePause],
+        // Only while paused.
+        vec![FrameAdvance],
+    ];
+    let mut runner = counter(script);
+    assert_eq!(frames(&mut runner, 3), [1, 1, 1]);
+    assert!(runner.is_paused());
+    // Coming back doesn't catch up on the time spent paused.
+    assert_eq!(frames(&mut runner, 4), [2, 4, 5, 6]);
+    assert!(!runner.is_paused());
+}
+
+#[test]
+fn reset_restarts_the_rom() {
+    let mut runner = counter(vec![vec![], vec![], vec![], vec![Command::Reset]]);
+    assert_eq!(frames(&mut runner, 4), [1, 2, 3, 1]);
+    assert_eq!(runner.chip8().pc(), 0x200);
+
+    // Without a ROM there is nothing to reset.
+    let mut runner = Runner::new(RunnerOptions::default(), (), Script(vec![vec![Command::Reset]]), (), ManualClock::default());
+    assert!(runner.step().unwrap());
+    assert_eq!(runner.chip8().pc(), 0x200);
+}
+
+#[test]
+fn a_failed_load_keeps_the_current_rom() {
+    let missing = Path::new("no/such/rom.ch8");
+    let mut runner = counter(vec![vec![], vec![Command::Load(missing.to_path_buf())]]);
+    assert!(runner.load(missing).unwrap_err().starts_with("no/such/rom.ch8: "));
+
+    assert_eq!(frames(&mut runner, 2), [1, 2]);
+    assert_eq!(runner.rom_path(), None);
+    let messages = &runner.display().0;
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].starts_with("failed to load no/such/rom.ch8: "), "{}", messages[0]);
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "std")]
+
+use std::time::Duration;
+
+use afoley_chip8::chip8::Chip8;
+use afoley_chip8::frontend::{ManualClock, TimeSource};
+use afoley_chip8::quirks::Quirks;
+use afoley_chip8::scheduler::{self, Scheduler, Speed, FRAME_RATE};
+
+fn frame() -> Duration {
+    Duration::from_secs(1) / FRAME_RATE
+}
+
+fn scheduler(clock: &ManualClock) -> Scheduler {
+    Scheduler::new(10, clock.now())
+}
+
+#[test]
+fn runs_one_frame_per_frame_duration() {
+    let mut clock = ManualClock::default();
+    let mut scheduler = scheduler(&clock);
+    assert_eq!(scheduler.frames_due(clock.now()), 1);
+    assert_eq!(scheduler.time_until_next_frame(clock.now()), frame());
+
+    clock.sleep(frame() / 2);
+    assert_eq!(scheduler.frames_due(clock.now()), 0);
+    clock.sleep(frame() / 2);
+    assert_eq!(scheduler.frames_due(clock.now()), 1);
+
+    // A late update catches up on the frames it missed.
+    clock.sleep(frame() * 3);
+    assert_eq!(scheduler.frames_due(clock.now()), 3);
+    assert_eq!(scheduler.frames_due(clock.now()), 0);
+}
+
+#[test]
+fn caps_catching_up_and_drops_the_rest() {
+    let mut clock = ManualClock::default();
+    let mut scheduler = scheduler(&clock);
+    assert_eq!(scheduler.frames_due(clock.now()), 1);
+
+    clock.sleep(Duration::from_secs(1));
+    assert_eq!(scheduler.frames_due(clock.now()), 5);
+    // The other 55 frames are gone, not run over the next updates.
+    assert_eq!(scheduler.frames_due(clock.now()), 0);
+    assert_eq!(scheduler.time_until_next_frame(clock.now()), frame());
+    clock.sleep(frame());
+    assert_eq!(scheduler.frames_due(clock.now()), 1);
+}
+
+#[test]
+fn turbo_runs_a_batch_every_update() {
+    let mut clock = ManualClock::default();
+    let mut scheduler = scheduler(&clock);
+    scheduler.toggle_turbo(clock.now());
+    assert_eq!(scheduler.speed(), Speed::Turbo);
+    assert_eq!(scheduler.frames_due(clock.now()), 20);
+    assert_eq!(scheduler.frames_due(clock.now()), 20);
+    assert_eq!(scheduler.time_until_next_frame(clock.now()), Duration::ZERO);
+
+    // Back at normal speed, the time spent in turbo isn't caught up.
+    clock.sleep(Duration::from_secs(1));
+    scheduler.frames_due(clock.now());
+    scheduler.toggle_turbo(clock.now());
+    assert_eq!(scheduler.speed(), Speed::Normal);
+    assert_eq!(scheduler.frames_due(clock.now()), 1);
+    assert_eq!(scheduler.frames_due(clock.now()), 0);
+}
+
+#[test]
+fn slow_motion_stretches_frames() {
+    let mut clock = ManualClock::default();
+    let mut scheduler = scheduler(&clock);
+    scheduler.toggle_slow_motion(clock.now());
+    assert_eq!(scheduler.speed(), Speed::SlowMotion);
+    assert_eq!(scheduler.frame_duration(), frame() * 4);
+    assert_eq!(scheduler.frames_due(clock.now()), 1);
+
+    clock.sleep(frame() * 3);
+    assert_eq!(scheduler.frames_due(clock.now()), 0);
+    clock.sleep(frame());
+    assert_eq!(scheduler.frames_due(clock.now()), 1);
+
+    scheduler.toggle_slow_motion(clock.now());
+    assert_eq!((scheduler.speed(), scheduler.frame_duration()), (Speed::Normal, frame()));
+}
+
+#[test]
+fn resync_forgets_time_spent_paused() {
+    let mut clock = ManualClock::default();
+    let mut scheduler = scheduler(&clock);
+    assert_eq!(scheduler.frames_due(clock.now()), 1);
+
+    clock.sleep(frame() * 4);
+    scheduler.resync(clock.now());
+    assert_eq!(scheduler.frames_due(clock.now()), 1);
+    assert_eq!(scheduler.frames_due(clock.now()), 0);
+}
+
+#[test]
+fn a_frame_stops_at_the_display_wait() {
+    // LD V0, 1; DRW V0, V0, 1; ADD V1, 1; JP 0x202
+    let rom = [0x60, 0x01, 0xD0, 0x01, 0x71, 0x01, 0x12, 0x02];
+    let mut chip8 = Chip8::from_bytes(&rom);
+    chip8.set_quirks(Quirks::cosmac_vip());
+    scheduler::run_frame(&mut chip8, 10);
+    assert_eq!((chip8.pc(), chip8.registers()[1]), (0x204, 0));
+    assert!(!chip8.is_waiting_for_vblank());
+    scheduler::run_frame(&mut chip8, 10);
+    assert_eq!((chip8.pc(), chip8.registers()[1]), (0x204, 1));
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "std")]
+
+use afoley_chip8::symbols::{SourceLine, Symbols};
+
+const TEXT: &str = "chip8-symbols 1
+# from a hand-written test
+line 200 3 src/main.8o
+line 204 5 src/main.8o
+
+line 2A4 17 src/player sprites.8o
+";
+
+fn source(file: &str, line: u32) -> SourceLine {
+    SourceLine { file: file.to_string(), line }
+}
+
+#[test]
+fn maps_addresses_and_lines() {
+    let symbols = Symbols::from_text(TEXT).unwrap();
+    assert_eq!(symbols.line(0x1FE), None);
+    assert_eq!(symbols.line(0x200), Some(&source("src/main.8o", 3)));
+    assert_eq!(symbols.line(0x202), Some(&source("src/main.8o", 3)));
+    assert_eq!(symbols.line(0x2B0), Some(&source("src/player sprites.8o", 17)));
+
+    assert_eq!(symbols.line_address("src/main.8o", 5).map(|(a, _)| a), Some(0x204));
+    // Breakpoints on lines without code move down to the next that has some.
+    assert_eq!(symbols.line_address("/home/me/game/src/main.8o", 4), Some((0x204, &source("src/main.8o", 5))));
+    assert_eq!(symbols.line_address("main.8o", 6), None);
+    assert_eq!(symbols.line_address("other.8o", 3), None);
+}
+
+#[test]
+fn reports_bad_lines() {
+    assert_eq!(Symbols::from_text("symbols"), Err("line 1: expected `chip8-symbols 1`".to_string()));
+    assert_eq!(
+        Symbols::from_text("chip8-symbols 1\nline 20G 3 main.8o"),
+        Err("line 2: expected an address".to_string())
+    );
+    assert_eq!(
+        Symbols::from_text("chip8-symbols 1\nline 200 three main.8o"),
+        Err("line 2: expected a line number".to_string())
+    );
+    assert_eq!(Symbols::from_text("chip8-symbols 1\nline 200 3"), Err("line 2: expected a file name".to_string()));
+    assert_eq!(Symbols::from_text("chip8-symbols 1\nfile main.8o"), Err("line 2: unknown record `file`".to_string()));
+}
+
+#[test]
+fn names_addresses_by_label() {
+    let text = "chip8-symbols 1\nlabel 200 main\nlabel 2A0 draw_player\nlabel 2A0 player_alias\n";
+    let symbols = Symbols::from_text(text).unwrap();
+    assert_eq!(symbols.label(0x2A0), Some("draw_player"));
+    assert_eq!(symbols.label_address("main"), Some(0x200));
+    assert_eq!(symbols.symbolize(0x2A4).as_deref(), Some("draw_player+0x4"));
+    assert_eq!(symbols.symbolize(0x200).as_deref(), Some("main"));
+    assert_eq!(symbols.symbolize(0x1FF), None);
+
+    assert_eq!(Symbols::from_text("chip8-symbols 2\n"), Err("line 1: unsupported version 2".to_string()));
+    assert_eq!(
+        Symbols::from_text("chip8-symbols 1\nlabel 2A0 draw player"),
+        Err("line 2: expected a name without spaces".to_string())
+    );
+}
+
+#[test]
+fn reads_octo_symbol_exports() {
+    let text = "# constants\n:const main 512\n:const SPEED 4\n:const draw-player 0x2A0\n\
+                :breakpoint loop 0x206\n:breakpoint again 512\n:monitor draw-player 8\n";
+    let symbols = Symbols::from_octo(text).unwrap();
+    let labels: Vec<(u16, &str)> = symbols.labels().collect();
+    assert_eq!(labels, [(0x200, "main"), (0x206, "loop"), (0x2A0, "draw-player")]);
+
+    assert_eq!(Symbols::from_octo(":const main"), Err("line 1: expected a name and a value".to_string()));
+    assert_eq!(Symbols::from_octo(":const main 5x"), Err("line 1: bad number `5x`".to_string()));
+    assert_eq!(Symbols::from_octo("main 512"), Err("line 1: expected an Octo directive".to_string()));
+
+    let path = std::env::temp_dir().join(format!("chip8-octo-{}.sym", std::process::id()));
+    std::fs::write(&path, text).unwrap();
+    let loaded = Symbols::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, Ok(symbols));
+}
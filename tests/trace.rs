@@ -0,0 +1,65 @@
+#![cfg(feature = "std")]
+
+use afoley_chip8::chip8::Chip8;
+use afoley_chip8::frontend::ManualClock;
+use afoley_chip8::runner::{Runner, RunnerOptions};
+use afoley_chip8::symbols::Symbols;
+use afoley_chip8::trace::Tracer;
+
+// main: CALL tick; JP main
+// tick: ADD V0, 1; RET
+const ROM: [u8; 8] = [0x22, 0x04, 0x12, 0x00, 0x70, 0x01, 0x00, 0xEE];
+
+fn symbols() -> Symbols {
+    let mut symbols = Symbols::default();
+    symbols.add_label(0x200, "main");
+    symbols.add_label(0x204, "tick");
+    symbols
+}
+
+fn trace(symbols: Symbols, cycles: usize) -> String {
+    let mut chip8 = Chip8::from_bytes(&ROM);
+    let mut tracer = Tracer::new(Vec::new(), symbols);
+    for _ in 0..cycles {
+        tracer.record(&chip8).unwrap();
+        chip8.cycle();
+    }
+    String::from_utf8(tracer.finish().unwrap()).unwrap()
+}
+
+#[test]
+fn traces_instructions_with_their_symbols() {
+    assert_eq!(
+        trace(symbols(), 5),
+        "0x200  2204  CALL tick  ; main\n\
+         0x204  7001  ADD V0, 0x01  ; tick\n\
+         0x206  00EE  RET  ; tick+0x2\n\
+         0x202  1200  JP main  ; main+0x2\n\
+         0x200  2204  CALL tick  ; main\n"
+    );
+    assert_eq!(
+        trace(Symbols::default(), 2),
+        "0x200  2204  CALL 0x204\n\
+         0x204  7001  ADD V0, 0x01\n"
+    );
+}
+
+#[test]
+fn runner_traces_every_frame_to_a_file() {
+    let path = std::env::temp_dir().join(format!("chip8-trace-{}.txt", std::process::id()));
+    let options = RunnerOptions { cycles_per_frame: Some(4), symbols: Some(symbols()), ..RunnerOptions::default() };
+    let mut runner = Runner::new(options, (), (), (), ManualClock::default());
+    runner.load_bytes(ROM.to_vec()).unwrap();
+    runner.start_trace(&path).unwrap();
+    runner.step().unwrap();
+    runner.step().unwrap();
+    runner.stop_trace().unwrap();
+
+    let trace = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(trace.lines().count(), 8);
+    assert_eq!(trace.lines().nth(4), Some("0x200  2204  CALL tick  ; main"));
+
+    let missing = std::env::temp_dir().join("no-such-dir").join("trace.txt");
+    assert!(runner.start_trace(&missing).unwrap_err().starts_with(&missing.display().to_string()));
+}
@@ -0,0 +1,61 @@
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use afoley_chip8::wasm::Emulator;
+use wasm_bindgen_test::*;
+
+// A050: I = font sprite "0", D015: draw it at (V0, V1), 1204: loop forever.
+const DRAW_ZERO: [u8; 6] = [0xA0, 0x50, 0xD0, 0x15, 0x12, 0x04];
+
+// 6005: V0 = 5, F018: sound timer = V0, 1204: loop forever.
+const BEEP: [u8; 6] = [0x60, 0x05, 0xF0, 0x18, 0x12, 0x04];
+
+fn pixel(emulator: &Emulator, x: usize, y: usize) -> [u8; 4] {
+    let fb = emulator.framebuffer();
+    let i = (y * Emulator::width() + x) * 4;
+    [fb[i], fb[i + 1], fb[i + 2], fb[i + 3]]
+}
+
+#[wasm_bindgen_test]
+fn framebuffer_is_rgba() {
+    let emulator = Emulator::new();
+    assert_eq!(emulator.framebuffer().len(), 64 * 32 * 4);
+    assert_eq!(pixel(&emulator, 0, 0), [0, 0, 0, 0xFF]);
+}
+
+#[wasm_bindgen_test]
+fn run_frame_draws() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&DRAW_ZERO).unwrap();
+    emulator.run_frame();
+
+    assert_eq!(pixel(&emulator, 0, 0), [0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(pixel(&emulator, 1, 1), [0, 0, 0, 0xFF]);
+}
+
+#[wasm_bindgen_test]
+fn beeps_while_sound_timer_runs() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&BEEP).unwrap();
+    assert!(!emulator.is_beeping());
+
+    emulator.run_frame();
+    assert!(emulator.is_beeping());
+
+    for _ in 0..4 {
+        emulator.run_frame();
+    }
+    assert!(!emulator.is_beeping());
+}
+
+#[wasm_bindgen_test]
+fn oversized_rom_is_rejected() {
+    let mut emulator = Emulator::new();
+    assert!(emulator.load_rom(&[0; 4096]).is_err());
+}
+
+#[wasm_bindgen_test]
+fn out_of_range_keys_are_ignored() {
+    let mut emulator = Emulator::new();
+    emulator.set_key(0xF, true);
+    emulator.set_key(0x10, true);
+}
@@ -0,0 +1,46 @@
+#![cfg(feature = "std")]
+
+use std::fs;
+use std::time::Duration;
+
+use afoley_chip8::frontend::{ManualClock, TimeSource};
+use afoley_chip8::watch::RomWatcher;
+
+const POLL: Duration = Duration::from_millis(250);
+
+#[test]
+fn reloads_once_after_the_file_settles() {
+    let path = std::env::temp_dir().join(format!("chip8-watch-{}.ch8", std::process::id()));
+    fs::write(&path, [0x12, 0x00]).unwrap();
+    let mut clock = ManualClock::default();
+    let mut watcher = RomWatcher::new(&path, clock.now());
+
+    // Nothing changed yet.
+    clock.sleep(POLL);
+    assert!(!watcher.poll(clock.now()));
+
+    // Still being written: each poll sees a different file.
+    fs::write(&path, [0x12, 0x00, 0x00]).unwrap();
+    clock.sleep(POLL);
+    assert!(!watcher.poll(clock.now()));
+    fs::write(&path, [0x12, 0x00, 0x00, 0x00]).unwrap();
+    clock.sleep(POLL);
+    assert!(!watcher.poll(clock.now()));
+
+    // Too soon after the last poll to look again.
+    clock.sleep(POLL / 2);
+    assert!(!watcher.poll(clock.now()));
+
+    // The same for two polls in a row, so it fires, and only the once.
+    clock.sleep(POLL / 2);
+    assert!(watcher.poll(clock.now()));
+    clock.sleep(POLL);
+    assert!(!watcher.poll(clock.now()));
+
+    // A missing file isn't a change to reload.
+    fs::remove_file(&path).unwrap();
+    for _ in 0..3 {
+        clock.sleep(POLL);
+        assert!(!watcher.poll(clock.now()));
+    }
+}